@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// What a viewer may do with a shared terminal.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareAccess {
+    /// Watch only, input from this viewer is dropped by the host
+    #[default]
+    ReadOnly,
+    /// Watch and type
+    Interactive,
+}
+
+impl ShareAccess {
+    pub fn can_type(self) -> bool {
+        self == ShareAccess::Interactive
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ShareAccess::ReadOnly => "View only",
+            ShareAccess::Interactive => "Can type",
+        }
+    }
+}
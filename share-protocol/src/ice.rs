@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+/// Used when no ICE servers are configured at all
+pub const DEFAULT_STUN_SERVER: &str = "stun:stun.l.google.com:19302";
+
+/// A STUN or TURN server, as one entry of `RTCConfiguration.iceServers`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    /// TURN only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+impl IceServer {
+    pub fn stun(url: &str) -> Self {
+        Self {
+            urls: vec![url.to_string()],
+            ..Self::default()
+        }
+    }
+}
+
+/// What a peer connection is set up with.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IceConfig {
+    pub servers: Vec<IceServer>,
+    /// Only connect through TURN relays, e.g. to check that they work
+    #[serde(default)]
+    pub relay_only: bool,
+}
+
+impl Default for IceConfig {
+    fn default() -> Self {
+        Self {
+            servers: vec![IceServer::stun(DEFAULT_STUN_SERVER)],
+            relay_only: false,
+        }
+    }
+}
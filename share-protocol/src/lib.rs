@@ -0,0 +1,31 @@
+//! Signals peers exchange through the backend's `/signal` relay.
+//!
+//! The backend only looks at the `type` of a signal and passes its
+//! `payload` through untouched, so the protocol version travels inside the
+//! payload as `v`.
+//!
+//! Between peers that exchanged [`Signal::PublicKey`]s the payload is sealed
+//! for the recipient, so the relay only ever sees a [`RelayMessage`] it
+//! can neither read nor forge.
+//!
+//! Without a relay, offers and answers can also be passed along by hand as
+//! a [`ManualDescription`] blob.
+//!
+//! Once connected, terminals are shared as [`TerminalFrame`]s over a data
+//! channel.
+
+mod access;
+mod ice;
+mod manual;
+mod quality;
+mod signal;
+mod terminal;
+
+pub use access::ShareAccess;
+pub use ice::{IceConfig, IceServer, DEFAULT_STUN_SERVER};
+pub use manual::ManualDescription;
+pub use quality::{QualityPreset, VideoQuality};
+pub use signal::{Envelope, IceCandidate, RelayMessage, Signal, SignalMessage, PROTOCOL_VERSION};
+pub use terminal::{
+    ChannelId, ControlState, FrameParts, TerminalFrame, MAX_FRAME_LEN, TERMINAL_CHANNEL_LABEL,
+};
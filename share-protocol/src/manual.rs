@@ -0,0 +1,128 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+// Versions the blob format, not the signals
+const BLOB_PREFIX: &str = "ST1:";
+// Far more than any description with all its candidates, keeps a bogus blob from eating memory
+const MAX_INFLATED_LEN: usize = 256 * 1024;
+
+/// An offer or answer the users pass along themselves, as text or QR code,
+/// when there is no relay at all. Carries every ICE candidate in its SDP,
+/// nothing else is exchanged.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "t", rename_all = "snake_case")]
+pub enum ManualDescription {
+    Offer { sdp: String },
+    Answer { sdp: String },
+}
+
+impl ManualDescription {
+    /// Compressed and base64 encoded, safe to paste anywhere.
+    pub fn to_blob(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        let compressed = miniz_oxide::deflate::compress_to_vec(&json, 9);
+        format!("{}{}", BLOB_PREFIX, URL_SAFE_NO_PAD.encode(compressed))
+    }
+
+    /// Reads a blob made by [`to_blob`](Self::to_blob), ignoring whitespace
+    /// picked up on the way, e.g. line breaks from a chat window.
+    pub fn from_blob(blob: &str) -> Result<Self, String> {
+        let blob: String = blob.chars().filter(|c| !c.is_whitespace()).collect();
+        let encoded = blob
+            .strip_prefix(BLOB_PREFIX)
+            .ok_or_else(|| "Not a connection code from this app".to_string())?;
+        let compressed = URL_SAFE_NO_PAD
+            .decode(encoded)
+            .map_err(|e| format!("Connection code is damaged: {}", e))?;
+        let json =
+            miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, MAX_INFLATED_LEN)
+                .map_err(|e| format!("Connection code is damaged: {:?}", e.status))?;
+        serde_json::from_slice(&json).map_err(|e| format!("Connection code is damaged: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob_of(json: &[u8]) -> String {
+        let compressed = miniz_oxide::deflate::compress_to_vec(json, 9);
+        format!("{}{}", BLOB_PREFIX, URL_SAFE_NO_PAD.encode(compressed))
+    }
+
+    #[test]
+    fn round_trips_offers_and_answers() {
+        let sdp = "v=0\r\no=- 0 0 IN IP4 0.0.0.0\r\na=candidate:1 1 udp 1 192.0.2.1 9 typ host\r\n";
+        for description in [
+            ManualDescription::Offer {
+                sdp: sdp.to_string(),
+            },
+            ManualDescription::Answer {
+                sdp: sdp.to_string(),
+            },
+        ] {
+            let blob = description.to_blob();
+            assert!(blob.starts_with(BLOB_PREFIX));
+            assert_eq!(ManualDescription::from_blob(&blob), Ok(description));
+        }
+    }
+
+    #[test]
+    fn ignores_whitespace_picked_up_on_the_way() {
+        let description = ManualDescription::Offer {
+            sdp: "v=0".to_string(),
+        };
+        let blob = description.to_blob();
+        let (head, tail) = blob.split_at(blob.len() / 2);
+        let wrapped = format!("  {}\n{} \r\n", head, tail);
+        assert_eq!(ManualDescription::from_blob(&wrapped), Ok(description));
+    }
+
+    #[test]
+    fn rejects_another_prefix() {
+        let blob = ManualDescription::Offer {
+            sdp: "v=0".to_string(),
+        }
+        .to_blob();
+        for other in [
+            blob.replacen(BLOB_PREFIX, "ST2:", 1),
+            blob[BLOB_PREFIX.len()..].to_string(),
+        ] {
+            assert_eq!(
+                ManualDescription::from_blob(&other),
+                Err("Not a connection code from this app".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_base64() {
+        let error = ManualDescription::from_blob("ST1:not*base64!").unwrap_err();
+        assert!(error.starts_with("Connection code is damaged"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_data_that_does_not_inflate() {
+        let blob = format!("{}{}", BLOB_PREFIX, URL_SAFE_NO_PAD.encode(b"plain bytes"));
+        let error = ManualDescription::from_blob(&blob).unwrap_err();
+        assert!(error.starts_with("Connection code is damaged"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_json_that_is_not_a_description() {
+        let error = ManualDescription::from_blob(&blob_of(br#"{"t":"hangup"}"#)).unwrap_err();
+        assert!(error.starts_with("Connection code is damaged"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_blob_that_inflates_past_the_limit() {
+        let sdp = "a".repeat(MAX_INFLATED_LEN);
+        let json = serde_json::to_vec(&ManualDescription::Offer { sdp }).unwrap();
+        let blob = blob_of(&json);
+        // Deflates down to a tiny code, which is the point of the limit
+        assert!(blob.len() < 4096);
+        let error = ManualDescription::from_blob(&blob).unwrap_err();
+        assert!(error.starts_with("Connection code is damaged"), "{}", error);
+    }
+}
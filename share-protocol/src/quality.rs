@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+/// Ready-made trade-offs a viewer can pick instead of setting every cap.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityPreset {
+    /// Resolution and frame rate follow the host's bandwidth estimate
+    #[default]
+    Auto,
+    /// Full resolution at a low frame rate, so code stays readable
+    TextClarity,
+    /// A smooth frame rate at a lower resolution, for scrolling and video
+    Motion,
+    /// Only the caps set by hand
+    Custom,
+}
+
+impl QualityPreset {
+    pub const ALL: [QualityPreset; 4] = [
+        QualityPreset::Auto,
+        QualityPreset::TextClarity,
+        QualityPreset::Motion,
+        QualityPreset::Custom,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            QualityPreset::Auto => "Auto",
+            QualityPreset::TextClarity => "Text clarity",
+            QualityPreset::Motion => "Motion",
+            QualityPreset::Custom => "Custom",
+        }
+    }
+}
+
+/// What a viewer asks the host's screen video to stay within. Caps set here
+/// tighten the preset's, none means the preset decides.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoQuality {
+    #[serde(default)]
+    pub preset: QualityPreset,
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    #[serde(default)]
+    pub max_frame_rate: Option<u32>,
+    #[serde(default)]
+    pub max_bitrate_kbps: Option<u32>,
+}
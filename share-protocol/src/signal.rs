@@ -0,0 +1,433 @@
+use crate::{ShareAccess, VideoQuality};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Version of the payloads below. Signals carrying any other version are rejected.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const VERSION_FIELD: &str = "v";
+const SESSION_FIELD: &str = "session";
+const ID_FIELD: &str = "id";
+
+/// Everything one peer can tell another through the relay.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum Signal {
+    /// A viewer asks to see the recipient's screen or terminals
+    RequestScreenShare {
+        #[serde(rename = "userName", default)]
+        user_name: String,
+    },
+    /// The host lets the viewer in, with what it may do
+    ScreenShareApproved {
+        access: ShareAccess,
+    },
+    ScreenShareRejected,
+    Offer {
+        sdp: String,
+        /// Made by a new peer connection replacing one that dropped, rather
+        /// than renegotiating the current one
+        #[serde(default)]
+        new_connection: bool,
+    },
+    Answer {
+        sdp: String,
+    },
+    IceCandidate {
+        candidate: IceCandidate,
+    },
+    /// Candidates gathered within a short window, sent as one signal
+    IceCandidates {
+        candidates: Vec<IceCandidate>,
+    },
+    /// A read-only viewer asks to type for a while
+    RequestControl,
+    ControlDenied,
+    /// A viewer asks the host to fit its screen video to these caps
+    VideoQuality {
+        quality: VideoQuality,
+    },
+    /// The sender closed the session, later signals for it are stale
+    Hangup,
+    /// Receipt of the signal with this message id
+    Ack {
+        // The envelope's own message id already takes `id`
+        #[serde(rename = "ack")]
+        id: String,
+    },
+    /// The sender's key for sealing signals, the only signal sent in the clear
+    PublicKey {
+        key: String,
+        /// The recipient should answer with its own key
+        #[serde(default)]
+        reply: bool,
+    },
+}
+
+/// What travels in the payload next to every signal.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Envelope {
+    /// Created by the viewer when it asks for the screen share, carried by
+    /// every signal of that share in both directions
+    pub session_id: String,
+    /// Unique per signal, retries of a signal keep its id
+    pub message_id: String,
+}
+
+/// An ICE candidate as `RTCIceCandidate.toJSON()` writes it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct IceCandidate {
+    pub candidate: String,
+    #[serde(default)]
+    pub sdp_mid: Option<String>,
+    #[serde(rename = "sdpMLineIndex", default)]
+    pub sdp_m_line_index: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username_fragment: Option<String>,
+}
+
+impl Signal {
+    /// The `type` the relay files this signal under.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Signal::RequestScreenShare { .. } => "request_screen_share",
+            Signal::ScreenShareApproved { .. } => "screen_share_approved",
+            Signal::ScreenShareRejected => "screen_share_rejected",
+            Signal::Offer { .. } => "offer",
+            Signal::Answer { .. } => "answer",
+            Signal::IceCandidate { .. } => "ice_candidate",
+            Signal::IceCandidates { .. } => "ice_candidates",
+            Signal::RequestControl => "request_control",
+            Signal::ControlDenied => "control_denied",
+            Signal::VideoQuality { .. } => "video_quality",
+            Signal::Hangup => "hangup",
+            Signal::Ack { .. } => "ack",
+            Signal::PublicKey { .. } => "public_key",
+        }
+    }
+
+    /// Whether the receiver acknowledges this signal and the sender retries
+    /// it until it does. Losing one of these stalls the whole handshake.
+    pub fn needs_ack(&self) -> bool {
+        matches!(
+            self,
+            Signal::Offer { .. }
+                | Signal::Answer { .. }
+                | Signal::ScreenShareApproved { .. }
+                | Signal::Hangup
+        )
+    }
+
+    /// Splits the signal into the relay's `type` and a versioned `payload`
+    /// carrying the envelope.
+    pub fn to_payload(&self, envelope: &Envelope) -> (String, Value) {
+        let mut payload = match serde_json::to_value(self) {
+            Ok(Value::Object(mut tagged)) => match tagged.remove("payload") {
+                Some(Value::Object(fields)) => fields,
+                _ => Map::new(),
+            },
+            _ => Map::new(),
+        };
+        payload.insert(VERSION_FIELD.to_string(), PROTOCOL_VERSION.into());
+        payload.insert(
+            SESSION_FIELD.to_string(),
+            envelope.session_id.as_str().into(),
+        );
+        payload.insert(ID_FIELD.to_string(), envelope.message_id.as_str().into());
+        (self.kind().to_string(), Value::Object(payload))
+    }
+
+    /// Parses a signal received from the relay into its envelope and the
+    /// signal, rejecting unknown types, other protocol versions, signals
+    /// without a session or id and payloads that don't fit the type.
+    pub fn from_payload(signal_type: &str, payload: Value) -> Result<(Envelope, Self), String> {
+        let Value::Object(mut fields) = payload else {
+            return Err(format!(
+                "Rejected {} signal: payload is not an object",
+                signal_type
+            ));
+        };
+        match fields.remove(VERSION_FIELD) {
+            Some(Value::Number(v)) if v.as_f64() == Some(PROTOCOL_VERSION as f64) => {}
+            Some(v) => {
+                return Err(format!(
+                    "Rejected {} signal: protocol version {} is not supported (expected {})",
+                    signal_type, v, PROTOCOL_VERSION
+                ))
+            }
+            None => {
+                return Err(format!(
+                    "Rejected {} signal: no protocol version",
+                    signal_type
+                ))
+            }
+        }
+        let session_id = match fields.remove(SESSION_FIELD) {
+            Some(Value::String(id)) if !id.is_empty() => id,
+            _ => return Err(format!("Rejected {} signal: no session id", signal_type)),
+        };
+        let message_id = match fields.remove(ID_FIELD) {
+            Some(Value::String(id)) if !id.is_empty() => id,
+            _ => return Err(format!("Rejected {} signal: no message id", signal_type)),
+        };
+        let envelope = Envelope {
+            session_id,
+            message_id,
+        };
+
+        let tagged = |payload: Option<Value>| {
+            let mut tagged = Map::new();
+            tagged.insert("type".to_string(), signal_type.into());
+            if let Some(payload) = payload {
+                tagged.insert("payload".to_string(), payload);
+            }
+            serde_json::from_value::<Signal>(Value::Object(tagged))
+        };
+        // Unit variants carry no payload at all, struct variants with only defaults an empty one
+        let result = if fields.is_empty() {
+            tagged(None).or_else(|_| tagged(Some(Value::Object(Map::new()))))
+        } else {
+            tagged(Some(Value::Object(fields)))
+        };
+        result
+            .map(|signal| (envelope, signal))
+            .map_err(|e| format!("Rejected {} signal: {}", signal_type, e))
+    }
+}
+
+/// A signal with its sender, recipient and envelope, as the relay hands it out.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "RelayMessage", into = "RelayMessage")]
+pub struct SignalMessage {
+    pub from_user_id: String,
+    pub to_user_id: String,
+    /// See [`Envelope::session_id`]
+    pub session_id: String,
+    /// See [`Envelope::message_id`]
+    pub message_id: String,
+    pub signal: Signal,
+}
+
+impl SignalMessage {
+    pub fn envelope(&self) -> Envelope {
+        Envelope {
+            session_id: self.session_id.clone(),
+            message_id: self.message_id.clone(),
+        }
+    }
+}
+
+/// A message exactly as it travels through the relay, its payload not
+/// looked into yet. Sealed payloads have to be opened before it can become
+/// a [`SignalMessage`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RelayMessage {
+    #[serde(rename = "fromUserId")]
+    pub from_user_id: String,
+    #[serde(rename = "toUserId")]
+    pub to_user_id: String,
+    #[serde(rename = "type")]
+    pub signal_type: String,
+    #[serde(default)]
+    pub payload: Value,
+}
+
+impl TryFrom<RelayMessage> for SignalMessage {
+    type Error = String;
+
+    fn try_from(raw: RelayMessage) -> Result<Self, Self::Error> {
+        let (envelope, signal) = Signal::from_payload(&raw.signal_type, raw.payload)?;
+        Ok(Self {
+            session_id: envelope.session_id,
+            message_id: envelope.message_id,
+            signal,
+            from_user_id: raw.from_user_id,
+            to_user_id: raw.to_user_id,
+        })
+    }
+}
+
+impl From<SignalMessage> for RelayMessage {
+    fn from(message: SignalMessage) -> Self {
+        let (signal_type, payload) = message.signal.to_payload(&message.envelope());
+        Self {
+            from_user_id: message.from_user_id,
+            to_user_id: message.to_user_id,
+            signal_type,
+            payload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QualityPreset;
+    use serde_json::json;
+
+    fn envelope() -> Envelope {
+        Envelope {
+            session_id: "session-1".to_string(),
+            message_id: "message-1".to_string(),
+        }
+    }
+
+    fn candidate() -> IceCandidate {
+        IceCandidate {
+            candidate: "candidate:1 1 udp 2122260223 192.0.2.1 54400 typ host".to_string(),
+            sdp_mid: Some("0".to_string()),
+            sdp_m_line_index: Some(0),
+            username_fragment: None,
+        }
+    }
+
+    fn every_signal() -> Vec<Signal> {
+        vec![
+            Signal::RequestScreenShare {
+                user_name: "Ada".to_string(),
+            },
+            Signal::ScreenShareApproved {
+                access: ShareAccess::Interactive,
+            },
+            Signal::ScreenShareRejected,
+            Signal::Offer {
+                sdp: "v=0".to_string(),
+                new_connection: true,
+            },
+            Signal::Answer {
+                sdp: "v=0".to_string(),
+            },
+            Signal::IceCandidate {
+                candidate: candidate(),
+            },
+            Signal::IceCandidates {
+                candidates: vec![candidate(), candidate()],
+            },
+            Signal::RequestControl,
+            Signal::ControlDenied,
+            Signal::VideoQuality {
+                quality: VideoQuality {
+                    preset: QualityPreset::Custom,
+                    max_height: Some(720),
+                    max_frame_rate: Some(15),
+                    max_bitrate_kbps: None,
+                },
+            },
+            Signal::Hangup,
+            Signal::Ack {
+                id: "message-0".to_string(),
+            },
+            Signal::PublicKey {
+                key: "a2V5".to_string(),
+                reply: true,
+            },
+        ]
+    }
+
+    #[test]
+    fn every_signal_survives_a_round_trip() {
+        for signal in every_signal() {
+            let (signal_type, payload) = signal.to_payload(&envelope());
+            assert_eq!(signal_type, signal.kind());
+            let parsed = Signal::from_payload(&signal_type, payload);
+            assert_eq!(parsed, Ok((envelope(), signal)));
+        }
+    }
+
+    #[test]
+    fn round_trips_through_the_relay_message() {
+        let message = SignalMessage {
+            from_user_id: "alice".to_string(),
+            to_user_id: "bob".to_string(),
+            session_id: "session-1".to_string(),
+            message_id: "message-1".to_string(),
+            signal: Signal::Hangup,
+        };
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["fromUserId"], "alice");
+        assert_eq!(json["type"], "hangup");
+        assert_eq!(serde_json::from_value::<SignalMessage>(json).unwrap(), message);
+    }
+
+    #[test]
+    fn rejects_a_payload_that_is_not_an_object() {
+        let e = Signal::from_payload("hangup", json!("hello")).unwrap_err();
+        assert!(e.contains("payload is not an object"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_a_missing_version() {
+        let payload = json!({ "session": "s", "id": "m" });
+        let e = Signal::from_payload("hangup", payload).unwrap_err();
+        assert!(e.contains("no protocol version"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_another_version() {
+        let payload = json!({ "v": PROTOCOL_VERSION + 1, "session": "s", "id": "m" });
+        let e = Signal::from_payload("hangup", payload).unwrap_err();
+        assert!(e.contains("is not supported"), "{}", e);
+
+        let payload = json!({ "v": "1", "session": "s", "id": "m" });
+        assert!(Signal::from_payload("hangup", payload).is_err());
+    }
+
+    #[test]
+    fn rejects_a_missing_or_empty_session() {
+        let payload = json!({ "v": PROTOCOL_VERSION, "id": "m" });
+        let e = Signal::from_payload("hangup", payload).unwrap_err();
+        assert!(e.contains("no session id"), "{}", e);
+
+        let payload = json!({ "v": PROTOCOL_VERSION, "session": "", "id": "m" });
+        let e = Signal::from_payload("hangup", payload).unwrap_err();
+        assert!(e.contains("no session id"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_a_missing_or_empty_message_id() {
+        let payload = json!({ "v": PROTOCOL_VERSION, "session": "s" });
+        let e = Signal::from_payload("hangup", payload).unwrap_err();
+        assert!(e.contains("no message id"), "{}", e);
+
+        let payload = json!({ "v": PROTOCOL_VERSION, "session": "s", "id": "" });
+        let e = Signal::from_payload("hangup", payload).unwrap_err();
+        assert!(e.contains("no message id"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_an_unknown_type() {
+        let payload = json!({ "v": PROTOCOL_VERSION, "session": "s", "id": "m" });
+        let e = Signal::from_payload("teleport", payload).unwrap_err();
+        assert!(e.starts_with("Rejected teleport signal"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_a_payload_that_does_not_fit_the_type() {
+        let payload = json!({ "v": PROTOCOL_VERSION, "session": "s", "id": "m", "sdp": 5 });
+        let e = Signal::from_payload("offer", payload).unwrap_err();
+        assert!(e.starts_with("Rejected offer signal"), "{}", e);
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let payload = json!({ "v": PROTOCOL_VERSION, "session": "s", "id": "m", "sdp": "v=0" });
+        let (_, signal) = Signal::from_payload("offer", payload).unwrap();
+        assert_eq!(
+            signal,
+            Signal::Offer {
+                sdp: "v=0".to_string(),
+                new_connection: false,
+            }
+        );
+
+        let payload = json!({ "v": PROTOCOL_VERSION, "session": "s", "id": "m" });
+        let (_, signal) = Signal::from_payload("request_screen_share", payload).unwrap();
+        assert_eq!(
+            signal,
+            Signal::RequestScreenShare {
+                user_name: String::new(),
+            }
+        );
+    }
+}
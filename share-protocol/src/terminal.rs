@@ -0,0 +1,286 @@
+use crate::ShareAccess;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Label of the data channel that carries terminal traffic
+pub const TERMINAL_CHANNEL_LABEL: &str = "terminal";
+
+/// Identifies one PTY among those multiplexed over the terminal data channel
+pub type ChannelId = u32;
+
+/// Frames longer than this go in parts of this many bytes. Browsers only promise
+/// 64 KiB per data channel message, which a part stays under even escaped again.
+pub const MAX_FRAME_LEN: usize = 16 * 1024;
+
+static NEXT_PART_ID: AtomicU32 = AtomicU32::new(0);
+
+/// A message exchanged over the terminal data channel. Snapshots are `S`,
+/// whatever the app's terminal emulator saves its state as; the agent never
+/// sends them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TerminalFrame<S> {
+    /// A new PTY was opened, sent by the host
+    Open { channel: ChannelId },
+    /// A PTY was closed or its shell exited, sent by the host
+    Close { channel: ChannelId },
+    /// Screen and scrollback of a PTY as it is right now, sent by the host
+    /// when the viewer connects, before any live output for that channel
+    Snapshot { channel: ChannelId, snapshot: S },
+    /// PTY output, sent by the host
+    Output { channel: ChannelId, data: String },
+    /// Keystrokes, sent by the viewer
+    Input { channel: ChannelId, data: String },
+    /// Access level the host granted this viewer, sent by the host on open
+    Access { access: ShareAccess },
+    /// Cols/rows the viewer has room for, sent by the viewer whenever it changes
+    Resize { cols: u16, rows: u16 },
+    /// Size every PTY is actually running at, sent by the host
+    Size { cols: u16, rows: u16 },
+    /// Who is driving the terminals, sent by the host on open and whenever it changes
+    Control { state: ControlState },
+    /// One piece of the JSON of a frame too long for a single message, sent
+    /// in order by either side and put back together by the receiver
+    Part {
+        id: u32,
+        index: u32,
+        count: u32,
+        data: String,
+    },
+}
+
+/// Control over the shared terminals as sent over the wire, to every viewer
+/// with whether it's the one holding it. The time left is relative so the two
+/// clocks don't have to agree.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "holder", rename_all = "snake_case")]
+pub enum ControlState {
+    Host,
+    Viewer {
+        seconds_left: Option<u32>,
+        yours: bool,
+    },
+}
+
+impl<S: Serialize> TerminalFrame<S> {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// The messages the frame goes out as: its JSON, or parts of it if too long.
+    pub fn to_messages(&self) -> Vec<String> {
+        let text = self.to_json();
+        if text.len() <= MAX_FRAME_LEN {
+            return vec![text];
+        }
+        let id = NEXT_PART_ID.fetch_add(1, Ordering::Relaxed);
+        let pieces = split(&text, MAX_FRAME_LEN);
+        let count = pieces.len() as u32;
+        pieces
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| {
+                TerminalFrame::<S>::Part {
+                    id,
+                    index: index as u32,
+                    count,
+                    data: data.to_string(),
+                }
+                .to_json()
+            })
+            .collect()
+    }
+}
+
+impl<S: DeserializeOwned> TerminalFrame<S> {
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|e| format!("Invalid terminal frame: {}", e))
+    }
+}
+
+/// Cuts `text` into pieces of at most `max_len` bytes, between characters.
+fn split(text: &str, max_len: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(max_len);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (piece, tail) = rest.split_at(end);
+        pieces.push(piece);
+        rest = tail;
+    }
+    pieces
+}
+
+/// Puts frames sent in parts back together, one per data channel. The
+/// channel is ordered and parts of one frame are sent back to back, so they
+/// arrive one after the other.
+#[derive(Default)]
+pub struct FrameParts {
+    // Id, index of the part expected next, and the JSON so far
+    pending: Option<(u32, u32, String)>,
+}
+
+impl FrameParts {
+    /// The frame complete with the message `text`: the frame in it unless
+    /// it's a part, None while parts are missing.
+    pub fn receive<S: DeserializeOwned>(
+        &mut self,
+        text: &str,
+    ) -> Result<Option<TerminalFrame<S>>, String> {
+        let frame = TerminalFrame::from_json(text)?;
+        let TerminalFrame::Part {
+            id,
+            index,
+            count,
+            data,
+        } = frame
+        else {
+            return Ok(Some(frame));
+        };
+        if index == 0 {
+            self.pending = Some((id, 0, String::new()));
+        }
+        match self.pending.as_mut() {
+            Some((pending_id, next, text)) if *pending_id == id && *next == index => {
+                text.push_str(&data);
+                *next += 1;
+            }
+            _ => {
+                self.pending = None;
+                return Err(format!(
+                    "Dropping terminal frame {}, part {} of {} came out of order",
+                    id, index, count
+                ));
+            }
+        }
+        if index + 1 < count {
+            return Ok(None);
+        }
+        let (_, _, text) = self.pending.take().unwrap_or_default();
+        TerminalFrame::from_json(&text).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Frame = TerminalFrame<()>;
+
+    #[test]
+    fn round_trips_frames() {
+        let frames = [
+            Frame::Input {
+                channel: 1,
+                data: "ls\r".to_string(),
+            },
+            Frame::Control {
+                state: ControlState::Viewer {
+                    seconds_left: Some(300),
+                    yours: true,
+                },
+            },
+            Frame::Access {
+                access: ShareAccess::Interactive,
+            },
+        ];
+        for frame in frames {
+            assert_eq!(Frame::from_json(&frame.to_json()), Ok(frame));
+        }
+    }
+
+    // What a data channel message is sure to fit in
+    const SCTP_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+    fn receive_all(parts: &mut FrameParts, messages: &[String]) -> Vec<Frame> {
+        messages
+            .iter()
+            .filter_map(|message| parts.receive(message).unwrap())
+            .collect()
+    }
+
+    fn long_output() -> Frame {
+        // Quotes and backslashes are escaped twice once in a part, the worst case
+        let data = "\"\\\x1b[1m✓ ünïcødé 🦀\r\n".repeat(10_000);
+        Frame::Output { channel: 1, data }
+    }
+
+    #[test]
+    fn sends_short_frames_whole() {
+        let frame = Frame::Output {
+            channel: 1,
+            data: "ls\r\n".to_string(),
+        };
+        let messages = frame.to_messages();
+        assert_eq!(messages, [frame.to_json()]);
+        assert_eq!(receive_all(&mut FrameParts::default(), &messages), [frame]);
+    }
+
+    #[test]
+    fn splits_long_frames_into_parts_that_fit_a_message() {
+        let frame = long_output();
+        let messages = frame.to_messages();
+        assert!(messages.len() > 1);
+        for message in &messages {
+            assert!(message.len() <= SCTP_MAX_MESSAGE_SIZE, "{}", message.len());
+        }
+        assert_eq!(receive_all(&mut FrameParts::default(), &messages), [frame]);
+    }
+
+    #[test]
+    fn splits_between_characters() {
+        let text = "é".repeat(10);
+        let pieces = split(&text, 3);
+        assert!(pieces.iter().all(|piece| piece.len() == 2));
+        assert_eq!(pieces.concat(), text);
+        assert!(split("", 3).is_empty());
+    }
+
+    #[test]
+    fn passes_other_frames_between_frames_in_parts() {
+        let mut parts = FrameParts::default();
+        let size = Frame::Size { cols: 80, rows: 24 };
+        let mut messages = long_output().to_messages();
+        messages.push(size.to_json());
+        messages.extend(long_output().to_messages());
+        assert_eq!(
+            receive_all(&mut parts, &messages),
+            [long_output(), size, long_output()]
+        );
+    }
+
+    #[test]
+    fn drops_a_frame_with_a_part_missing() {
+        let mut parts = FrameParts::default();
+        let mut messages = long_output().to_messages();
+        messages.remove(1);
+        let results: Vec<Result<Option<Frame>, String>> = messages
+            .iter()
+            .map(|message| parts.receive(message))
+            .collect();
+        assert_eq!(results[0], Ok(None));
+        assert!(results[1].is_err());
+        // The rest of it can't complete anything either
+        assert!(results[2..].iter().all(|result| result.is_err()));
+
+        // The next frame still comes through
+        let next = long_output().to_messages();
+        assert_eq!(receive_all(&mut parts, &next), [long_output()]);
+    }
+
+    #[test]
+    fn tags_frames_and_control_holders() {
+        let frame = Frame::Control {
+            state: ControlState::Host,
+        };
+        assert_eq!(
+            frame.to_json(),
+            r#"{"type":"control","state":{"holder":"host"}}"#
+        );
+        assert!(Frame::from_json(r#"{"type":"nope"}"#).is_err());
+    }
+}
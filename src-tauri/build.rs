@@ -1,3 +1,5 @@
 fn main() {
+    // The headless agent is built without Tauri, see the `agent` feature
+    #[cfg(feature = "gui")]
     tauri_build::build()
 }
@@ -0,0 +1,181 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use crate::api::ice::{IceServer, IceSettings, TurnRest};
+use share_protocol::{ShareAccess, DEFAULT_STUN_SERVER};
+
+/// Environment variable the password is read from when the config has none
+pub const PASSWORD_ENV: &str = "SHARE_AGENT_PASSWORD";
+
+const DEFAULT_KEYS_FILE: &str = "share-agent-keys.json";
+
+/// Command line of the `share-agent` binary. Flags override the config file.
+#[derive(Parser, Debug)]
+#[command(name = "share-agent", about = "Share a terminal without a window")]
+pub struct Cli {
+    /// TOML config file
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Account the agent logs in as
+    #[arg(long)]
+    pub email: Option<String>,
+    /// User id allowed to view the terminal, can be repeated
+    #[arg(long = "allow", value_name = "USER_ID")]
+    pub allowed_users: Vec<String>,
+    /// Access granted to viewers: read_only or interactive
+    #[arg(long, value_parser = parse_access)]
+    pub access: Option<ShareAccess>,
+    /// Shell to run instead of the user's default shell
+    #[arg(long)]
+    pub shell: Option<String>,
+    /// How often the signal inbox is polled
+    #[arg(long)]
+    pub poll_interval_ms: Option<u64>,
+    /// Record every session to an asciicast file in this directory
+    #[arg(long)]
+    pub record_dir: Option<PathBuf>,
+    /// File with the agent's signal key and the keys of its viewers
+    #[arg(long)]
+    pub keys_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub email: String,
+    pub password: String,
+    pub allowed_users: Vec<String>,
+    pub access: ShareAccess,
+    pub shell: Option<String>,
+    pub poll_interval_ms: u64,
+    pub cols: u16,
+    pub rows: u16,
+    pub stun_servers: Vec<String>,
+    /// TURN servers with their credentials, tried along with the STUN servers
+    pub turn_servers: Vec<IceServer>,
+    pub turn_rest: Option<TurnRest>,
+    pub relay_only: bool,
+    pub record_dir: Option<PathBuf>,
+    pub keys_file: PathBuf,
+    // Peers on the local network are only identified by the address they advertise
+    pub lan_signaling: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            email: String::new(),
+            password: String::new(),
+            allowed_users: Vec::new(),
+            access: ShareAccess::ReadOnly,
+            shell: None,
+            poll_interval_ms: 2000,
+            cols: 80,
+            rows: 24,
+            stun_servers: vec![DEFAULT_STUN_SERVER.to_string()],
+            turn_servers: Vec::new(),
+            turn_rest: None,
+            relay_only: false,
+            record_dir: None,
+            keys_file: PathBuf::from(DEFAULT_KEYS_FILE),
+            lan_signaling: false,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file named on the command line, if any, and applies
+    /// the remaining flags on top of it.
+    pub fn load(cli: Cli) -> Result<Self, String> {
+        let mut config = match &cli.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                toml::from_str(&text)
+                    .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?
+            }
+            None => Config::default(),
+        };
+
+        if let Some(email) = cli.email {
+            config.email = email;
+        }
+        if !cli.allowed_users.is_empty() {
+            config.allowed_users = cli.allowed_users;
+        }
+        if let Some(access) = cli.access {
+            config.access = access;
+        }
+        if cli.shell.is_some() {
+            config.shell = cli.shell;
+        }
+        if let Some(poll_interval_ms) = cli.poll_interval_ms {
+            config.poll_interval_ms = poll_interval_ms;
+        }
+        if cli.record_dir.is_some() {
+            config.record_dir = cli.record_dir;
+        }
+        if let Some(keys_file) = cli.keys_file {
+            config.keys_file = keys_file;
+        }
+        // Keep the password out of the process list and, if preferred, out of the file
+        if config.password.is_empty() {
+            config.password = std::env::var(PASSWORD_ENV).unwrap_or_default();
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn ice_settings(&self) -> IceSettings {
+        let mut servers = Vec::new();
+        if !self.stun_servers.is_empty() {
+            servers.push(IceServer {
+                urls: self.stun_servers.clone(),
+                ..IceServer::default()
+            });
+        }
+        servers.extend(self.turn_servers.iter().cloned());
+        IceSettings {
+            servers: Some(servers),
+            turn_rest: self.turn_rest.clone(),
+            relay_only: self.relay_only,
+        }
+    }
+
+    pub fn is_allowed(&self, user_id: &str) -> bool {
+        self.allowed_users.iter().any(|allowed| allowed == user_id)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.email.is_empty() {
+            return Err("No email configured".to_string());
+        }
+        if self.password.is_empty() {
+            return Err(format!(
+                "No password configured, set it in the config file or {}",
+                PASSWORD_ENV
+            ));
+        }
+        // An agent that accepts anyone would hand out a shell to every connection
+        if self.allowed_users.is_empty() {
+            return Err("No allowed users configured".to_string());
+        }
+        if self.poll_interval_ms == 0 {
+            return Err("poll_interval_ms must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn parse_access(value: &str) -> Result<ShareAccess, String> {
+    match value {
+        "read_only" => Ok(ShareAccess::ReadOnly),
+        "interactive" => Ok(ShareAccess::Interactive),
+        other => Err(format!(
+            "Unknown access '{}', expected read_only or interactive",
+            other
+        )),
+    }
+}
@@ -0,0 +1,39 @@
+pub use share_protocol::{ChannelId, ControlState, TERMINAL_CHANNEL_LABEL};
+
+/// Channel id of the agent's single PTY
+pub const AGENT_CHANNEL: ChannelId = 1;
+
+/// The app's terminal protocol. The agent never sends snapshots, every
+/// viewer gets a fresh shell, so any it receives stay raw JSON.
+pub type TerminalFrame = share_protocol::TerminalFrame<serde_json::Value>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use share_protocol::{FrameParts, MAX_FRAME_LEN};
+
+    #[test]
+    fn reassembles_a_long_paste_sent_in_parts() {
+        // As the app sends it, with its own snapshot type
+        let data = "echo \"pasted\"\n".repeat(MAX_FRAME_LEN / 4);
+        let sent = share_protocol::TerminalFrame::<()>::Input {
+            channel: AGENT_CHANNEL,
+            data: data.clone(),
+        };
+        let messages = sent.to_messages();
+        assert!(messages.len() > 1);
+
+        let mut parts = FrameParts::default();
+        let received: Vec<TerminalFrame> = messages
+            .iter()
+            .filter_map(|message| parts.receive(message).unwrap())
+            .collect();
+        assert_eq!(
+            received,
+            [TerminalFrame::Input {
+                channel: AGENT_CHANNEL,
+                data
+            }]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Headless host: shares a terminal with allowed users without a window.
+
+pub mod config;
+pub mod frame;
+mod session;
+
+pub use config::{Cli, Config};
+
+use crate::api::auth;
+use crate::api::ice::IceServers;
+use crate::api::keys::Keyring;
+use crate::api::signaling::{Signal, SignalMessage, Signaling};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Logs in, then answers screen share requests until Ctrl+C.
+pub async fn run(config: Config) -> Result<(), String> {
+    let config = Arc::new(config);
+    let mut token = login(&config).await?;
+    let keys = Keyring::load(config.keys_file.clone())?;
+    let signaling = Arc::new(if config.lan_signaling {
+        Signaling::with_lan(keys)
+    } else {
+        Signaling::new(keys)
+    });
+    let ice = Arc::new(IceServers::new(config.ice_settings()));
+    println!("Logged in as {}, waiting for viewers", config.email);
+
+    // Viewer and signals of each running session, keyed by session id
+    let mut sessions: HashMap<String, (String, mpsc::UnboundedSender<SignalMessage>)> =
+        HashMap::new();
+    let mut interval = tokio::time::interval(Duration::from_millis(config.poll_interval_ms));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            // Pushed signals are handled right away, the interval polls and retries
+            _ = signaling.arrived() => {}
+            _ = tokio::signal::ctrl_c() => {
+                println!("Shutting down");
+                return Ok(());
+            }
+        }
+
+        // A session's receiver goes away when it ends
+        sessions.retain(|_, (_, signals)| !signals.is_closed());
+
+        let inbox = match signaling.receive(&token).await {
+            Ok(inbox) => inbox,
+            Err(e) => {
+                eprintln!("Failed to fetch inbox: {}", e);
+                // Most likely the token expired, log in again before the next poll
+                match login(&config).await {
+                    Ok(new_token) => token = new_token,
+                    Err(e) => eprintln!("{}", e),
+                }
+                continue;
+            }
+        };
+
+        for e in &inbox.malformed {
+            eprintln!("{}", e);
+        }
+
+        for user_id in &inbox.trusted {
+            println!("Trusting signal key of {}", user_id);
+        }
+
+        for failure in inbox.failures {
+            eprintln!(
+                "{} to {} was not delivered: {}",
+                failure.signal_type, failure.to_user_id, failure.reason
+            );
+            // The handshake can't go on without it
+            if sessions.remove(&failure.session_id).is_some() {
+                println!("Ending session {}", failure.session_id);
+            }
+        }
+
+        for message in inbox.messages {
+            let viewer = message.from_user_id.clone();
+            let session_id = message.session_id.clone();
+            let running = sessions
+                .get(&session_id)
+                .filter(|(session_viewer, _)| *session_viewer == viewer);
+            match (&message.signal, running) {
+                (Signal::RequestScreenShare { .. }, None) => {
+                    if !config.is_allowed(&viewer) {
+                        println!("Rejecting screen share request from {}", viewer);
+                        reject(&signaling, &token, &viewer, &session_id).await;
+                        continue;
+                    }
+                    // Every request is its own session, a reloaded viewer's old one
+                    // ends with its hangup or connection
+                    let (signals, receiver) = mpsc::unbounded_channel();
+                    sessions.insert(session_id.clone(), (viewer.clone(), signals));
+                    println!("Sharing terminal with {} (session {})", viewer, session_id);
+                    tokio::spawn(session::serve(
+                        config.clone(),
+                        signaling.clone(),
+                        ice.clone(),
+                        viewer,
+                        session_id,
+                        receiver,
+                    ));
+                }
+                (Signal::Hangup, Some(_)) => {
+                    println!("{} hung up session {}", viewer, session_id);
+                    // Dropping the sender ends the session
+                    sessions.remove(&session_id);
+                }
+                (_, Some((_, signals))) => {
+                    let _ = signals.send(message);
+                }
+                (signal, None) => println!(
+                    "Dropping {} from {}, unknown or closed session {}",
+                    signal.kind(),
+                    viewer,
+                    session_id
+                ),
+            }
+        }
+    }
+}
+
+async fn login(config: &Config) -> Result<String, String> {
+    auth::login(config.email.clone(), config.password.clone())
+        .await
+        .map(|response| response.token)
+}
+
+async fn reject(signaling: &Signaling, token: &str, viewer: &str, session_id: &str) {
+    if let Err(e) = signaling
+        .send(
+            token,
+            viewer.to_string(),
+            session_id.to_string(),
+            Signal::ScreenShareRejected,
+        )
+        .await
+    {
+        eprintln!("Failed to send rejection: {}", e);
+    }
+}
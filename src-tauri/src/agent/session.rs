@@ -0,0 +1,395 @@
+use super::config::Config;
+use super::frame::{ControlState, TerminalFrame, AGENT_CHANNEL, TERMINAL_CHANNEL_LABEL};
+use crate::api::ice::{IceConfig, IceServers};
+use crate::api::signaling::{Signal, SignalMessage, Signaling};
+use crate::terminal::session::TerminalSession;
+use share_protocol::{FrameParts, IceCandidate};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use webrtc::api::APIBuilder;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice_transport::ice_candidate::{RTCIceCandidate, RTCIceCandidateInit};
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::policy::ice_transport_policy::RTCIceTransportPolicy;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+
+/// How long the viewer has to answer the offer
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(60);
+/// Local candidates gathered within this window go out as one signal
+const CANDIDATE_BATCH_WINDOW: Duration = Duration::from_millis(200);
+
+/// Everything the session loop reacts to besides signals.
+enum Event {
+    ChannelOpen,
+    Message(String),
+    LocalCandidate(RTCIceCandidateInit),
+    Output(String),
+    ShellExited(Option<u32>),
+    ConnectionClosed,
+}
+
+/// Shares a fresh shell with `viewer` until either side hangs up or goes away.
+pub async fn serve(
+    config: Arc<Config>,
+    signaling: Arc<Signaling>,
+    ice: Arc<IceServers>,
+    viewer: String,
+    session_id: String,
+    signals: mpsc::UnboundedReceiver<SignalMessage>,
+) {
+    let peer = Peer {
+        signaling: &signaling,
+        viewer: &viewer,
+        session_id: &session_id,
+    };
+    let ice = ice.config(&config.email).await;
+    match run(&config, &ice, &peer, signals).await {
+        Ok(()) => println!("Session with {} ended", viewer),
+        Err(e) => eprintln!("Session with {} failed: {}", viewer, e),
+    }
+    // Whatever the viewer still sends for this session is stale now
+    if let Err(e) = peer.send(Signal::Hangup).await {
+        eprintln!("{}", e);
+    }
+}
+
+/// Where a session's signals go.
+struct Peer<'a> {
+    signaling: &'a Signaling,
+    viewer: &'a str,
+    session_id: &'a str,
+}
+
+impl Peer<'_> {
+    /// Sends through whatever transport the agent's inbox loop last set up.
+    async fn send(&self, signal: Signal) -> Result<(), String> {
+        self.signaling
+            .send_latest(self.viewer.to_string(), self.session_id.to_string(), signal)
+            .await
+    }
+}
+
+async fn run(
+    config: &Config,
+    ice: &IceConfig,
+    peer: &Peer<'_>,
+    mut signals: mpsc::UnboundedReceiver<SignalMessage>,
+) -> Result<(), String> {
+    let viewer = peer.viewer;
+    peer.send(Signal::ScreenShareApproved {
+        access: config.access,
+    })
+    .await?;
+
+    let (events, mut event_rx) = mpsc::unbounded_channel();
+    let pc = create_peer_connection(ice).await?;
+    let data_channel = pc
+        .create_data_channel(TERMINAL_CHANNEL_LABEL, None)
+        .await
+        .map_err(|e| format!("Failed to create data channel: {}", e))?;
+    attach_handlers(&pc, &data_channel, &events);
+
+    let offer = pc
+        .create_offer(None)
+        .await
+        .map_err(|e| format!("Failed to create offer: {}", e))?;
+    pc.set_local_description(offer.clone())
+        .await
+        .map_err(|e| format!("Failed to set local description: {}", e))?;
+    peer.send(Signal::Offer {
+        sdp: offer.sdp,
+        new_connection: false,
+    })
+    .await?;
+
+    let mut terminal: Option<TerminalSession> = None;
+    // Frames the viewer sent in parts, until the last one is in
+    let mut parts = FrameParts::default();
+    let (mut cols, mut rows) = (config.cols, config.rows);
+    let mut answered = false;
+    // Candidates can't be added before the answer is set
+    let mut early_candidates = Vec::new();
+    let answer_timeout = tokio::time::sleep(ANSWER_TIMEOUT);
+    tokio::pin!(answer_timeout);
+    // Local candidates waiting for the batch to go out, and when it does
+    let mut outgoing_candidates = Vec::new();
+    let mut flush_candidates_at: Option<Instant> = None;
+
+    let result = loop {
+        tokio::select! {
+            _ = &mut answer_timeout, if !answered => {
+                break Err("Viewer did not answer in time".to_string());
+            }
+            _ = tokio::time::sleep_until(flush_candidates_at.unwrap_or_else(Instant::now)),
+                if flush_candidates_at.is_some() =>
+            {
+                flush_candidates_at = None;
+                let candidates = std::mem::take(&mut outgoing_candidates);
+                if let Err(e) = peer.send(Signal::IceCandidates { candidates }).await {
+                    eprintln!("{}", e);
+                }
+            }
+            signal = signals.recv() => {
+                // The viewer hung up or the agent is shutting down
+                let Some(signal) = signal else {
+                    break Ok(());
+                };
+                match signal.signal {
+                    Signal::Answer { sdp } => {
+                        let answer = match RTCSessionDescription::answer(sdp) {
+                            Ok(answer) => answer,
+                            Err(e) => break Err(format!("Invalid answer: {}", e)),
+                        };
+                        if let Err(e) = pc.set_remote_description(answer).await {
+                            break Err(format!("Failed to set remote description: {}", e));
+                        }
+                        answered = true;
+                        for candidate in early_candidates.drain(..) {
+                            add_candidate(&pc, candidate).await;
+                        }
+                    }
+                    Signal::IceCandidate { candidate } => {
+                        receive_candidates(&pc, answered, &mut early_candidates, vec![candidate]).await;
+                    }
+                    Signal::IceCandidates { candidates } => {
+                        receive_candidates(&pc, answered, &mut early_candidates, candidates).await;
+                    }
+                    Signal::RequestControl => {
+                        if let Err(e) = peer.send(Signal::ControlDenied).await {
+                            eprintln!("{}", e);
+                        }
+                    }
+                    other => println!("Ignoring {} from {}", other.kind(), viewer),
+                }
+            }
+            Some(event) = event_rx.recv() => match event {
+                Event::ChannelOpen => {
+                    send_frame(&data_channel, TerminalFrame::Access { access: config.access }).await;
+                    send_frame(&data_channel, TerminalFrame::Control { state: ControlState::Host }).await;
+                    send_frame(&data_channel, TerminalFrame::Size { cols, rows }).await;
+                    send_frame(&data_channel, TerminalFrame::Open { channel: AGENT_CHANNEL }).await;
+                    match start_terminal(config, viewer, &events) {
+                        Ok(session) => terminal = Some(session),
+                        Err(e) => break Err(e),
+                    }
+                }
+                Event::Message(text) => {
+                    let frame: TerminalFrame = match parts.receive(&text) {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            continue;
+                        }
+                    };
+                    let Some(session) = terminal.as_mut() else {
+                        continue;
+                    };
+                    match frame {
+                        TerminalFrame::Input { channel, data } if channel == AGENT_CHANNEL => {
+                            // Read-only viewers don't get to type
+                            if !config.access.can_type() {
+                                continue;
+                            }
+                            if let Err(e) = session.write(data.as_bytes()) {
+                                eprintln!("{}", e);
+                            }
+                        }
+                        TerminalFrame::Resize { cols: new_cols, rows: new_rows } => {
+                            // Only someone who can type decides the size, as in the app
+                            if config.access.can_type() && (new_cols, new_rows) != (cols, rows) {
+                                match session.resize(new_cols.max(1), new_rows.max(1)) {
+                                    Ok(()) => (cols, rows) = (new_cols.max(1), new_rows.max(1)),
+                                    Err(e) => eprintln!("{}", e),
+                                }
+                            }
+                            send_frame(&data_channel, TerminalFrame::Size { cols, rows }).await;
+                        }
+                        other => println!("Unexpected terminal frame from {}: {:?}", viewer, other),
+                    }
+                }
+                Event::LocalCandidate(candidate) => {
+                    let candidate = IceCandidate {
+                        candidate: candidate.candidate,
+                        sdp_mid: candidate.sdp_mid,
+                        sdp_m_line_index: candidate.sdp_mline_index,
+                        username_fragment: candidate.username_fragment,
+                    };
+                    outgoing_candidates.push(candidate);
+                    flush_candidates_at
+                        .get_or_insert_with(|| Instant::now() + CANDIDATE_BATCH_WINDOW);
+                }
+                Event::Output(data) => {
+                    send_frame(&data_channel, TerminalFrame::Output { channel: AGENT_CHANNEL, data }).await;
+                }
+                Event::ShellExited(code) => {
+                    println!("Shell for {} exited with {:?}", viewer, code);
+                    terminal = None;
+                    send_frame(&data_channel, TerminalFrame::Close { channel: AGENT_CHANNEL }).await;
+                    break Ok(());
+                }
+                Event::ConnectionClosed => break Ok(()),
+            }
+        }
+    };
+
+    if let Some(mut session) = terminal {
+        let _ = session.kill();
+    }
+    let _ = pc.close().await;
+    result
+}
+
+async fn create_peer_connection(ice: &IceConfig) -> Result<Arc<RTCPeerConnection>, String> {
+    let rtc_config = RTCConfiguration {
+        ice_servers: ice
+            .servers
+            .iter()
+            .map(|server| RTCIceServer {
+                urls: server.urls.clone(),
+                username: server.username.clone().unwrap_or_default(),
+                credential: server.credential.clone().unwrap_or_default(),
+            })
+            .collect(),
+        ice_transport_policy: if ice.relay_only {
+            RTCIceTransportPolicy::Relay
+        } else {
+            RTCIceTransportPolicy::All
+        },
+        ..Default::default()
+    };
+
+    // Data channels only, no media engine needed
+    let api = APIBuilder::new().build();
+    api.new_peer_connection(rtc_config)
+        .await
+        .map(Arc::new)
+        .map_err(|e| format!("Failed to create peer connection: {}", e))
+}
+
+/// Forwards the callbacks of the peer connection and data channel into the
+/// session loop.
+fn attach_handlers(
+    pc: &RTCPeerConnection,
+    data_channel: &Arc<RTCDataChannel>,
+    events: &mpsc::UnboundedSender<Event>,
+) {
+    let on_open = events.clone();
+    data_channel.on_open(Box::new(move || {
+        let _ = on_open.send(Event::ChannelOpen);
+        Box::pin(async {})
+    }));
+
+    let on_message = events.clone();
+    data_channel.on_message(Box::new(move |message: DataChannelMessage| {
+        if message.is_string {
+            if let Ok(text) = String::from_utf8(message.data.to_vec()) {
+                let _ = on_message.send(Event::Message(text));
+            }
+        }
+        Box::pin(async {})
+    }));
+
+    let on_candidate = events.clone();
+    pc.on_ice_candidate(Box::new(move |candidate: Option<RTCIceCandidate>| {
+        if let Some(candidate) = candidate.and_then(|candidate| candidate.to_json().ok()) {
+            let _ = on_candidate.send(Event::LocalCandidate(candidate));
+        }
+        Box::pin(async {})
+    }));
+
+    let on_state = events.clone();
+    pc.on_peer_connection_state_change(Box::new(move |state: RTCPeerConnectionState| {
+        println!("Connection state: {}", state);
+        if matches!(
+            state,
+            RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed
+        ) {
+            let _ = on_state.send(Event::ConnectionClosed);
+        }
+        Box::pin(async {})
+    }));
+}
+
+fn start_terminal(
+    config: &Config,
+    viewer: &str,
+    events: &mpsc::UnboundedSender<Event>,
+) -> Result<TerminalSession, String> {
+    let output = events.clone();
+    let exit = events.clone();
+    let session = TerminalSession::spawn_with(
+        config.cols,
+        config.rows,
+        config.shell.clone(),
+        move |data| {
+            let _ = output.send(Event::Output(data));
+        },
+        |path, e| eprintln!("Stopped recording to {}: {}", path.display(), e),
+        move |code| {
+            let _ = exit.send(Event::ShellExited(code));
+        },
+    )?;
+
+    if let Some(dir) = &config.record_dir {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create recordings directory: {}", e))?;
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        // The viewer id comes from the server, keep it from escaping the directory
+        let viewer: String = viewer
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect();
+        let path = dir.join(format!("{}-{}.cast", started, viewer));
+        session.start_recording(&path)?;
+        println!("Recording session to {}", path.display());
+    }
+    Ok(session)
+}
+
+/// Adds the viewer's candidates, or keeps them until the answer is set.
+async fn receive_candidates(
+    pc: &RTCPeerConnection,
+    answered: bool,
+    early_candidates: &mut Vec<RTCIceCandidateInit>,
+    candidates: Vec<IceCandidate>,
+) {
+    for candidate in candidates {
+        let candidate = RTCIceCandidateInit {
+            candidate: candidate.candidate,
+            sdp_mid: candidate.sdp_mid,
+            sdp_mline_index: candidate.sdp_m_line_index,
+            username_fragment: candidate.username_fragment,
+        };
+        if answered {
+            add_candidate(pc, candidate).await;
+        } else {
+            early_candidates.push(candidate);
+        }
+    }
+}
+
+async fn add_candidate(pc: &RTCPeerConnection, candidate: RTCIceCandidateInit) {
+    if let Err(e) = pc.add_ice_candidate(candidate).await {
+        eprintln!("Failed to add ICE candidate: {}", e);
+    }
+}
+
+/// Sends a frame, in parts if it's too long for one message.
+async fn send_frame(data_channel: &RTCDataChannel, frame: TerminalFrame) {
+    for message in frame.to_messages() {
+        if let Err(e) = data_channel.send_text(message).await {
+            eprintln!("Failed to send terminal frame: {}", e);
+            return;
+        }
+    }
+}
@@ -12,16 +12,19 @@ pub struct LoginResponse {
     pub token: String,
 }
 
+#[cfg(feature = "gui")]
 #[derive(Serialize, Deserialize)]
 pub struct SignupRequest {
     pub email: String,
 }
 
+#[cfg(feature = "gui")]
 #[derive(Serialize, Deserialize)]
 pub struct SignupResponse {
     pub message: String,
 }
 
+#[cfg(feature = "gui")]
 #[derive(Serialize, Deserialize)]
 pub struct LogoutResponse {
     pub message: String,
@@ -50,6 +53,7 @@ pub async fn login(email: String, password: String) -> Result<LoginResponse, Str
     Ok(login_response)
 }
 
+#[cfg(feature = "gui")]
 pub async fn signup(email: String) -> Result<SignupResponse, String> {
     let client = reqwest::Client::new();
     let signup_data = SignupRequest { email };
@@ -73,9 +77,45 @@ pub async fn signup(email: String) -> Result<SignupResponse, String> {
     Ok(signup_response)
 }
 
+#[cfg(feature = "gui")]
 pub async fn logout() -> Result<LogoutResponse, String> {
     // Simulate a successful logout
     Ok(LogoutResponse {
         message: "Logged out successfully!".to_string(),
     })
 }
+
+/// Who a token was issued to, read from its JWT claims.
+pub struct TokenClaims {
+    pub user_id: String,
+    pub email: String,
+}
+
+/// Reads the claims of `token` without verifying it, the backend does that.
+/// Only for telling peers who we are where the backend can't be asked.
+pub fn token_claims(token: &str) -> Result<TokenClaims, String> {
+    use base64::Engine;
+
+    let claims = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| "Token is not a JWT".to_string())?;
+    let claims = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(claims.trim_end_matches('='))
+        .map_err(|e| format!("Failed to decode token claims: {}", e))?;
+    let claims: serde_json::Value = serde_json::from_slice(&claims)
+        .map_err(|e| format!("Failed to parse token claims: {}", e))?;
+
+    let claim = |names: &[&str]| {
+        names.iter().find_map(|name| match claims.get(*name) {
+            Some(serde_json::Value::String(value)) => Some(value.clone()),
+            Some(serde_json::Value::Number(value)) => Some(value.to_string()),
+            _ => None,
+        })
+    };
+    Ok(TokenClaims {
+        user_id: claim(&["userId", "user_id", "id", "sub"])
+            .ok_or_else(|| "Token has no user id".to_string())?,
+        email: claim(&["email"]).unwrap_or_default(),
+    })
+}
@@ -0,0 +1,448 @@
+use super::signaling::{OutgoingSignal, SignalMessage};
+use serde::Serialize;
+use share_protocol::{Envelope, Signal};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// First retry of an unacknowledged signal, the wait doubles after every attempt
+const RETRY_INITIAL: Duration = Duration::from_secs(1);
+const RETRY_MAX: Duration = Duration::from_secs(8);
+// Give up on a signal that wasn't acknowledged within this
+const ACK_DEADLINE: Duration = Duration::from_secs(30);
+// How many received message ids are remembered to spot repeats
+const SEEN_CAPACITY: usize = 1000;
+
+static MESSAGE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A unique id for an outgoing signal.
+pub fn new_message_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    let count = MESSAGE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}-{:x}-{:x}", nanos, std::process::id(), count)
+}
+
+/// A signal the recipient never acknowledged.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryFailure {
+    pub to_user_id: String,
+    pub session_id: String,
+    pub signal_type: String,
+    pub reason: String,
+}
+
+/// What one look at the inbox turned up.
+#[derive(Serialize, Default, Debug)]
+pub struct Inbox {
+    pub messages: Vec<SignalMessage>,
+    pub failures: Vec<DeliveryFailure>,
+    /// Why messages that couldn't be read were dropped
+    pub malformed: Vec<String>,
+    /// Peers whose signal key is trusted from now on
+    pub trusted: Vec<String>,
+}
+
+struct Pending {
+    signal: OutgoingSignal,
+    attempts: u32,
+    next_retry: Instant,
+    expires: Instant,
+}
+
+struct Held {
+    signal: OutgoingSignal,
+    expires: Instant,
+}
+
+/// Signals waiting for their ack, those waiting for their recipient's key
+/// before they can be sealed, and the ids of those already received.
+#[derive(Default)]
+pub struct Delivery {
+    outbox: Mutex<HashMap<String, Pending>>,
+    // By recipient, in the order they were sent
+    held: Mutex<HashMap<String, Vec<Held>>>,
+    seen: Mutex<Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl Seen {
+    /// Remembers `id`, false if it was already known.
+    fn insert(&mut self, id: &str) -> bool {
+        if !self.ids.insert(id.to_string()) {
+            return false;
+        }
+        self.order.push_back(id.to_string());
+        if self.order.len() > SEEN_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+impl Delivery {
+    /// Keeps `signal` around for retries if it needs an ack.
+    pub fn track(&self, signal: OutgoingSignal) {
+        if !signal.signal.needs_ack() {
+            return;
+        }
+        let now = Instant::now();
+        self.outbox.lock().unwrap().insert(
+            signal.envelope.message_id.clone(),
+            Pending {
+                signal,
+                attempts: 1,
+                next_retry: now + RETRY_INITIAL,
+                expires: now + ACK_DEADLINE,
+            },
+        );
+    }
+
+    /// Keeps `signal` until [`release`](Self::release) is called for its
+    /// recipient, true if it's the first one waiting for them.
+    pub fn hold(&self, signal: OutgoingSignal) -> bool {
+        let mut held = self.held.lock().unwrap();
+        let waiting = held.entry(signal.to_user_id.clone()).or_default();
+        waiting.push(Held {
+            signal,
+            expires: Instant::now() + ACK_DEADLINE,
+        });
+        waiting.len() == 1
+    }
+
+    /// The signals held for `user_id`, now that they can be sent.
+    pub fn release(&self, user_id: &str) -> Vec<OutgoingSignal> {
+        self.held
+            .lock()
+            .unwrap()
+            .remove(user_id)
+            .map(|waiting| waiting.into_iter().map(|held| held.signal).collect())
+            .unwrap_or_default()
+    }
+
+    /// Settles the outbox with the acks among `messages` and drops repeats.
+    /// Returns the messages for the app and the acks to send back.
+    pub fn receive(
+        &self,
+        messages: Vec<SignalMessage>,
+    ) -> (Vec<SignalMessage>, Vec<OutgoingSignal>) {
+        let mut outbox = self.outbox.lock().unwrap();
+        let mut seen = self.seen.lock().unwrap();
+        let mut fresh = Vec::new();
+        let mut acks = Vec::new();
+
+        for message in messages {
+            if let Signal::Ack { id } = &message.signal {
+                // Only the recipient of a signal can settle it
+                if outbox
+                    .get(id)
+                    .is_some_and(|pending| pending.signal.to_user_id == message.from_user_id)
+                {
+                    outbox.remove(id);
+                }
+                continue;
+            }
+            // Acked again even when repeated, the first ack may be what got lost
+            if message.signal.needs_ack() {
+                acks.push(OutgoingSignal {
+                    to_user_id: message.from_user_id.clone(),
+                    envelope: Envelope {
+                        session_id: message.session_id.clone(),
+                        message_id: new_message_id(),
+                    },
+                    signal: Signal::Ack {
+                        id: message.message_id.clone(),
+                    },
+                });
+            }
+            if seen.insert(&message.message_id) {
+                // Nobody is left in the session to acknowledge what we still retry
+                if message.signal == Signal::Hangup {
+                    outbox.retain(|_, pending| {
+                        pending.signal.envelope.session_id != message.session_id
+                            || pending.signal.signal == Signal::Hangup
+                    });
+                    if let Some(waiting) = self.held.lock().unwrap().get_mut(&message.from_user_id)
+                    {
+                        waiting.retain(|held| {
+                            held.signal.envelope.session_id != message.session_id
+                                || held.signal.signal == Signal::Hangup
+                        });
+                    }
+                }
+                fresh.push(message);
+            } else {
+                println!(
+                    "Dropping repeated {} from {}",
+                    message.signal.kind(),
+                    message.from_user_id
+                );
+            }
+        }
+        (fresh, acks)
+    }
+
+    /// Signals due for another attempt, and those that ran out of time,
+    /// acknowledged or not, or still held.
+    pub fn due(&self) -> (Vec<OutgoingSignal>, Vec<DeliveryFailure>) {
+        self.due_at(Instant::now())
+    }
+
+    fn due_at(&self, now: Instant) -> (Vec<OutgoingSignal>, Vec<DeliveryFailure>) {
+        let mut outbox = self.outbox.lock().unwrap();
+        let mut retries = Vec::new();
+        let mut failures = Vec::new();
+
+        outbox.retain(|_, pending| {
+            if now >= pending.expires {
+                failures.push(DeliveryFailure {
+                    to_user_id: pending.signal.to_user_id.clone(),
+                    session_id: pending.signal.envelope.session_id.clone(),
+                    signal_type: pending.signal.signal.kind().to_string(),
+                    reason: format!("not acknowledged after {} attempts", pending.attempts),
+                });
+                return false;
+            }
+            if now >= pending.next_retry {
+                let wait = RETRY_INITIAL
+                    .saturating_mul(2u32.saturating_pow(pending.attempts))
+                    .min(RETRY_MAX);
+                pending.attempts += 1;
+                pending.next_retry = now + wait;
+                retries.push(pending.signal.clone());
+            }
+            true
+        });
+
+        let mut held = self.held.lock().unwrap();
+        held.retain(|_, waiting| {
+            waiting.retain(|held| {
+                if now < held.expires {
+                    return true;
+                }
+                failures.push(DeliveryFailure {
+                    to_user_id: held.signal.to_user_id.clone(),
+                    session_id: held.signal.envelope.session_id.clone(),
+                    signal_type: held.signal.signal.kind().to_string(),
+                    reason: "never got their encryption key".to_string(),
+                });
+                false
+            });
+            !waiting.is_empty()
+        });
+        (retries, failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outgoing(to_user_id: &str, message_id: &str, signal: Signal) -> OutgoingSignal {
+        OutgoingSignal {
+            to_user_id: to_user_id.to_string(),
+            envelope: Envelope {
+                session_id: "session-1".to_string(),
+                message_id: message_id.to_string(),
+            },
+            signal,
+        }
+    }
+
+    fn offer() -> Signal {
+        Signal::Offer {
+            sdp: "v=0".to_string(),
+            new_connection: false,
+        }
+    }
+
+    fn incoming(from_user_id: &str, message_id: &str, signal: Signal) -> SignalMessage {
+        SignalMessage {
+            from_user_id: from_user_id.to_string(),
+            to_user_id: "me".to_string(),
+            session_id: "session-1".to_string(),
+            message_id: message_id.to_string(),
+            signal,
+        }
+    }
+
+    fn ack(from_user_id: &str, id: &str) -> SignalMessage {
+        incoming(
+            from_user_id,
+            &new_message_id(),
+            Signal::Ack { id: id.to_string() },
+        )
+    }
+
+    #[test]
+    fn message_ids_are_unique() {
+        let ids: HashSet<String> = (0..100).map(|_| new_message_id()).collect();
+        assert_eq!(ids.len(), 100);
+    }
+
+    #[test]
+    fn only_tracks_signals_that_need_an_ack() {
+        let delivery = Delivery::default();
+        delivery.track(outgoing("peer", "m1", Signal::RequestControl));
+        delivery.track(outgoing("peer", "m2", offer()));
+        assert_eq!(delivery.outbox.lock().unwrap().len(), 1);
+        assert!(delivery.outbox.lock().unwrap().contains_key("m2"));
+    }
+
+    #[test]
+    fn drops_repeated_messages_but_acks_them_again() {
+        let delivery = Delivery::default();
+        let (fresh, acks) = delivery.receive(vec![
+            incoming("peer", "m1", offer()),
+            incoming("peer", "m1", offer()),
+            incoming("peer", "m2", Signal::RequestControl),
+        ]);
+        let ids: Vec<_> = fresh.iter().map(|m| m.message_id.as_str()).collect();
+        assert_eq!(ids, ["m1", "m2"]);
+        assert_eq!(acks.len(), 2);
+        for ack in &acks {
+            assert_eq!(ack.to_user_id, "peer");
+            assert_eq!(ack.envelope.session_id, "session-1");
+            assert_eq!(
+                ack.signal,
+                Signal::Ack {
+                    id: "m1".to_string()
+                }
+            );
+        }
+
+        let (fresh, _) = delivery.receive(vec![incoming("peer", "m1", offer())]);
+        assert!(fresh.is_empty());
+    }
+
+    #[test]
+    fn forgets_the_oldest_ids_past_capacity() {
+        let mut seen = Seen::default();
+        for i in 0..=SEEN_CAPACITY {
+            assert!(seen.insert(&i.to_string()));
+        }
+        assert_eq!(seen.ids.len(), SEEN_CAPACITY);
+        assert!(!seen.insert(&SEEN_CAPACITY.to_string()));
+        // The first id fell out and counts as new again
+        assert!(seen.insert("0"));
+    }
+
+    #[test]
+    fn an_ack_settles_the_signal() {
+        let delivery = Delivery::default();
+        delivery.track(outgoing("peer", "m1", offer()));
+        let (fresh, acks) = delivery.receive(vec![ack("peer", "m1")]);
+        assert!(fresh.is_empty());
+        assert!(acks.is_empty());
+        assert!(delivery.outbox.lock().unwrap().is_empty());
+
+        let (retries, failures) = delivery.due_at(Instant::now() + ACK_DEADLINE);
+        assert!(retries.is_empty());
+        assert!(failures.is_empty());
+    }
+
+    #[test]
+    fn ignores_an_ack_from_someone_else() {
+        let delivery = Delivery::default();
+        delivery.track(outgoing("peer", "m1", offer()));
+        delivery.receive(vec![ack("intruder", "m1"), ack("peer", "unknown")]);
+        assert!(delivery.outbox.lock().unwrap().contains_key("m1"));
+    }
+
+    #[test]
+    fn retries_with_a_doubling_wait_up_to_the_cap() {
+        let delivery = Delivery::default();
+        let start = Instant::now();
+        delivery.track(outgoing("peer", "m1", offer()));
+
+        let (retries, _) = delivery.due_at(start);
+        assert!(retries.is_empty());
+
+        // Each step lands just past the previous wait: 1s, 2s, 4s, 8s, then 8s again
+        let mut at = start;
+        for wait in [1, 2, 4, 8, 8] {
+            at += Duration::from_secs(wait) + Duration::from_millis(10);
+            let (retries, failures) = delivery.due_at(at);
+            assert_eq!(retries.len(), 1, "no retry {wait}s after the last");
+            assert_eq!(retries[0].envelope.message_id, "m1");
+            assert!(failures.is_empty());
+
+            let (retries, _) = delivery.due_at(at + Duration::from_millis(500));
+            assert!(retries.is_empty(), "retried early after {wait}s");
+        }
+        let wait = delivery.outbox.lock().unwrap()["m1"].next_retry - at;
+        assert_eq!(wait, RETRY_MAX);
+    }
+
+    #[test]
+    fn gives_up_after_the_deadline() {
+        let delivery = Delivery::default();
+        delivery.track(outgoing("peer", "m1", offer()));
+        delivery.due_at(Instant::now() + RETRY_INITIAL + Duration::from_millis(10));
+
+        let (retries, failures) = delivery.due_at(Instant::now() + ACK_DEADLINE);
+        assert!(retries.is_empty());
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].to_user_id, "peer");
+        assert_eq!(failures[0].session_id, "session-1");
+        assert_eq!(failures[0].signal_type, "offer");
+        assert_eq!(failures[0].reason, "not acknowledged after 2 attempts");
+        assert!(delivery.outbox.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn holds_signals_until_released() {
+        let delivery = Delivery::default();
+        assert!(delivery.hold(outgoing("peer", "m1", offer())));
+        assert!(!delivery.hold(outgoing("peer", "m2", Signal::RequestControl)));
+        assert!(delivery.hold(outgoing("other", "m3", offer())));
+
+        let released: Vec<_> = delivery
+            .release("peer")
+            .into_iter()
+            .map(|signal| signal.envelope.message_id)
+            .collect();
+        assert_eq!(released, ["m1", "m2"]);
+        assert!(delivery.release("peer").is_empty());
+        // Released signals are tracked once they are actually sent
+        assert!(delivery.outbox.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn gives_up_on_held_signals_after_the_deadline() {
+        let delivery = Delivery::default();
+        delivery.hold(outgoing("peer", "m1", Signal::RequestControl));
+
+        let (_, failures) = delivery.due_at(Instant::now());
+        assert!(failures.is_empty());
+
+        let (retries, failures) = delivery.due_at(Instant::now() + ACK_DEADLINE);
+        assert!(retries.is_empty());
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].signal_type, "request_control");
+        assert_eq!(failures[0].reason, "never got their encryption key");
+        assert!(delivery.release("peer").is_empty());
+    }
+
+    #[test]
+    fn a_hangup_stops_retries_for_its_session() {
+        let delivery = Delivery::default();
+        delivery.track(outgoing("peer", "m1", offer()));
+        delivery.track(outgoing("peer", "m2", Signal::Hangup));
+        delivery.receive(vec![incoming("peer", "m3", Signal::Hangup)]);
+        let outbox = delivery.outbox.lock().unwrap();
+        assert!(!outbox.contains_key("m1"));
+        assert!(outbox.contains_key("m2"));
+    }
+}
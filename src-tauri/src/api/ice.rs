@@ -0,0 +1,308 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub use share_protocol::{IceConfig, IceServer};
+
+// Give up on the TURN REST API and go without its relay after this
+const TURN_REST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// ICE servers to use, read from a settings file:
+///
+/// ```json
+/// {
+///   "servers": [
+///     { "urls": ["stun:stun.example.com:3478"] },
+///     { "urls": ["turn:turn.example.com:3478"], "username": "alice", "credential": "secret" }
+///   ],
+///   "turn_rest": { "url": "https://example.com/turn-credentials", "api_key": "..." },
+///   "relay_only": false
+/// }
+/// ```
+///
+/// A local coturn started with `turnserver -n --lt-cred-mech --user alice:secret
+/// --realm local` and `relay_only` set is enough to check TURN works.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct IceSettings {
+    /// Falls back to a public STUN server when not given
+    pub servers: Option<Vec<IceServer>>,
+    pub turn_rest: Option<TurnRest>,
+    pub relay_only: bool,
+}
+
+/// A TURN REST API handing out short-lived TURN credentials, as in
+/// draft-uberti-behave-turn-rest.
+#[derive(Deserialize, Clone, Debug)]
+pub struct TurnRest {
+    pub url: String,
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TurnCredentials {
+    username: String,
+    password: String,
+    ttl: u64,
+    uris: Vec<String>,
+}
+
+impl IceSettings {
+    /// Reads the settings at `path`, the defaults if there is no such file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+}
+
+/// The configured ICE servers plus TURN credentials from the REST API for
+/// each user, fetched again once half their lifetime is up.
+pub struct IceServers {
+    settings: IceSettings,
+    // By user, with when to fetch them again
+    turn: tokio::sync::Mutex<HashMap<String, (Instant, IceServer)>>,
+}
+
+impl IceServers {
+    pub fn new(settings: IceSettings) -> Self {
+        Self {
+            settings,
+            turn: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// What to set up a peer connection for `user` with. A TURN REST API that
+    /// can't be reached only costs its relay.
+    pub async fn config(&self, user: &str) -> IceConfig {
+        let mut config = IceConfig {
+            relay_only: self.settings.relay_only,
+            ..IceConfig::default()
+        };
+        if let Some(servers) = &self.settings.servers {
+            config.servers = servers.clone();
+        }
+
+        if let Some(rest) = &self.settings.turn_rest {
+            let mut turn = self.turn.lock().await;
+            let now = Instant::now();
+            turn.retain(|_, (refresh_at, _)| now < *refresh_at);
+            if !turn.contains_key(user) {
+                match fetch_turn_credentials(rest, user).await {
+                    Ok((ttl, server)) => {
+                        turn.insert(user.to_string(), (Instant::now() + ttl / 2, server));
+                    }
+                    Err(e) => eprintln!("{}, going without its TURN server", e),
+                }
+            }
+            if let Some((_, server)) = turn.get(user) {
+                config.servers.push(server.clone());
+            }
+        }
+        config
+    }
+}
+
+async fn fetch_turn_credentials(
+    rest: &TurnRest,
+    user: &str,
+) -> Result<(Duration, IceServer), String> {
+    let client = reqwest::Client::builder()
+        .timeout(TURN_REST_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let mut query = vec![("service", "turn"), ("username", user)];
+    if let Some(key) = &rest.api_key {
+        query.push(("key", key));
+    }
+
+    let response = client
+        .get(&rest.url)
+        .query(&query)
+        .send()
+        .await
+        .map_err(|e| format!("TURN credentials request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "Failed to get TURN credentials: {}",
+            response.status()
+        ));
+    }
+
+    let credentials: TurnCredentials = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse TURN credentials: {}", e))?;
+    Ok((
+        Duration::from_secs(credentials.ttl),
+        IceServer {
+            urls: credentials.uris,
+            username: Some(credentials.username),
+            credential: Some(credentials.password),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A TURN REST API answering every request with `status` and `body`,
+    /// and the request lines it got so far.
+    async fn turn_rest(
+        status: &'static str,
+        body: &'static str,
+    ) -> (TurnRest, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/turn", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        {
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => break,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let line = request.lines().next().unwrap_or_default().to_string();
+                    requests.lock().unwrap().push(line);
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            });
+        }
+        let rest = TurnRest {
+            url,
+            api_key: Some("secret-key".to_string()),
+        };
+        (rest, requests)
+    }
+
+    const CREDENTIALS: &str = r#"{
+        "username": "1700000000:alice",
+        "password": "c2VjcmV0",
+        "ttl": 600,
+        "uris": ["turn:turn.example.com:3478?transport=udp", "turns:turn.example.com:5349"]
+    }"#;
+
+    fn settings(rest: TurnRest) -> IceSettings {
+        IceSettings {
+            servers: Some(vec![IceServer::stun("stun:stun.example.com:3478")]),
+            turn_rest: Some(rest),
+            relay_only: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn adds_the_turn_server_from_the_rest_api() {
+        let (rest, requests) = turn_rest("200 OK", CREDENTIALS).await;
+        let config = IceServers::new(settings(rest)).config("alice").await;
+
+        assert!(config.relay_only);
+        assert_eq!(
+            config.servers,
+            [
+                IceServer::stun("stun:stun.example.com:3478"),
+                IceServer {
+                    urls: vec![
+                        "turn:turn.example.com:3478?transport=udp".to_string(),
+                        "turns:turn.example.com:5349".to_string()
+                    ],
+                    username: Some("1700000000:alice".to_string()),
+                    credential: Some("c2VjcmV0".to_string()),
+                }
+            ]
+        );
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let line = &requests[0];
+        assert!(line.starts_with("GET /turn?"), "{}", line);
+        for param in ["service=turn", "username=alice", "key=secret-key"] {
+            assert!(line.contains(param), "{} lacks {}", line, param);
+        }
+    }
+
+    #[tokio::test]
+    async fn fetches_credentials_again_after_half_their_lifetime() {
+        let (rest, requests) = turn_rest("200 OK", CREDENTIALS).await;
+        let ice = IceServers::new(settings(rest));
+        let before = Instant::now();
+        ice.config("alice").await;
+        ice.config("alice").await;
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        // Refreshed at half of the 600s ttl
+        let refresh_at = ice.turn.lock().await["alice"].0;
+        let half = Duration::from_secs(300);
+        assert!(refresh_at >= before + half);
+        assert!(refresh_at <= Instant::now() + half);
+
+        ice.turn.lock().await.get_mut("alice").unwrap().0 = Instant::now();
+        let config = ice.config("alice").await;
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        assert_eq!(config.servers.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fetches_credentials_for_each_user() {
+        let (rest, requests) = turn_rest("200 OK", CREDENTIALS).await;
+        let ice = IceServers::new(settings(rest));
+        ice.config("alice").await;
+        ice.config("bob").await;
+        ice.config("alice").await;
+
+        // Bob doesn't get the credentials fetched for Alice
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains("username=alice"), "{}", requests[0]);
+        assert!(requests[1].contains("username=bob"), "{}", requests[1]);
+    }
+
+    #[tokio::test]
+    async fn goes_without_turn_when_the_rest_api_fails() {
+        for (status, body) in [
+            ("500 Internal Server Error", "{}"),
+            ("200 OK", r#"{"username": "alice"}"#),
+        ] {
+            let (rest, requests) = turn_rest(status, body).await;
+            let ice = IceServers::new(settings(rest));
+            let config = ice.config("alice").await;
+            assert_eq!(
+                config.servers,
+                [IceServer::stun("stun:stun.example.com:3478")]
+            );
+            // Nothing is cached, the next connection tries again
+            ice.config("alice").await;
+            assert_eq!(requests.lock().unwrap().len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_public_stun_server() {
+        let config = IceServers::new(IceSettings::default())
+            .config("alice")
+            .await;
+        assert_eq!(config, IceConfig::default());
+    }
+}
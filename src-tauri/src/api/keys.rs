@@ -0,0 +1,405 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use crypto_box::aead::{Aead, AeadCore, OsRng};
+use crypto_box::{PublicKey, SalsaBox, SecretKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use share_protocol::{RelayMessage, Signal, SignalMessage, PROTOCOL_VERSION};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const VERSION_FIELD: &str = "v";
+const SEALED_FIELD: &str = "sealed";
+const NONCE_LEN: usize = 24;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyFile {
+    secret_key: String,
+    // Public keys of peers by user id, pinned the first time they are seen
+    #[serde(default)]
+    peers: HashMap<String, String>,
+}
+
+/// Our key pair and the public keys of the peers we exchanged keys with,
+/// kept in a file so peers can keep trusting us across restarts.
+///
+/// Payloads are sealed with NaCl's crypto_box: only the recipient can open
+/// them, and opening proves they came from the holder of the sender's key.
+/// The first key the relay delivers from a peer is trusted and a different
+/// one later is refused, so only the relay, which vouches for every
+/// sender, could swap keys, and only before the first exchange. Keys that
+/// come over the local network are never trusted on first sight.
+pub struct Keyring {
+    path: PathBuf,
+    secret: SecretKey,
+    peers: Mutex<HashMap<String, PublicKey>>,
+}
+
+impl Keyring {
+    /// Reads the keyring at `path`, creating a new key pair there if there is none.
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        if !path.exists() {
+            let keyring = Self {
+                path,
+                secret: SecretKey::generate(&mut OsRng),
+                peers: Mutex::new(HashMap::new()),
+            };
+            keyring.save()?;
+            println!("Created signal keys at {}", keyring.path.display());
+            return Ok(keyring);
+        }
+
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let file: KeyFile = serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        let secret = SecretKey::from(
+            decode_key(&file.secret_key)
+                .map_err(|e| format!("Invalid secret key in {}: {}", path.display(), e))?,
+        );
+        let mut peers = HashMap::new();
+        for (user_id, key) in file.peers {
+            match decode_key(&key) {
+                Ok(key) => {
+                    peers.insert(user_id, PublicKey::from(key));
+                }
+                Err(e) => eprintln!("Ignoring key of {} in {}: {}", user_id, path.display(), e),
+            }
+        }
+        Ok(Self {
+            path,
+            secret,
+            peers: Mutex::new(peers),
+        })
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let file = KeyFile {
+            secret_key: STANDARD.encode(self.secret.to_bytes()),
+            peers: self
+                .peers
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(user_id, key)| (user_id.clone(), STANDARD.encode(key.as_bytes())))
+                .collect(),
+        };
+        let text = serde_json::to_string_pretty(&file)
+            .map_err(|e| format!("Failed to serialize keys: {}", e))?;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        write_private(&self.path, &text)
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    /// Our public key, as it goes into a [`Signal::PublicKey`].
+    pub fn public_key(&self) -> String {
+        STANDARD.encode(self.secret.public_key().as_bytes())
+    }
+
+    pub fn has_key(&self, user_id: &str) -> bool {
+        self.peers.lock().unwrap().contains_key(user_id)
+    }
+
+    /// Trusts `key` for `user_id` unless another key is already trusted for them.
+    /// Returns whether `key` wasn't trusted before.
+    pub fn pin(&self, user_id: &str, key: &str) -> Result<bool, String> {
+        let key = PublicKey::from(decode_key(key)?);
+        {
+            let mut peers = self.peers.lock().unwrap();
+            match peers.get(user_id) {
+                Some(pinned) if *pinned == key => return Ok(false),
+                Some(_) => {
+                    return Err(format!(
+                        "{} sent a different key than the one trusted for them, remove them from {} to accept it",
+                        user_id,
+                        self.path.display()
+                    ))
+                }
+                None => {
+                    peers.insert(user_id.to_string(), key);
+                }
+            }
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Seals `signal_type` and `payload` for `to_user_id` into a payload
+    /// that only says which protocol version is inside.
+    pub fn seal(
+        &self,
+        to_user_id: &str,
+        signal_type: &str,
+        payload: Value,
+    ) -> Result<Value, String> {
+        let peer = self
+            .peers
+            .lock()
+            .unwrap()
+            .get(to_user_id)
+            .cloned()
+            .ok_or_else(|| format!("No signal key for {}", to_user_id))?;
+        // The type goes inside too, or the relay could pass one signal off as another
+        let plaintext = serde_json::json!({ "type": signal_type, "payload": payload }).to_string();
+        let nonce = SalsaBox::generate_nonce(&mut OsRng);
+        let ciphertext = SalsaBox::new(&peer, &self.secret)
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| format!("Failed to seal {} for {}", signal_type, to_user_id))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        let mut fields = Map::new();
+        fields.insert(VERSION_FIELD.to_string(), PROTOCOL_VERSION.into());
+        fields.insert(SEALED_FIELD.to_string(), STANDARD.encode(sealed).into());
+        Ok(Value::Object(fields))
+    }
+
+    /// Opens a message from the relay. Only a public key may come unsealed,
+    /// anything else has to open with the key trusted for its sender.
+    pub fn open(&self, message: RelayMessage) -> Result<SignalMessage, String> {
+        let sealed = match message.payload.get(SEALED_FIELD) {
+            Some(Value::String(sealed)) => sealed.clone(),
+            Some(_) => {
+                return Err(format!(
+                    "Rejected {} from {}: sealed payload is not a string",
+                    message.signal_type, message.from_user_id
+                ))
+            }
+            None => {
+                let message = SignalMessage::try_from(message)?;
+                if !matches!(message.signal, Signal::PublicKey { .. }) {
+                    return Err(format!(
+                        "Rejected {} from {}: not sealed",
+                        message.signal.kind(),
+                        message.from_user_id
+                    ));
+                }
+                return Ok(message);
+            }
+        };
+
+        let reject = |reason: &str| {
+            format!(
+                "Rejected {} from {}: {}",
+                message.signal_type, message.from_user_id, reason
+            )
+        };
+        let peer = self
+            .peers
+            .lock()
+            .unwrap()
+            .get(&message.from_user_id)
+            .cloned()
+            .ok_or_else(|| reject("no signal key for the sender"))?;
+        let sealed = STANDARD
+            .decode(sealed)
+            .map_err(|_| reject("sealed payload is not base64"))?;
+        if sealed.len() < NONCE_LEN {
+            return Err(reject("sealed payload is too short"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = SalsaBox::new(&peer, &self.secret)
+            .decrypt(nonce.into(), ciphertext)
+            .map_err(|_| reject("failed authentication"))?;
+
+        #[derive(Deserialize)]
+        struct Sealed {
+            #[serde(rename = "type")]
+            signal_type: String,
+            payload: Value,
+        }
+        let inner: Sealed = serde_json::from_slice(&plaintext)
+            .map_err(|e| reject(&format!("sealed payload is malformed: {}", e)))?;
+        if inner.signal_type != message.signal_type {
+            return Err(reject(&format!("sealed as {}", inner.signal_type)));
+        }
+        SignalMessage::try_from(RelayMessage {
+            payload: inner.payload,
+            ..message
+        })
+    }
+}
+
+fn decode_key(key: &str) -> Result<[u8; 32], String> {
+    STANDARD
+        .decode(key)
+        .map_err(|e| format!("Key is not base64: {}", e))?
+        .try_into()
+        .map_err(|_| "Key is not 32 bytes long".to_string())
+}
+
+/// Writes `text` to `path`, readable by the current user only where that's possible.
+fn write_private(path: &Path, text: &str) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(text.as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        std::fs::write(path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use share_protocol::Envelope;
+
+    fn keyring(name: &str) -> Keyring {
+        let path = std::env::temp_dir()
+            .join(format!("share-terminal-keys-{}", std::process::id()))
+            .join(format!("{}.json", name));
+        let _ = std::fs::remove_file(&path);
+        Keyring::load(path).unwrap()
+    }
+
+    /// Alice and Bob, each trusting the other's key.
+    fn pair(test: &str) -> (Keyring, Keyring) {
+        let alice = keyring(&format!("{}-alice", test));
+        let bob = keyring(&format!("{}-bob", test));
+        alice.pin("bob", &bob.public_key()).unwrap();
+        bob.pin("alice", &alice.public_key()).unwrap();
+        (alice, bob)
+    }
+
+    fn sealed(from: &Keyring, signal: &Signal) -> RelayMessage {
+        let envelope = Envelope {
+            session_id: "session-1".to_string(),
+            message_id: "message-1".to_string(),
+        };
+        let (signal_type, payload) = signal.to_payload(&envelope);
+        RelayMessage {
+            from_user_id: "alice".to_string(),
+            to_user_id: "bob".to_string(),
+            payload: from.seal("bob", &signal_type, payload).unwrap(),
+            signal_type,
+        }
+    }
+
+    fn answer() -> Signal {
+        Signal::Answer {
+            sdp: "v=0".to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trips_a_sealed_signal() {
+        let (alice, bob) = pair("round-trip");
+        let message = sealed(&alice, &answer());
+        // Nothing but the version is readable on the way
+        let fields: Vec<_> = message.payload.as_object().unwrap().keys().collect();
+        assert_eq!(fields, ["sealed", "v"]);
+
+        let opened = bob.open(message).unwrap();
+        assert_eq!(opened.signal, answer());
+        assert_eq!(opened.from_user_id, "alice");
+        assert_eq!(opened.session_id, "session-1");
+        assert_eq!(opened.message_id, "message-1");
+    }
+
+    #[test]
+    fn rejects_a_tampered_payload() {
+        let (alice, bob) = pair("tampered");
+        let mut message = sealed(&alice, &answer());
+        let mut bytes = STANDARD
+            .decode(message.payload[SEALED_FIELD].as_str().unwrap())
+            .unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        message.payload[SEALED_FIELD] = STANDARD.encode(bytes).into();
+        let error = bob.open(message).unwrap_err();
+        assert!(error.ends_with("failed authentication"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_payload_passed_off_as_another_type() {
+        let (alice, bob) = pair("retyped");
+        let mut message = sealed(&alice, &answer());
+        message.signal_type = "offer".to_string();
+        let error = bob.open(message).unwrap_err();
+        assert!(error.ends_with("sealed as answer"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_payload_sealed_with_another_key() {
+        let (_, bob) = pair("wrong-key");
+        // Mallory claims to be Alice, but doesn't hold her key
+        let mallory = keyring("wrong-key-mallory");
+        mallory.pin("bob", &bob.public_key()).unwrap();
+        let error = bob.open(sealed(&mallory, &answer())).unwrap_err();
+        assert!(error.ends_with("failed authentication"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_sender_without_a_key() {
+        let (alice, _) = pair("no-key");
+        let carol = keyring("no-key-carol");
+        let error = carol.open(sealed(&alice, &answer())).unwrap_err();
+        assert!(error.ends_with("no signal key for the sender"), "{}", error);
+    }
+
+    #[test]
+    fn only_a_public_key_comes_unsealed() {
+        let bob = keyring("unsealed");
+        let envelope = Envelope {
+            session_id: "keys".to_string(),
+            message_id: "message-1".to_string(),
+        };
+        let unsealed = |signal: Signal| {
+            let (signal_type, payload) = signal.to_payload(&envelope);
+            RelayMessage {
+                from_user_id: "alice".to_string(),
+                to_user_id: "bob".to_string(),
+                signal_type,
+                payload,
+            }
+        };
+
+        let key = Signal::PublicKey {
+            key: keyring("unsealed-alice").public_key(),
+            reply: true,
+        };
+        assert_eq!(bob.open(unsealed(key.clone())).unwrap().signal, key);
+        let error = bob.open(unsealed(answer())).unwrap_err();
+        assert!(error.ends_with("not sealed"), "{}", error);
+    }
+
+    #[test]
+    fn refuses_a_changed_key() {
+        let bob = keyring("changed");
+        let first = keyring("changed-alice").public_key();
+        let second = keyring("changed-other").public_key();
+        assert!(bob.pin("alice", &first).unwrap());
+        // The same key again is fine
+        assert!(!bob.pin("alice", &first).unwrap());
+        assert!(bob.pin("alice", &second).is_err());
+        assert!(bob.pin("carol", "not a key").is_err());
+    }
+
+    #[test]
+    fn keeps_keys_across_restarts() {
+        let bob = keyring("restart");
+        let alice = keyring("restart-alice").public_key();
+        bob.pin("alice", &alice).unwrap();
+
+        let reloaded = Keyring::load(bob.path.clone()).unwrap();
+        assert_eq!(reloaded.public_key(), bob.public_key());
+        assert!(reloaded.has_key("alice"));
+        assert!(reloaded
+            .pin("alice", &keyring("restart-other").public_key())
+            .is_err());
+    }
+}
@@ -0,0 +1,426 @@
+use super::auth::token_claims;
+use super::signaling::{Received, RelayMessage, SignalFuture, SignalTransport};
+use futures_util::{SinkExt, StreamExt};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+/// DNS-SD service every app on the network advertises and browses for
+const SERVICE_TYPE: &str = "_share-terminal._tcp.local.";
+const USER_PROPERTY: &str = "user";
+const NAME_PROPERTY: &str = "name";
+const LAN_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+// How long to stay on the local network before trying the relay again
+const RELAY_RETRY: Duration = Duration::from_secs(60);
+
+/// Another app found on the local network.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LanPeer {
+    pub user_id: String,
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+}
+
+/// Advertises this user on the local network with mDNS, finds the others,
+/// and exchanges signals with them over direct WebSocket connections, for
+/// when the relay can't be reached.
+///
+/// A signal is only taken from the address its sender advertised, and
+/// like any other it still has to open with the sender's key. Since
+/// anyone can advertise any user, keys aren't first exchanged this way.
+pub struct LanTransport {
+    user_id: String,
+    daemon: ServiceDaemon,
+    fullname: String,
+    // Peers by their mDNS instance name
+    peers: Arc<Mutex<HashMap<String, LanPeer>>>,
+    inbox: Arc<Mutex<Vec<RelayMessage>>>,
+    // Open connections to peers by user id
+    connections: tokio::sync::Mutex<HashMap<String, mpsc::UnboundedSender<Message>>>,
+    listener: JoinHandle<()>,
+    since: Mutex<Instant>,
+}
+
+impl LanTransport {
+    /// Starts listening and advertising as the user `token` belongs to.
+    /// `arrived` is notified whenever a peer sends a signal.
+    pub async fn start(token: &str, arrived: Arc<Notify>) -> Result<Self, String> {
+        let claims = token_claims(token)?;
+        let name = if claims.email.is_empty() {
+            claims.user_id.clone()
+        } else {
+            claims.email
+        };
+
+        let listener = TcpListener::bind(("0.0.0.0", 0))
+            .await
+            .map_err(|e| format!("Failed to listen for local peers: {}", e))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to listen for local peers: {}", e))?
+            .port();
+
+        let daemon = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS: {}", e))?;
+        let instance = uuid::Uuid::new_v4().simple().to_string();
+        let properties = [
+            (USER_PROPERTY, claims.user_id.as_str()),
+            (NAME_PROPERTY, name.as_str()),
+        ];
+        let service = ServiceInfo::new(
+            SERVICE_TYPE,
+            &instance,
+            &format!("{}.local.", instance),
+            "",
+            port,
+            &properties[..],
+        )
+        .map_err(|e| format!("Failed to describe mDNS service: {}", e))?
+        .enable_addr_auto();
+        let fullname = service.get_fullname().to_string();
+        daemon
+            .register(service)
+            .map_err(|e| format!("Failed to advertise on the local network: {}", e))?;
+        let events = daemon
+            .browse(SERVICE_TYPE)
+            .map_err(|e| format!("Failed to browse the local network: {}", e))?;
+
+        let peers = Arc::new(Mutex::new(HashMap::new()));
+        {
+            let peers = peers.clone();
+            let own = fullname.clone();
+            // The daemon's receiver blocks, ends once the daemon shuts down
+            std::thread::spawn(move || {
+                while let Ok(event) = events.recv() {
+                    match event {
+                        ServiceEvent::ServiceResolved(info) if info.get_fullname() != own => {
+                            let Some(user_id) = info.get_property_val_str(USER_PROPERTY) else {
+                                continue;
+                            };
+                            let peer = LanPeer {
+                                user_id: user_id.to_string(),
+                                name: info
+                                    .get_property_val_str(NAME_PROPERTY)
+                                    .unwrap_or(user_id)
+                                    .to_string(),
+                                addresses: info.get_addresses().iter().copied().collect(),
+                                port: info.get_port(),
+                            };
+                            println!("Found {} on the local network", peer.name);
+                            peers
+                                .lock()
+                                .unwrap()
+                                .insert(info.get_fullname().to_string(), peer);
+                        }
+                        ServiceEvent::ServiceRemoved(_, fullname) => {
+                            peers.lock().unwrap().remove(&fullname);
+                        }
+                        _ => {}
+                    }
+                }
+            });
+        }
+
+        let inbox = Arc::new(Mutex::new(Vec::new()));
+        let listener = {
+            let user_id = claims.user_id.clone();
+            let peers = peers.clone();
+            let inbox = inbox.clone();
+            tokio::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, address)) => {
+                            tokio::spawn(serve_peer(
+                                stream,
+                                address.ip(),
+                                user_id.clone(),
+                                peers.clone(),
+                                inbox.clone(),
+                                arrived.clone(),
+                            ));
+                        }
+                        Err(e) => eprintln!("Failed to accept local peer: {}", e),
+                    }
+                }
+            })
+        };
+
+        println!("Advertising on the local network, port {}", port);
+        Ok(Self {
+            user_id: claims.user_id,
+            daemon,
+            fullname,
+            peers,
+            inbox,
+            connections: tokio::sync::Mutex::new(HashMap::new()),
+            listener,
+            since: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// The user this transport advertises.
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    /// Everyone found on the local network so far.
+    #[cfg(feature = "gui")]
+    pub fn peers(&self) -> Vec<LanPeer> {
+        self.peers.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Called when signaling switches over to this transport.
+    pub fn activate(&self) {
+        *self.since.lock().unwrap() = Instant::now();
+    }
+
+    fn address_of(&self, user_id: &str) -> Option<SocketAddr> {
+        let peers = self.peers.lock().unwrap();
+        let peer = peers.values().find(|peer| peer.user_id == user_id)?;
+        // Link-local IPv6 would need a scope, IPv4 is the safer bet
+        let ip = peer
+            .addresses
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .or_else(|| peer.addresses.first())?;
+        Some(SocketAddr::new(*ip, peer.port))
+    }
+
+    async fn connection(&self, user_id: &str) -> Result<mpsc::UnboundedSender<Message>, String> {
+        let mut connections = self.connections.lock().await;
+        if let Some(connection) = connections.get(user_id) {
+            if !connection.is_closed() {
+                return Ok(connection.clone());
+            }
+        }
+
+        let address = self
+            .address_of(user_id)
+            .ok_or_else(|| format!("{} is not on the local network", user_id))?;
+        let (socket, _) = tokio::time::timeout(
+            LAN_CONNECT_TIMEOUT,
+            tokio_tungstenite::connect_async(format!("ws://{}", address)),
+        )
+        .await
+        .map_err(|_| format!("Connecting to {} timed out", address))?
+        .map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+        let (mut sink, mut stream) = socket.split();
+
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<Message>();
+        tokio::spawn(async move {
+            while let Some(message) = outgoing_rx.recv().await {
+                if let Err(e) = sink.send(message).await {
+                    eprintln!("Failed to write to local peer: {}", e);
+                    break;
+                }
+            }
+        });
+        // Nothing comes back on this connection, but the socket has to be read to notice it closing
+        tokio::spawn(async move { while let Some(Ok(_)) = stream.next().await {} });
+
+        connections.insert(user_id.to_string(), outgoing.clone());
+        Ok(outgoing)
+    }
+}
+
+/// Reads the signals one peer sends over its connection to us.
+async fn serve_peer(
+    stream: TcpStream,
+    ip: IpAddr,
+    user_id: String,
+    peers: Arc<Mutex<HashMap<String, LanPeer>>>,
+    inbox: Arc<Mutex<Vec<RelayMessage>>>,
+    arrived: Arc<Notify>,
+) {
+    let mut socket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("Rejected local connection from {}: {}", ip, e);
+            return;
+        }
+    };
+    while let Some(Ok(message)) = socket.next().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+        let message: RelayMessage = match serde_json::from_str(&text) {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("Ignoring local signal: {} - {}", e, text);
+                continue;
+            }
+        };
+        let known = peers
+            .lock()
+            .unwrap()
+            .values()
+            .any(|peer| peer.user_id == message.from_user_id && peer.addresses.contains(&ip));
+        if message.to_user_id != user_id || !known {
+            eprintln!(
+                "Ignoring {} from {} at {}, not meant for us or not from where they were found",
+                message.signal_type, message.from_user_id, ip
+            );
+            continue;
+        }
+        inbox.lock().unwrap().push(message);
+        arrived.notify_one();
+    }
+}
+
+impl SignalTransport for LanTransport {
+    fn send(&self, message: RelayMessage) -> SignalFuture<'_, ()> {
+        Box::pin(async move {
+            // No relay to say who it's from
+            let message = RelayMessage {
+                from_user_id: self.user_id.clone(),
+                ..message
+            };
+            let text = serde_json::to_string(&message)
+                .map_err(|e| format!("Failed to serialize signal: {}", e))?;
+            self.connection(&message.to_user_id)
+                .await?
+                .send(Message::Text(text))
+                .map_err(|_| format!("Connection to {} is closed", message.to_user_id))
+        })
+    }
+
+    fn receive(&self) -> SignalFuture<'_, Received> {
+        let messages = std::mem::take(&mut *self.inbox.lock().unwrap());
+        Box::pin(async move {
+            Ok(Received {
+                messages,
+                ..Received::default()
+            })
+        })
+    }
+
+    fn should_reconnect(&self) -> bool {
+        // The relay may be back
+        self.since.lock().unwrap().elapsed() >= RELAY_RETRY
+    }
+
+    fn name(&self) -> &'static str {
+        "lan"
+    }
+}
+
+impl Drop for LanTransport {
+    fn drop(&mut self) {
+        self.listener.abort();
+        let _ = self.daemon.unregister(&self.fullname);
+        let _ = self.daemon.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn peer(user_id: &str, ip: IpAddr) -> LanPeer {
+        LanPeer {
+            user_id: user_id.to_string(),
+            name: user_id.to_string(),
+            addresses: vec![ip],
+            port: 1,
+        }
+    }
+
+    fn message(from_user_id: &str, to_user_id: &str) -> Message {
+        let message = RelayMessage {
+            from_user_id: from_user_id.to_string(),
+            to_user_id: to_user_id.to_string(),
+            signal_type: "hangup".to_string(),
+            payload: serde_json::Value::Null,
+        };
+        Message::Text(serde_json::to_string(&message).unwrap())
+    }
+
+    /// Sends `messages` to a `serve_peer` for "me" over a local connection,
+    /// and returns what it let into the inbox.
+    async fn serve(peers: Vec<LanPeer>, messages: Vec<Message>) -> Vec<RelayMessage> {
+        let peers = Arc::new(Mutex::new(
+            peers
+                .into_iter()
+                .enumerate()
+                .map(|(i, peer)| (i.to_string(), peer))
+                .collect(),
+        ));
+        let inbox = Arc::new(Mutex::new(Vec::new()));
+        let listener = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = {
+            let inbox = inbox.clone();
+            tokio::spawn(async move {
+                let (stream, from) = listener.accept().await.unwrap();
+                let arrived = Arc::new(Notify::new());
+                serve_peer(stream, from.ip(), "me".to_string(), peers, inbox, arrived).await;
+            })
+        };
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}", address))
+            .await
+            .unwrap();
+        for message in messages {
+            socket.send(message).await.unwrap();
+        }
+        socket.close(None).await.unwrap();
+        server.await.unwrap();
+        let messages = std::mem::take(&mut *inbox.lock().unwrap());
+        messages
+    }
+
+    #[tokio::test]
+    async fn takes_signals_from_where_the_sender_was_found() {
+        let inbox = serve(vec![peer("alice", LOCALHOST)], vec![message("alice", "me")]).await;
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].from_user_id, "alice");
+    }
+
+    #[tokio::test]
+    async fn ignores_a_sender_found_at_another_address() {
+        let elsewhere = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let inbox = serve(vec![peer("alice", elsewhere)], vec![message("alice", "me")]).await;
+        assert!(inbox.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ignores_a_sender_claiming_another_user() {
+        // Bob is at this address, but can't sign his signals as Alice
+        let inbox = serve(
+            vec![
+                peer("alice", IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))),
+                peer("bob", LOCALHOST),
+            ],
+            vec![message("alice", "me"), message("bob", "me")],
+        )
+        .await;
+        let senders: Vec<_> = inbox.iter().map(|m| m.from_user_id.as_str()).collect();
+        assert_eq!(senders, ["bob"]);
+    }
+
+    #[tokio::test]
+    async fn ignores_unknown_senders_and_signals_for_someone_else() {
+        let inbox = serve(
+            vec![peer("alice", LOCALHOST)],
+            vec![
+                message("mallory", "me"),
+                message("alice", "someone-else"),
+                Message::Text("not a signal".to_string()),
+                Message::Binary(vec![1, 2, 3]),
+            ],
+        )
+        .await;
+        assert!(inbox.is_empty());
+    }
+}
@@ -1,3 +1,8 @@
+#[cfg(feature = "gui")]
 pub mod access;
 pub mod auth;
+pub mod delivery;
+pub mod ice;
+pub mod keys;
+pub mod lan;
 pub mod signaling;
@@ -1,36 +1,60 @@
-use crate::constants::{build_url, endpoints};
+use super::auth::token_claims;
+use super::delivery::{new_message_id, Delivery};
+use super::keys::Keyring;
+use super::lan::LanTransport;
+use crate::constants::{build_url, build_ws_url, endpoints, API_BASE_URL};
+use futures_util::{SinkExt, StreamExt};
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SignalMessage {
-    #[serde(rename = "fromUserId")]
-    pub from_user_id: String,
-    #[serde(rename = "toUserId")]
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+#[cfg(feature = "gui")]
+use {
+    super::access::{get_connected_users, get_viewers},
+    super::lan::LanPeer,
+};
+
+// Give up on the socket and poll instead if it can't connect within this
+const WEBSOCKET_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// How long to poll before trying the socket again
+const WEBSOCKET_RETRY: Duration = Duration::from_secs(60);
+// The relay counts as down if it doesn't answer within this
+const RELAY_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub use super::delivery::Inbox;
+pub use share_protocol::{Envelope, RelayMessage, Signal, SignalMessage};
+
+/// A signal on its way to `to_user_id`.
+#[derive(Clone, Debug)]
+pub struct OutgoingSignal {
     pub to_user_id: String,
-    #[serde(rename = "type")]
-    pub signal_type: String,
-    pub payload: HashMap<String, serde_json::Value>,
+    pub envelope: Envelope,
+    pub signal: Signal,
 }
 
-pub async fn send_signal(
-    token: String,
-    to_user_id: String,
-    signal_type: String,
-    payload: serde_json::Value,
-) -> Result<(), String> {
+/// What the relay expects, over HTTP and the socket alike. The sender is
+/// whoever the token belongs to.
+fn relay_json(message: &RelayMessage) -> serde_json::Value {
+    serde_json::json!({
+        "toUserId": message.to_user_id,
+        "type": message.signal_type,
+        "payload": message.payload
+    })
+}
+
+pub async fn send_signal(token: String, message: RelayMessage) -> Result<(), String> {
     let client = reqwest::Client::new();
 
     let response = client
         .post(&build_url(endpoints::SIGNAL_SEND))
         .header(AUTHORIZATION, format!("Bearer {}", token))
         .header(CONTENT_TYPE, "application/json")
-        .json(&serde_json::json!({
-            "toUserId": to_user_id,
-            "type": signal_type,
-            "payload": payload
-        }))
+        .json(&relay_json(&message))
         .send()
         .await
         .map_err(|e| format!("Request failed: {}", e))?;
@@ -44,7 +68,7 @@ pub async fn send_signal(
     Ok(())
 }
 
-pub async fn fetch_inbox(token: String) -> Result<Vec<SignalMessage>, String> {
+pub async fn fetch_inbox(token: String) -> Result<Received, String> {
     let client = reqwest::Client::new();
 
     let response = client
@@ -76,8 +100,7 @@ pub async fn fetch_inbox(token: String) -> Result<Vec<SignalMessage>, String> {
         .get("messages")
         .ok_or_else(|| format!("Missing 'messages' field in response: {}", response_text))?;
 
-    // Deserialize the messages
-    let messages_vec: Vec<SignalMessage> =
+    let messages: Vec<serde_json::Value> =
         serde_json::from_value(messages.clone()).map_err(|e| {
             format!(
                 "Failed to deserialize messages: {} - Messages: {}",
@@ -85,5 +108,574 @@ pub async fn fetch_inbox(token: String) -> Result<Vec<SignalMessage>, String> {
             )
         })?;
 
-    Ok(messages_vec)
+    // Drop messages that don't follow the protocol, the rest are still worth delivering
+    let mut received = Received::default();
+    for message in messages {
+        match serde_json::from_value::<RelayMessage>(message.clone()) {
+            Ok(message) => received.messages.push(message),
+            Err(e) => received.malformed.push(malformed(e, &message)),
+        }
+    }
+    Ok(received)
+}
+
+/// What a transport received since the last call.
+#[derive(Default, Debug)]
+pub struct Received {
+    /// Oldest first
+    pub messages: Vec<RelayMessage>,
+    /// Why messages that couldn't be read were dropped
+    pub malformed: Vec<String>,
+}
+
+fn malformed(e: impl std::fmt::Display, message: impl std::fmt::Display) -> String {
+    format!("Dropped a malformed signal: {} - {}", e, message)
+}
+
+pub type SignalFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + Send + 'a>>;
+
+/// A way of exchanging signals with the backend on behalf of one user.
+/// Transports carry messages as they are, sealing happens in [`Signaling`].
+pub trait SignalTransport: Send + Sync {
+    fn send(&self, message: RelayMessage) -> SignalFuture<'_, ()>;
+
+    /// Everything received since the last call.
+    fn receive(&self) -> SignalFuture<'_, Received>;
+
+    /// Whether it's time to replace this transport with a fresh [`connect`].
+    fn should_reconnect(&self) -> bool;
+
+    fn name(&self) -> &'static str;
+}
+
+/// Connects over WebSocket, or falls back to polling `/signal/inbox` when the
+/// socket can't be opened. When the relay doesn't answer at all, signals go
+/// straight to peers on the local network through `lan`, if given.
+/// `arrived` is notified whenever the socket pushes a signal.
+pub async fn connect(
+    token: String,
+    lan: Option<Arc<LanTransport>>,
+    arrived: Arc<Notify>,
+) -> Arc<dyn SignalTransport> {
+    let e = match WebSocketTransport::connect(token.clone(), arrived).await {
+        Ok(transport) => return Arc::new(transport),
+        Err(e) => e,
+    };
+    match lan {
+        Some(lan) if !relay_reachable().await => {
+            eprintln!(
+                "{}, and the relay is unreachable, signaling on the local network",
+                e
+            );
+            lan.activate();
+            lan
+        }
+        _ => {
+            eprintln!("{}, polling the inbox instead", e);
+            Arc::new(PollingTransport::new(token))
+        }
+    }
+}
+
+/// Whether the relay answers at all, whatever it answers.
+async fn relay_reachable() -> bool {
+    match reqwest::Client::builder()
+        .timeout(RELAY_PROBE_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client.get(API_BASE_URL).send().await.is_ok(),
+        Err(_) => true,
+    }
+}
+
+/// Sends through `POST /signal/send` and polls `GET /signal/inbox`.
+pub struct PollingTransport {
+    token: String,
+    since: Instant,
+}
+
+impl PollingTransport {
+    pub fn new(token: String) -> Self {
+        Self {
+            token,
+            since: Instant::now(),
+        }
+    }
+}
+
+impl SignalTransport for PollingTransport {
+    fn send(&self, message: RelayMessage) -> SignalFuture<'_, ()> {
+        Box::pin(send_signal(self.token.clone(), message))
+    }
+
+    fn receive(&self) -> SignalFuture<'_, Received> {
+        Box::pin(fetch_inbox(self.token.clone()))
+    }
+
+    fn should_reconnect(&self) -> bool {
+        // The socket may have been down only briefly
+        self.since.elapsed() >= WEBSOCKET_RETRY
+    }
+
+    fn name(&self) -> &'static str {
+        "polling"
+    }
+}
+
+/// Keeps a socket to `/signal/ws` open. The backend pushes every signal as
+/// soon as it arrives; they are buffered here until [`receive`] is called,
+/// which the notified `arrived` should prompt.
+///
+/// [`receive`]: SignalTransport::receive
+pub struct WebSocketTransport {
+    outgoing: mpsc::UnboundedSender<Message>,
+    inbox: Arc<Mutex<Received>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl WebSocketTransport {
+    pub async fn connect(token: String, arrived: Arc<Notify>) -> Result<Self, String> {
+        let mut request = build_ws_url(endpoints::SIGNAL_WS)
+            .into_client_request()
+            .map_err(|e| format!("Invalid signal socket URL: {}", e))?;
+        // tungstenite's http crate isn't reqwest's, so no AUTHORIZATION constant here
+        request.headers_mut().insert(
+            "Authorization",
+            format!("Bearer {}", token)
+                .parse()
+                .map_err(|e| format!("Invalid token: {}", e))?,
+        );
+
+        let (socket, _) = tokio::time::timeout(
+            WEBSOCKET_CONNECT_TIMEOUT,
+            tokio_tungstenite::connect_async(request),
+        )
+        .await
+        .map_err(|_| "Signal socket timed out".to_string())?
+        .map_err(|e| format!("Failed to open signal socket: {}", e))?;
+        let (mut sink, mut stream) = socket.split();
+
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<Message>();
+        let inbox = Arc::new(Mutex::new(Received::default()));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        {
+            let closed = closed.clone();
+            tokio::spawn(async move {
+                while let Some(message) = outgoing_rx.recv().await {
+                    if let Err(e) = sink.send(message).await {
+                        eprintln!("Failed to write to signal socket: {}", e);
+                        break;
+                    }
+                }
+                closed.store(true, Ordering::SeqCst);
+            });
+        }
+
+        {
+            let inbox = inbox.clone();
+            let closed = closed.clone();
+            let outgoing = outgoing.clone();
+            tokio::spawn(async move {
+                while let Some(message) = stream.next().await {
+                    match message {
+                        Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                            Ok(message) => {
+                                inbox.lock().unwrap().messages.push(message);
+                                arrived.notify_one();
+                            }
+                            Err(e) => {
+                                inbox.lock().unwrap().malformed.push(malformed(e, &text));
+                                arrived.notify_one();
+                            }
+                        },
+                        Ok(Message::Ping(data)) => {
+                            let _ = outgoing.send(Message::Pong(data));
+                        }
+                        Ok(Message::Close(_)) => break,
+                        Ok(_) => {}
+                        Err(e) => {
+                            eprintln!("Signal socket failed: {}", e);
+                            break;
+                        }
+                    }
+                }
+                closed.store(true, Ordering::SeqCst);
+            });
+        }
+
+        Ok(Self {
+            outgoing,
+            inbox,
+            closed,
+        })
+    }
+}
+
+impl SignalTransport for WebSocketTransport {
+    fn send(&self, message: RelayMessage) -> SignalFuture<'_, ()> {
+        let text = relay_json(&message).to_string();
+        let result = if self.closed.load(Ordering::SeqCst) {
+            Err("Signal socket is closed".to_string())
+        } else {
+            self.outgoing
+                .send(Message::Text(text))
+                .map_err(|_| "Signal socket is closed".to_string())
+        };
+        Box::pin(async move { result })
+    }
+
+    fn receive(&self) -> SignalFuture<'_, Received> {
+        let received = std::mem::take(&mut *self.inbox.lock().unwrap());
+        // Hand out what arrived before the socket dropped, report the drop after that
+        let result = if received.messages.is_empty()
+            && received.malformed.is_empty()
+            && self.closed.load(Ordering::SeqCst)
+        {
+            Err("Signal socket is closed".to_string())
+        } else {
+            Ok(received)
+        };
+        Box::pin(async move { result })
+    }
+
+    fn should_reconnect(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+}
+
+impl Drop for WebSocketTransport {
+    fn drop(&mut self) {
+        let _ = self.outgoing.send(Message::Close(None));
+    }
+}
+
+/// The transport signals currently go through, replaced when the token
+/// changes or the transport asks to be, the bookkeeping that makes
+/// delivery over it reliable and the keys that seal every signal.
+pub struct Signaling {
+    current: tokio::sync::Mutex<Option<(String, Arc<dyn SignalTransport>)>>,
+    delivery: Delivery,
+    keys: Keyring,
+    // Poked by transports that have signals pushed to them
+    arrived: Arc<Notify>,
+    lan_enabled: AtomicBool,
+    // Kept across transports so local peers can find us whichever is in use
+    lan: tokio::sync::Mutex<Option<Arc<LanTransport>>>,
+}
+
+impl Signaling {
+    pub fn new(keys: Keyring) -> Self {
+        Self {
+            current: tokio::sync::Mutex::new(None),
+            delivery: Delivery::default(),
+            keys,
+            arrived: Arc::new(Notify::new()),
+            lan_enabled: AtomicBool::new(false),
+            lan: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Also advertises on the local network and signals there while the
+    /// relay is unreachable.
+    pub fn with_lan(keys: Keyring) -> Self {
+        Self {
+            lan_enabled: AtomicBool::new(true),
+            ..Self::new(keys)
+        }
+    }
+
+    pub async fn transport(&self, token: &str) -> Arc<dyn SignalTransport> {
+        let mut current = self.current.lock().await;
+        if let Some((current_token, transport)) = current.as_ref() {
+            if current_token == token && !transport.should_reconnect() {
+                return transport.clone();
+            }
+        }
+        let lan = self.lan(token).await;
+        let transport = connect(token.to_string(), lan, self.arrived.clone()).await;
+        println!("Signaling over {}", transport.name());
+        *current = Some((token.to_string(), transport.clone()));
+        transport
+    }
+
+    /// The local network transport for the user `token` belongs to, started
+    /// on first use.
+    async fn lan(&self, token: &str) -> Option<Arc<LanTransport>> {
+        if !self.lan_enabled.load(Ordering::SeqCst) {
+            return None;
+        }
+        let user_id = match token_claims(token) {
+            Ok(claims) => claims.user_id,
+            Err(e) => {
+                eprintln!("{}, not signaling on the local network", e);
+                self.lan_enabled.store(false, Ordering::SeqCst);
+                return None;
+            }
+        };
+        let mut lan = self.lan.lock().await;
+        if let Some(transport) = lan.as_ref() {
+            if transport.user_id() == user_id {
+                return Some(transport.clone());
+            }
+        }
+        // Someone else logged in, advertise them instead
+        *lan = None;
+        match LanTransport::start(token, self.arrived.clone()).await {
+            Ok(transport) => {
+                let transport = Arc::new(transport);
+                *lan = Some(transport.clone());
+                Some(transport)
+            }
+            Err(e) => {
+                eprintln!("{}, not signaling on the local network", e);
+                self.lan_enabled.store(false, Ordering::SeqCst);
+                None
+            }
+        }
+    }
+
+    /// Peers found on the local network so far, starting to look for them
+    /// if nothing did yet. Empty when the local network isn't used.
+    #[cfg(feature = "gui")]
+    pub async fn lan_peers(&self, token: &str) -> Vec<LanPeer> {
+        match self.lan(token).await {
+            Some(lan) => lan.peers(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The transport in use for whichever token was seen last.
+    pub async fn latest(&self) -> Result<Arc<dyn SignalTransport>, String> {
+        self.current
+            .lock()
+            .await
+            .as_ref()
+            .map(|(_, transport)| transport.clone())
+            .ok_or_else(|| "Not connected for signaling".to_string())
+    }
+
+    /// Sends `signal` with a fresh message id. Signals that need an ack are
+    /// sent again on later [`receive`](Self::receive) calls until acknowledged.
+    ///
+    /// Fails for peers we have no key for yet, after asking them for theirs.
+    pub async fn send(
+        &self,
+        token: &str,
+        to_user_id: String,
+        session_id: String,
+        signal: Signal,
+    ) -> Result<(), String> {
+        let transport = self.transport(token).await;
+        self.send_over(transport, to_user_id, session_id, signal)
+            .await
+    }
+
+    /// Like [`send`](Self::send), through whatever transport was set up
+    /// last, so a long-lived sender doesn't hold on to a dropped socket or
+    /// an expired token.
+    pub async fn send_latest(
+        &self,
+        to_user_id: String,
+        session_id: String,
+        signal: Signal,
+    ) -> Result<(), String> {
+        let transport = self.latest().await?;
+        self.send_over(transport, to_user_id, session_id, signal)
+            .await
+    }
+
+    async fn send_over(
+        &self,
+        transport: Arc<dyn SignalTransport>,
+        to_user_id: String,
+        session_id: String,
+        signal: Signal,
+    ) -> Result<(), String> {
+        let signal = OutgoingSignal {
+            to_user_id,
+            envelope: Envelope {
+                session_id,
+                message_id: new_message_id(),
+            },
+            signal,
+        };
+        if !self.keys.has_key(&signal.to_user_id) {
+            // Sent once their key is in, see `receive`
+            let to_user_id = signal.to_user_id.clone();
+            if self.delivery.hold(signal) {
+                self.send_key(&*transport, &to_user_id, true).await?;
+            }
+            return Ok(());
+        }
+        self.deliver(&*transport, signal.clone()).await?;
+        self.delivery.track(signal);
+        Ok(())
+    }
+
+    /// Seals `signal` for its recipient and sends it, public keys go as they are.
+    async fn deliver(
+        &self,
+        transport: &dyn SignalTransport,
+        signal: OutgoingSignal,
+    ) -> Result<(), String> {
+        let (signal_type, payload) = signal.signal.to_payload(&signal.envelope);
+        let payload = if matches!(signal.signal, Signal::PublicKey { .. }) {
+            payload
+        } else {
+            self.keys.seal(&signal.to_user_id, &signal_type, payload)?
+        };
+        transport
+            .send(RelayMessage {
+                // Filled in by the relay, or the transport that knows who we are
+                from_user_id: String::new(),
+                to_user_id: signal.to_user_id,
+                signal_type,
+                payload,
+            })
+            .await
+    }
+
+    /// Tells `to_user_id` our public key, `reply` asks for theirs in return.
+    async fn send_key(
+        &self,
+        transport: &dyn SignalTransport,
+        to_user_id: &str,
+        reply: bool,
+    ) -> Result<(), String> {
+        let id = new_message_id();
+        self.deliver(
+            transport,
+            OutgoingSignal {
+                to_user_id: to_user_id.to_string(),
+                envelope: Envelope {
+                    // Keys belong to no session, but every signal needs one
+                    session_id: format!("keys-{}", id),
+                    message_id: id,
+                },
+                signal: Signal::PublicKey {
+                    key: self.keys.public_key(),
+                    reply,
+                },
+            },
+        )
+        .await
+    }
+
+    /// Exchanges keys with every viewer and connection we have none for
+    /// yet, e.g. right after granting someone access.
+    #[cfg(feature = "gui")]
+    pub async fn share_keys(&self, token: &str) -> Result<(), String> {
+        let mut users = get_viewers(token.to_string()).await?;
+        users.extend(get_connected_users(token.to_string()).await?);
+        let transport = self.transport(token).await;
+        for user in users {
+            if self.keys.has_key(&user.id) {
+                continue;
+            }
+            if let Err(e) = self.send_key(&*transport, &user.id, true).await {
+                eprintln!("Failed to send our key to {}: {}", user.id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves once a transport has signals pushed to it, so they can be
+    /// [`receive`](Self::receive)d right away instead of on the next poll.
+    /// A push since the last call counts.
+    pub async fn arrived(&self) {
+        self.arrived.notified().await
+    }
+
+    /// Messages received since the last call, without acks, key exchanges,
+    /// duplicates and anything that doesn't open with its sender's key, the
+    /// signals nobody acknowledged in time, and why unreadable messages were
+    /// dropped. Acknowledges what needs it and retries what is overdue on the way.
+    pub async fn receive(&self, token: &str) -> Result<Inbox, String> {
+        let transport = self.transport(token).await;
+        let Received {
+            messages: received,
+            malformed,
+        } = transport.receive().await?;
+        let (relayed, mut local) = if transport.name() == "lan" {
+            (Vec::new(), received)
+        } else {
+            (received, Vec::new())
+        };
+        // Local peers that lost the relay still reach us directly
+        let lan = self.lan.lock().await.clone();
+        if let Some(lan) = lan.filter(|_| transport.name() != "lan") {
+            // What the relay delivered is still good when the local network isn't
+            match lan.receive().await {
+                Ok(received) => local.extend(received.messages),
+                Err(e) => eprintln!("Failed to receive from the local network: {}", e),
+            }
+        }
+        let open = |message| self.keys.open(message).map_err(|e| eprintln!("{}", e)).ok();
+        let mut messages: Vec<SignalMessage> = relayed.into_iter().filter_map(open).collect();
+        // Anyone on the network can claim to be anyone, only the relay vouches for
+        // who sent a key we have none for yet
+        messages.extend(local.into_iter().filter_map(open).filter(|message| {
+            let untrusted = matches!(message.signal, Signal::PublicKey { .. })
+                && !self.keys.has_key(&message.from_user_id);
+            if untrusted {
+                eprintln!(
+                    "Ignoring the key {} sent over the local network, keys are first exchanged through the relay",
+                    message.from_user_id
+                );
+            }
+            !untrusted
+        }));
+
+        let (messages, acks) = self.delivery.receive(messages);
+        let mut fresh = Vec::new();
+        let mut trusted = Vec::new();
+        for message in messages {
+            let Signal::PublicKey { key, reply } = &message.signal else {
+                fresh.push(message);
+                continue;
+            };
+            match self.keys.pin(&message.from_user_id, key) {
+                Ok(true) => trusted.push(message.from_user_id.clone()),
+                Ok(false) => {}
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            }
+            for signal in self.delivery.release(&message.from_user_id) {
+                let kind = signal.signal.kind();
+                match self.deliver(&*transport, signal.clone()).await {
+                    Ok(()) => self.delivery.track(signal),
+                    Err(e) => eprintln!("Failed to send {}: {}", kind, e),
+                }
+            }
+            if *reply {
+                if let Err(e) = self
+                    .send_key(&*transport, &message.from_user_id, false)
+                    .await
+                {
+                    eprintln!("Failed to send our key to {}: {}", message.from_user_id, e);
+                }
+            }
+        }
+
+        let (retries, failures) = self.delivery.due();
+        for signal in acks.into_iter().chain(retries) {
+            let kind = signal.signal.kind();
+            if let Err(e) = self.deliver(&*transport, signal).await {
+                eprintln!("Failed to send {}: {}", kind, e);
+            }
+        }
+        Ok(Inbox {
+            messages: fresh,
+            failures,
+            malformed,
+            trusted,
+        })
+    }
 }
@@ -0,0 +1,18 @@
+use clap::Parser;
+use terminal_lib::agent::{self, Cli, Config};
+
+#[tokio::main]
+async fn main() {
+    let config = match Config::load(Cli::parse()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = agent::run(config).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::api;
+use crate::api::access::UserResponse;
+use crate::api::auth::{token_claims, LoginResponse, LogoutResponse, SignupResponse};
+use crate::api::ice::{IceConfig, IceServers};
+use crate::api::lan::LanPeer;
+use crate::api::signaling::{Signal, Signaling};
+use crate::inbox::InboxWatcher;
+use crate::settings::ConnectionSettings;
+use crate::terminal::TerminalManager;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+
+#[tauri::command]
+pub fn greet(name: &str) -> String {
+    format!("Hello, {}! You've been greeted from Rust!", name)
+}
+
+#[tauri::command]
+pub async fn login(
+    app: tauri::AppHandle,
+    inbox: tauri::State<'_, InboxWatcher>,
+    email: String,
+    password: String,
+) -> Result<LoginResponse, String> {
+    let response = api::auth::login(email, password).await?;
+    inbox.watch(app, response.token.clone());
+    Ok(response)
+}
+
+#[tauri::command]
+pub async fn signup(email: String) -> Result<SignupResponse, String> {
+    api::auth::signup(email).await
+}
+
+#[tauri::command]
+pub async fn logout(inbox: tauri::State<'_, InboxWatcher>) -> Result<LogoutResponse, String> {
+    inbox.stop();
+    api::auth::logout().await
+}
+
+#[tauri::command]
+pub async fn get_connected_users(token: String) -> Result<Vec<UserResponse>, String> {
+    api::access::get_connected_users(token).await
+}
+
+#[tauri::command]
+pub async fn get_viewers(token: String) -> Result<Vec<UserResponse>, String> {
+    api::access::get_viewers(token).await
+}
+
+#[tauri::command]
+pub async fn request_access(token: String, target_user_id: String) -> Result<(), String> {
+    api::access::request_access(token, target_user_id).await
+}
+
+#[tauri::command]
+pub async fn get_incoming_requests(token: String) -> Result<Vec<UserResponse>, String> {
+    api::access::get_incoming_requests(token).await
+}
+
+#[tauri::command]
+pub async fn get_outgoing_requests(token: String) -> Result<Vec<UserResponse>, String> {
+    api::access::get_outgoing_requests(token).await
+}
+
+/// Accepts the request and exchanges signal keys with the new viewer.
+#[tauri::command]
+pub async fn accept_request(
+    signaling: tauri::State<'_, Signaling>,
+    token: String,
+    access_id: String,
+) -> Result<(), String> {
+    api::access::accept_request(token.clone(), access_id).await?;
+    signaling
+        .share_keys(&token)
+        .await
+        .map_err(|e| format!("Accepted, but failed to exchange signal keys: {}", e))
+}
+
+#[tauri::command]
+pub async fn reject_request(token: String, access_id: String) -> Result<(), String> {
+    api::access::reject_request(token, access_id).await
+}
+
+#[tauri::command]
+pub async fn cancel_request(token: String, access_id: String) -> Result<(), String> {
+    api::access::cancel_request(token, access_id).await
+}
+
+#[tauri::command]
+pub async fn send_signal(
+    signaling: tauri::State<'_, Signaling>,
+    token: String,
+    to_user_id: String,
+    session_id: String,
+    signal: Signal,
+) -> Result<(), String> {
+    signaling.send(&token, to_user_id, session_id, signal).await
+}
+
+/// Starts emitting `signal`, `signal-failure`, `inbox-error` and `key-trusted`
+/// events for the user `token` belongs to, e.g. when the app starts with a
+/// stored token. Login does this on its own.
+#[tauri::command]
+pub fn watch_inbox(app: tauri::AppHandle, inbox: tauri::State<'_, InboxWatcher>, token: String) {
+    inbox.watch(app, token);
+}
+
+/// Other apps found on the local network, reachable even when the relay isn't.
+#[tauri::command]
+pub async fn get_lan_peers(
+    signaling: tauri::State<'_, Signaling>,
+    token: String,
+) -> Result<Vec<LanPeer>, String> {
+    Ok(signaling.lan_peers(&token).await)
+}
+
+/// ICE servers for a new peer connection, with fresh TURN credentials if
+/// they come from a TURN REST API.
+#[tauri::command]
+pub async fn get_ice_config(
+    ice: tauri::State<'_, IceServers>,
+    token: String,
+) -> Result<IceConfig, String> {
+    let user = token_claims(&token)?.user_id;
+    Ok(ice.config(&user).await)
+}
+
+/// How connections behave once they are up, e.g. how long to reconnect.
+#[tauri::command]
+pub fn get_connection_settings(
+    settings: tauri::State<'_, ConnectionSettings>,
+) -> ConnectionSettings {
+    settings.inner().clone()
+}
+
+#[tauri::command]
+pub fn start_terminal(
+    app: tauri::AppHandle,
+    terminals: tauri::State<'_, TerminalManager>,
+    cols: u16,
+    rows: u16,
+    shell: Option<String>,
+) -> Result<String, String> {
+    terminals.start(app, cols, rows, shell)
+}
+
+#[tauri::command]
+pub fn write_terminal(
+    terminals: tauri::State<'_, TerminalManager>,
+    id: String,
+    data: String,
+) -> Result<(), String> {
+    terminals.write(&id, &data)
+}
+
+#[tauri::command]
+pub fn resize_terminal(
+    terminals: tauri::State<'_, TerminalManager>,
+    id: String,
+    cols: u16,
+    rows: u16,
+) -> Result<(), String> {
+    terminals.resize(&id, cols, rows)
+}
+
+#[tauri::command]
+pub fn kill_terminal(
+    terminals: tauri::State<'_, TerminalManager>,
+    id: String,
+) -> Result<(), String> {
+    terminals.kill(&id)
+}
+
+#[tauri::command]
+pub fn start_recording(
+    app: tauri::AppHandle,
+    terminals: tauri::State<'_, TerminalManager>,
+    id: String,
+) -> Result<String, String> {
+    terminals.start_recording(&app, &id)
+}
+
+#[tauri::command]
+pub fn stop_recording(
+    terminals: tauri::State<'_, TerminalManager>,
+    id: String,
+) -> Result<String, String> {
+    terminals.stop_recording(&id)
+}
+
+/// Writes connection stats exported for a bug report next to the recordings,
+/// returning the path of the file.
+#[tauri::command]
+pub fn save_connection_stats(app: tauri::AppHandle, report: String) -> Result<String, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data directory: {}", e))?
+        .join("connection-stats");
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let path = dir.join(format!("{}.json", timestamp));
+    std::fs::write(&path, report)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path.to_string_lossy().into_owned())
+}
@@ -5,19 +5,37 @@ pub const API_BASE_URL: &str =
 // API Endpoints
 pub mod endpoints {
     pub const AUTH_LOGIN: &str = "/auth/login";
+    #[cfg(feature = "gui")]
     pub const AUTH_SIGNUP: &str = "/auth/sign-up";
+    #[cfg(feature = "gui")]
     pub const ACCESS_CONNECTIONS: &str = "/access/connections";
+    #[cfg(feature = "gui")]
     pub const ACCESS_VIEWERS: &str = "/access/viewers";
+    #[cfg(feature = "gui")]
     pub const ACCESS_REQUEST: &str = "/access/request";
+    #[cfg(feature = "gui")]
     pub const ACCESS_REQUESTS_INCOMING: &str = "/access/requests/incoming";
+    #[cfg(feature = "gui")]
     pub const ACCESS_REQUESTS_OUTGOING: &str = "/access/requests/outgoing";
+    #[cfg(feature = "gui")]
     pub const ACCESS_REQUESTS_ACCEPT: &str = "/access/requests"; // accessId and /accept will be appended
+    #[cfg(feature = "gui")]
     pub const ACCESS_REQUESTS_REJECT: &str = "/access/requests"; // accessId and /reject will be appended
+    #[cfg(feature = "gui")]
     pub const ACCESS_CANCEL_REQUEST: &str = "/access/requests"; // accessId and /cancel will be appended
     pub const SIGNAL_SEND: &str = "/signal/send";
     pub const SIGNAL_INBOX: &str = "/signal/inbox";
+    pub const SIGNAL_WS: &str = "/signal/ws";
 }
 
 pub fn build_url(endpoint: &str) -> String {
     format!("{}{}", API_BASE_URL, endpoint)
 }
+
+/// Same as [`build_url`], for the WebSocket endpoints.
+pub fn build_ws_url(endpoint: &str) -> String {
+    let base = API_BASE_URL
+        .replacen("https://", "wss://", 1)
+        .replacen("http://", "ws://", 1);
+    format!("{}{}", base, endpoint)
+}
@@ -0,0 +1,83 @@
+use crate::api::signaling::Signaling;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager};
+
+// Events emitted to the webview for everything the inbox turns up
+pub const EVENT_SIGNAL: &str = "signal";
+pub const EVENT_SIGNAL_FAILURE: &str = "signal-failure";
+pub const EVENT_INBOX_ERROR: &str = "inbox-error";
+pub const EVENT_KEY_TRUSTED: &str = "key-trusted";
+
+// Polls the inbox when there's no socket and resends what is overdue,
+// signals pushed over the socket or the local network don't wait for it
+const RECEIVE_INTERVAL: Duration = Duration::from_secs(1);
+// Wait longer after a failure, the relay may be down or the token expired
+const ERROR_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Receives signals for the logged in user for as long as the app runs,
+/// whichever page the webview is on, and emits them as events.
+#[derive(Default)]
+pub struct InboxWatcher {
+    task: Mutex<Option<(String, JoinHandle<()>)>>,
+}
+
+impl InboxWatcher {
+    /// Starts receiving for `token`, replacing the task of any other token.
+    pub fn watch(&self, app: AppHandle, token: String) {
+        let mut task = self.task.lock().unwrap();
+        if let Some((current, _)) = task.as_ref() {
+            if *current == token {
+                return;
+            }
+        }
+        if let Some((_, previous)) = task.take() {
+            previous.abort();
+        }
+
+        let handle = tauri::async_runtime::spawn({
+            let token = token.clone();
+            async move {
+                loop {
+                    let signaling = app.state::<Signaling>();
+                    match signaling.receive(&token).await {
+                        Ok(inbox) => {
+                            for message in inbox.messages {
+                                let _ = app.emit(EVENT_SIGNAL, message);
+                            }
+                            for failure in inbox.failures {
+                                let _ = app.emit(EVENT_SIGNAL_FAILURE, failure);
+                            }
+                            for user_id in inbox.trusted {
+                                let _ = app.emit(EVENT_KEY_TRUSTED, user_id);
+                            }
+                            for e in inbox.malformed {
+                                eprintln!("{}", e);
+                                let _ = app.emit(EVENT_INBOX_ERROR, e);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to fetch inbox: {}", e);
+                            let _ = app.emit(EVENT_INBOX_ERROR, e);
+                            tokio::time::sleep(ERROR_BACKOFF).await;
+                            continue;
+                        }
+                    }
+                    tokio::select! {
+                        _ = tokio::time::sleep(RECEIVE_INTERVAL) => {}
+                        _ = signaling.arrived() => {}
+                    }
+                }
+            }
+        });
+        *task = Some((token, handle));
+    }
+
+    /// Stops receiving, e.g. on logout.
+    pub fn stop(&self) {
+        if let Some((_, task)) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+    }
+}
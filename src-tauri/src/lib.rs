@@ -1,104 +1,73 @@
 mod api;
-mod constants; // <-- Added this line
-
-use api::access::UserResponse;
-use api::auth::{LoginResponse, LogoutResponse, SignupResponse}; // Import UserResponse
-use api::signaling::SignalMessage;
-
-#[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
-}
-
-#[tauri::command]
-async fn login(email: String, password: String) -> Result<LoginResponse, String> {
-    api::auth::login(email, password).await
-}
-
-#[tauri::command]
-async fn signup(email: String) -> Result<SignupResponse, String> {
-    api::auth::signup(email).await
-}
-
-#[tauri::command]
-async fn logout() -> Result<LogoutResponse, String> {
-    api::auth::logout().await
-}
-
-#[tauri::command]
-async fn get_connected_users(token: String) -> Result<Vec<UserResponse>, String> {
-    api::access::get_connected_users(token).await
-}
-
-#[tauri::command]
-async fn get_viewers(token: String) -> Result<Vec<UserResponse>, String> {
-    api::access::get_viewers(token).await
-}
-
-#[tauri::command]
-async fn request_access(token: String, target_user_id: String) -> Result<(), String> {
-    api::access::request_access(token, target_user_id).await
-}
-
-#[tauri::command]
-async fn get_incoming_requests(token: String) -> Result<Vec<UserResponse>, String> {
-    api::access::get_incoming_requests(token).await
-}
-
-#[tauri::command]
-async fn get_outgoing_requests(token: String) -> Result<Vec<UserResponse>, String> {
-    api::access::get_outgoing_requests(token).await
-}
-
-#[tauri::command]
-async fn accept_request(token: String, access_id: String) -> Result<(), String> {
-    api::access::accept_request(token, access_id).await
-}
-
-#[tauri::command]
-async fn reject_request(token: String, access_id: String) -> Result<(), String> {
-    api::access::reject_request(token, access_id).await
-}
-
-#[tauri::command]
-async fn cancel_request(token: String, access_id: String) -> Result<(), String> {
-    api::access::cancel_request(token, access_id).await
-}
-
-#[tauri::command]
-async fn send_signal(
-    token: String,
-    to_user_id: String,
-    r#type: String,
-    payload: serde_json::Value,
-) -> Result<(), String> {
-    api::signaling::send_signal(token, to_user_id, r#type, payload).await
-}
-
-#[tauri::command]
-async fn fetch_inbox(token: String) -> Result<Vec<SignalMessage>, String> {
-    api::signaling::fetch_inbox(token).await
-}
-
+mod constants;
+mod terminal;
+
+#[cfg(feature = "agent")]
+pub mod agent;
+#[cfg(feature = "gui")]
+mod commands;
+#[cfg(feature = "gui")]
+mod inbox;
+#[cfg(feature = "gui")]
+mod settings;
+
+#[cfg(feature = "gui")]
+use api::ice::{IceServers, IceSettings};
+#[cfg(feature = "gui")]
+use api::keys::Keyring;
+#[cfg(feature = "gui")]
+use api::signaling::Signaling;
+#[cfg(feature = "gui")]
+use inbox::InboxWatcher;
+#[cfg(feature = "gui")]
+use settings::ConnectionSettings;
+#[cfg(feature = "gui")]
+use tauri::Manager;
+#[cfg(feature = "gui")]
+use terminal::TerminalManager;
+
+#[cfg(feature = "gui")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .manage(TerminalManager::default())
+        .manage(InboxWatcher::default())
+        .setup(|app| {
+            // Signal keys live with the app's data, they have to outlast restarts
+            let keys = Keyring::load(app.path().app_data_dir()?.join("signal-keys.json"))?;
+            app.manage(Signaling::with_lan(keys));
+            let ice = IceSettings::load(&app.path().app_config_dir()?.join("ice-servers.json"))?;
+            app.manage(IceServers::new(ice));
+            let connection = app.path().app_config_dir()?.join("connection.json");
+            app.manage(ConnectionSettings::load(&connection)?);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
-            greet,
-            login,
-            signup,
-            logout,
-            get_connected_users,
-            get_viewers,
-            request_access,
-            get_incoming_requests,
-            get_outgoing_requests,
-            accept_request,
-            reject_request,
-            cancel_request,
-            send_signal,
-            fetch_inbox
+            commands::greet,
+            commands::login,
+            commands::signup,
+            commands::logout,
+            commands::get_connected_users,
+            commands::get_viewers,
+            commands::request_access,
+            commands::get_incoming_requests,
+            commands::get_outgoing_requests,
+            commands::accept_request,
+            commands::reject_request,
+            commands::cancel_request,
+            commands::send_signal,
+            commands::watch_inbox,
+            commands::get_lan_peers,
+            commands::get_ice_config,
+            commands::get_connection_settings,
+            commands::start_terminal,
+            commands::write_terminal,
+            commands::resize_terminal,
+            commands::kill_terminal,
+            commands::start_recording,
+            commands::stop_recording,
+            commands::save_connection_stats
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// How long a dropped connection is tried to bring back when not configured
+const DEFAULT_RECONNECT_DEADLINE_SECS: u64 = 60;
+
+/// How connections behave once they are up, read from a settings file:
+///
+/// ```json
+/// { "reconnect_deadline_secs": 60 }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ConnectionSettings {
+    /// How long viewers and hosts try to bring back a dropped connection
+    pub reconnect_deadline_secs: u64,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            reconnect_deadline_secs: DEFAULT_RECONNECT_DEADLINE_SECS,
+        }
+    }
+}
+
+impl ConnectionSettings {
+    /// Reads the settings at `path`, the defaults if there is no such file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_settings_file_or_the_defaults() {
+        let dir =
+            std::env::temp_dir().join(format!("share-terminal-settings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("connection.json");
+
+        assert_eq!(
+            ConnectionSettings::load(&path),
+            Ok(ConnectionSettings::default())
+        );
+        std::fs::write(&path, r#"{ "reconnect_deadline_secs": 15 }"#).unwrap();
+        assert_eq!(
+            ConnectionSettings::load(&path)
+                .unwrap()
+                .reconnect_deadline_secs,
+            15
+        );
+        std::fs::write(&path, "{}").unwrap();
+        assert_eq!(
+            ConnectionSettings::load(&path),
+            Ok(ConnectionSettings::default())
+        );
+        std::fs::write(&path, "not json").unwrap();
+        assert!(ConnectionSettings::load(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+use super::session::TerminalSession;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+/// Keeps track of every terminal spawned by the app, keyed by terminal id.
+#[derive(Default)]
+pub struct TerminalManager {
+    sessions: Mutex<HashMap<String, TerminalSession>>,
+}
+
+impl TerminalManager {
+    pub fn start(
+        &self,
+        app: AppHandle,
+        cols: u16,
+        rows: u16,
+        shell: Option<String>,
+    ) -> Result<String, String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let session = TerminalSession::spawn(app, id.clone(), cols, rows, shell)?;
+        self.sessions.lock().unwrap().insert(id.clone(), session);
+        Ok(id)
+    }
+
+    pub fn write(&self, id: &str, data: &str) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(id)
+            .ok_or_else(|| format!("Unknown terminal: {}", id))?;
+        session.write(data.as_bytes())
+    }
+
+    pub fn resize(&self, id: &str, cols: u16, rows: u16) -> Result<(), String> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get(id)
+            .ok_or_else(|| format!("Unknown terminal: {}", id))?;
+        session.resize(cols, rows)
+    }
+
+    pub fn kill(&self, id: &str) -> Result<(), String> {
+        let mut session = self
+            .sessions
+            .lock()
+            .unwrap()
+            .remove(id)
+            .ok_or_else(|| format!("Unknown terminal: {}", id))?;
+        session.kill()
+    }
+
+    /// Starts recording a terminal to a new `.cast` file under the app data
+    /// directory and returns its path.
+    pub fn start_recording(&self, app: &AppHandle, id: &str) -> Result<String, String> {
+        let dir = app
+            .path()
+            .app_data_dir()
+            .map_err(|e| format!("Failed to resolve app data directory: {}", e))?
+            .join("recordings");
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let path = dir.join(format!("{}-{}.cast", timestamp, id));
+
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get(id)
+            .ok_or_else(|| format!("Unknown terminal: {}", id))?;
+        session.start_recording(&path)?;
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    /// Stops recording a terminal and returns the path of the recording.
+    pub fn stop_recording(&self, id: &str) -> Result<String, String> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get(id)
+            .ok_or_else(|| format!("Unknown terminal: {}", id))?;
+        let path = session.stop_recording()?;
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    /// Forgets a terminal whose shell has already exited.
+    pub(crate) fn remove(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+}
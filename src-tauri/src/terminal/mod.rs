@@ -0,0 +1,15 @@
+#[cfg(feature = "gui")]
+pub mod manager;
+pub mod recording;
+pub mod session;
+
+#[cfg(feature = "gui")]
+pub use manager::TerminalManager;
+
+// Events emitted to the webview while a terminal is running
+#[cfg(feature = "gui")]
+pub const EVENT_TERMINAL_OUTPUT: &str = "terminal-output";
+#[cfg(feature = "gui")]
+pub const EVENT_TERMINAL_EXIT: &str = "terminal-exit";
+#[cfg(feature = "gui")]
+pub const EVENT_TERMINAL_RECORDING_ERROR: &str = "terminal-recording-error";
@@ -0,0 +1,154 @@
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Writes a terminal session to an asciicast v2 file
+/// (https://docs.asciinema.org/manual/asciicast/v2/).
+///
+/// The first line is the header, every following line is an
+/// `[elapsed_seconds, code, data]` event.
+pub struct Recorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, cols: u16, rows: u16) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create recordings directory: {}", e))?;
+        }
+        let file =
+            File::create(path).map_err(|e| format!("Failed to create recording file: {}", e))?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let header = json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": timestamp,
+            "env": { "TERM": "xterm-256color" },
+        });
+
+        let mut recorder = Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            started: Instant::now(),
+        };
+        recorder.write_line(&header.to_string())?;
+        Ok(recorder)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn output(&mut self, data: &str) -> Result<(), String> {
+        self.event("o", data)
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<(), String> {
+        self.event("r", &format!("{}x{}", cols, rows))
+    }
+
+    fn event(&mut self, code: &str, data: &str) -> Result<(), String> {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let line = json!([elapsed, code, data]).to_string();
+        self.write_line(&line)
+    }
+
+    // Flushed line by line so a crash still leaves a playable file behind
+    fn write_line(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.writer, "{}", line)
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| format!("Failed to write recording: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn recording_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("share-terminal-{}", std::process::id()))
+            .join(format!("{}.cast", name))
+    }
+
+    fn read_lines(path: &Path) -> Vec<Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn starts_with_an_asciicast_header() {
+        let path = recording_path("header");
+        let recorder = Recorder::create(&path, 120, 40).unwrap();
+        assert_eq!(recorder.path(), path);
+
+        let lines = read_lines(&path);
+        assert_eq!(lines.len(), 1);
+        let header = &lines[0];
+        assert_eq!(header["version"], 2);
+        assert_eq!(header["width"], 120);
+        assert_eq!(header["height"], 40);
+        assert!(header["timestamp"].as_u64().unwrap() > 0);
+        assert_eq!(header["env"]["TERM"], "xterm-256color");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn writes_output_and_resize_events() {
+        let path = recording_path("events");
+        let mut recorder = Recorder::create(&path, 80, 24).unwrap();
+        recorder.output("hello \"world\"\r\n").unwrap();
+        recorder.resize(100, 30).unwrap();
+        recorder.output("\x1b[1mbold").unwrap();
+
+        // Each event is flushed as it's written, before the recorder is dropped
+        let lines = read_lines(&path);
+        assert_eq!(lines.len(), 4);
+        let events: Vec<(&str, &str)> = lines[1..]
+            .iter()
+            .map(|event| (event[1].as_str().unwrap(), event[2].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("o", "hello \"world\"\r\n"),
+                ("r", "100x30"),
+                ("o", "\x1b[1mbold")
+            ]
+        );
+
+        let times: Vec<f64> = lines[1..]
+            .iter()
+            .map(|event| event[0].as_f64().unwrap())
+            .collect();
+        assert!(times[0] >= 0.0);
+        assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+        drop(recorder);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn creates_the_recordings_directory() {
+        let path = recording_path("nested")
+            .with_file_name("nested")
+            .join("deeper")
+            .join("session.cast");
+        Recorder::create(&path, 80, 24).unwrap();
+        assert!(path.exists());
+        fs::remove_dir_all(path.parent().unwrap().parent().unwrap()).unwrap();
+    }
+}
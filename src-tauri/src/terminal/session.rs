@@ -0,0 +1,292 @@
+use super::recording::Recorder;
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+#[cfg(feature = "gui")]
+use {
+    super::{EVENT_TERMINAL_EXIT, EVENT_TERMINAL_OUTPUT, EVENT_TERMINAL_RECORDING_ERROR},
+    serde::Serialize,
+    std::path::PathBuf,
+    tauri::{AppHandle, Emitter, Manager},
+};
+
+#[cfg(feature = "gui")]
+#[derive(Serialize, Clone)]
+pub struct TerminalOutput {
+    pub id: String,
+    pub data: String,
+}
+
+#[cfg(feature = "gui")]
+#[derive(Serialize, Clone)]
+pub struct TerminalExit {
+    pub id: String,
+    pub code: Option<u32>,
+}
+
+/// A recording that could not be written any more, and was stopped.
+#[cfg(feature = "gui")]
+#[derive(Serialize, Clone)]
+pub struct TerminalRecordingError {
+    pub id: String,
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// A shell running inside a pseudo-terminal.
+pub struct TerminalSession {
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    // Shared with the reader thread, which appends output while a recording is running
+    recorder: Arc<Mutex<Option<Recorder>>>,
+}
+
+impl TerminalSession {
+    /// Spawns `shell` (or the user's default shell) and starts streaming its
+    /// output to the webview as `terminal-output` events. A recording that
+    /// fails to write is reported as a `terminal-recording-error` event.
+    #[cfg(feature = "gui")]
+    pub fn spawn(
+        app: AppHandle,
+        id: String,
+        cols: u16,
+        rows: u16,
+        shell: Option<String>,
+    ) -> Result<Self, String> {
+        let output_app = app.clone();
+        let output_id = id.clone();
+        let recording_app = app.clone();
+        let recording_id = id.clone();
+        Self::spawn_with(
+            cols,
+            rows,
+            shell,
+            move |data| {
+                let _ = output_app.emit(
+                    EVENT_TERMINAL_OUTPUT,
+                    TerminalOutput {
+                        id: output_id.clone(),
+                        data,
+                    },
+                );
+            },
+            move |path, error| {
+                let _ = recording_app.emit(
+                    EVENT_TERMINAL_RECORDING_ERROR,
+                    TerminalRecordingError {
+                        id: recording_id.clone(),
+                        path: path.to_path_buf(),
+                        error,
+                    },
+                );
+            },
+            move |code| {
+                app.state::<super::TerminalManager>().remove(&id);
+                let _ = app.emit(EVENT_TERMINAL_EXIT, TerminalExit { id, code });
+            },
+        )
+    }
+
+    /// Spawns `shell` (or the user's default shell), handing its output to
+    /// `on_output` and its exit code to `on_exit`, both on a reader thread.
+    /// A recording that fails to write is stopped and handed to
+    /// `on_recording_error` with the error.
+    pub fn spawn_with(
+        cols: u16,
+        rows: u16,
+        shell: Option<String>,
+        mut on_output: impl FnMut(String) + Send + 'static,
+        mut on_recording_error: impl FnMut(&Path, String) + Send + 'static,
+        on_exit: impl FnOnce(Option<u32>) + Send + 'static,
+    ) -> Result<Self, String> {
+        let pair = native_pty_system()
+            .openpty(pty_size(cols, rows))
+            .map_err(|e| format!("Failed to open pty: {}", e))?;
+
+        let mut cmd = match shell {
+            Some(shell) => CommandBuilder::new(shell),
+            None => CommandBuilder::new_default_prog(),
+        };
+        cmd.env("TERM", "xterm-256color");
+        cmd.env("COLORTERM", "truecolor");
+
+        let mut child = pair
+            .slave
+            .spawn_command(cmd)
+            .map_err(|e| format!("Failed to spawn shell: {}", e))?;
+        // The slave end must be closed here so the reader sees EOF once the shell exits
+        drop(pair.slave);
+
+        let killer = child.clone_killer();
+        let mut reader = pair
+            .master
+            .try_clone_reader()
+            .map_err(|e| format!("Failed to open pty reader: {}", e))?;
+        let writer = pair
+            .master
+            .take_writer()
+            .map_err(|e| format!("Failed to open pty writer: {}", e))?;
+
+        let recorder = Arc::new(Mutex::new(None::<Recorder>));
+
+        let reader_recorder = recorder.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 8192];
+            let mut pending = Vec::new();
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        let data = decode_utf8_chunk(&mut pending, &buf[..n]);
+                        if data.is_empty() {
+                            continue;
+                        }
+                        let mut recorder = reader_recorder.lock().unwrap();
+                        if let Some(Err(e)) = recorder.as_mut().map(|r| r.output(&data)) {
+                            // Every later chunk would fail the same way
+                            if let Some(failed) = recorder.take() {
+                                on_recording_error(failed.path(), e);
+                            }
+                        }
+                        drop(recorder);
+                        on_output(data);
+                    }
+                }
+            }
+
+            let code = child.wait().ok().map(|status| status.exit_code());
+            on_exit(code);
+        });
+
+        Ok(Self {
+            master: pair.master,
+            writer,
+            killer,
+            recorder,
+        })
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        self.writer
+            .write_all(data)
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| format!("Failed to write to terminal: {}", e))
+    }
+
+    pub fn resize(&self, cols: u16, rows: u16) -> Result<(), String> {
+        self.master
+            .resize(pty_size(cols, rows))
+            .map_err(|e| format!("Failed to resize terminal: {}", e))?;
+        if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+            recorder.resize(cols, rows)?;
+        }
+        Ok(())
+    }
+
+    /// Starts writing everything the shell prints from now on to `path`.
+    pub fn start_recording(&self, path: &Path) -> Result<(), String> {
+        let mut recorder = self.recorder.lock().unwrap();
+        if recorder.is_some() {
+            return Err("Terminal is already being recorded".to_string());
+        }
+        let size = self
+            .master
+            .get_size()
+            .map_err(|e| format!("Failed to read terminal size: {}", e))?;
+        *recorder = Some(Recorder::create(path, size.cols, size.rows)?);
+        Ok(())
+    }
+
+    /// Stops the running recording and returns the path of the finished file.
+    #[cfg(feature = "gui")]
+    pub fn stop_recording(&self) -> Result<PathBuf, String> {
+        self.recorder
+            .lock()
+            .unwrap()
+            .take()
+            .map(|recorder| recorder.path().to_path_buf())
+            .ok_or_else(|| "Terminal is not being recorded".to_string())
+    }
+
+    pub fn kill(&mut self) -> Result<(), String> {
+        self.killer
+            .kill()
+            .map_err(|e| format!("Failed to kill terminal: {}", e))
+    }
+}
+
+fn pty_size(cols: u16, rows: u16) -> PtySize {
+    PtySize {
+        rows: rows.max(1),
+        cols: cols.max(1),
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+/// Decodes as much of `chunk` as possible, keeping an incomplete trailing
+/// UTF-8 sequence in `pending` so it can be completed by the next read.
+fn decode_utf8_chunk(pending: &mut Vec<u8>, chunk: &[u8]) -> String {
+    pending.extend_from_slice(chunk);
+    let valid_up_to = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        // Invalid bytes in the middle of the stream, decode lossily and move on
+        Err(_) => pending.len(),
+    };
+    let rest = pending.split_off(valid_up_to);
+    let data = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_complete_chunks() {
+        let mut pending = Vec::new();
+        assert_eq!(
+            decode_utf8_chunk(&mut pending, "héllo ✓".as_bytes()),
+            "héllo ✓"
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn keeps_a_character_split_across_chunks() {
+        let bytes = "a✓b".as_bytes();
+        let mut pending = Vec::new();
+        // '✓' is three bytes, cut after the first and second of them
+        assert_eq!(decode_utf8_chunk(&mut pending, &bytes[..2]), "a");
+        assert_eq!(pending, &bytes[1..2]);
+        assert_eq!(decode_utf8_chunk(&mut pending, &bytes[2..3]), "");
+        assert_eq!(decode_utf8_chunk(&mut pending, &bytes[3..]), "✓b");
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn decodes_every_split_of_a_multibyte_string() {
+        let text = "日本語 🦀 ok";
+        let bytes = text.as_bytes();
+        for split in 0..=bytes.len() {
+            let mut pending = Vec::new();
+            let mut decoded = decode_utf8_chunk(&mut pending, &bytes[..split]);
+            decoded += &decode_utf8_chunk(&mut pending, &bytes[split..]);
+            assert_eq!(decoded, text, "split at {}", split);
+            assert!(pending.is_empty());
+        }
+    }
+
+    #[test]
+    fn replaces_invalid_bytes_and_carries_on() {
+        let mut pending = Vec::new();
+        assert_eq!(decode_utf8_chunk(&mut pending, b"a\xffb"), "a\u{fffd}b");
+        assert!(pending.is_empty());
+        assert_eq!(decode_utf8_chunk(&mut pending, b"c"), "c");
+    }
+}
@@ -1,4 +1,5 @@
 use crate::constants::{STORAGE_KEY_THEME, THEME_DARK, THEME_LIGHT};
+use crate::services::{ice, settings, share_requests, signal_bus};
 use crate::utils::auth::get_auth_token;
 use web_sys::window;
 use yew::prelude::*;
@@ -20,6 +21,15 @@ pub fn app() -> Html {
         })
     };
 
+    // Signals arrive whichever page is open, requests are collected from the start
+    use_effect_with((), |_| {
+        signal_bus::start();
+        share_requests::start();
+        ice::start();
+        settings::start();
+        || ()
+    });
+
     let initial_route = if get_auth_token().is_some() {
         Route::Access
     } else {
@@ -0,0 +1,247 @@
+use crate::services::api::{get_connected_users, save_connection_stats};
+use crate::services::stats::{read_stats, summarize, StatsSample};
+use crate::utils::auth::get_auth_token;
+use gloo::timers::callback::Interval;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use web_sys::RtcPeerConnection;
+use yew::prelude::*;
+
+/// How often the stats are read
+const STATS_INTERVAL_MS: u32 = 1000;
+/// Samples kept for an export, five minutes' worth
+const STATS_HISTORY: usize = 300;
+
+#[derive(Properties, PartialEq)]
+pub struct ConnectionStatsProps {
+    /// The connection with each peer by user id, none until the page has set one up
+    pub peer_connections: Vec<(String, RtcPeerConnection)>,
+}
+
+/// What was sampled of the connection with one peer
+#[derive(Default)]
+struct PeerStats {
+    samples: VecDeque<StatsSample>,
+    // The last raw report, exported along with the samples
+    last_report: Vec<Value>,
+}
+
+/// Live numbers of every peer connection in an overlay, sampled in the
+/// background so an export also covers what happened before it was opened.
+#[function_component(ConnectionStats)]
+pub fn connection_stats(props: &ConnectionStatsProps) -> Html {
+    let open = use_state(|| false);
+    let stats = use_mut_ref(HashMap::<String, PeerStats>::new);
+    let names = use_state(HashMap::<String, String>::new);
+    // Where the last export went, or why it didn't
+    let export_notice = use_state(|| Option::<String>::None);
+    let force_update = use_force_update();
+
+    // The interval reads whichever connections the page has by then
+    let peer_connections = use_mut_ref(Vec::new);
+    *peer_connections.borrow_mut() = props.peer_connections.clone();
+
+    {
+        let names = names.clone();
+        use_effect_with((), move |_| {
+            wasm_bindgen_futures::spawn_local(async move {
+                let Some(token) = get_auth_token() else {
+                    return;
+                };
+                match get_connected_users(token).await {
+                    Ok(users) => {
+                        names.set(users.into_iter().map(|user| (user.id, user.name)).collect())
+                    }
+                    Err(e) => log::error!("Failed to fetch connected users: {}", e),
+                }
+            });
+            || ()
+        });
+    }
+
+    use_effect_with((), {
+        let stats = stats.clone();
+        let force_update = force_update.clone();
+
+        move |_| {
+            let interval = Interval::new(STATS_INTERVAL_MS, move || {
+                let peers = peer_connections.borrow().clone();
+                // Peers that left take their history with them
+                stats
+                    .borrow_mut()
+                    .retain(|user_id, _| peers.iter().any(|(id, _)| id == user_id));
+                for (user_id, pc) in peers {
+                    let stats = stats.clone();
+                    let force_update = force_update.clone();
+                    wasm_bindgen_futures::spawn_local(async move {
+                        let report = match read_stats(&pc).await {
+                            Ok(report) => report,
+                            Err(e) => {
+                                log::warn!("{}", e);
+                                return;
+                            }
+                        };
+                        {
+                            let mut stats = stats.borrow_mut();
+                            let peer = stats.entry(user_id).or_default();
+                            let sample = summarize(&report, peer.samples.back());
+                            peer.samples.push_back(sample);
+                            if peer.samples.len() > STATS_HISTORY {
+                                peer.samples.pop_front();
+                            }
+                            peer.last_report = report;
+                        }
+                        force_update.force_update();
+                    });
+                }
+            });
+
+            move || drop(interval)
+        }
+    });
+
+    let name_of = {
+        let names = (*names).clone();
+        move |user_id: &str| match names.get(user_id) {
+            Some(name) => name.clone(),
+            None if user_id.is_empty() => "Peer".to_string(),
+            None => user_id.to_string(),
+        }
+    };
+
+    let on_toggle = {
+        let open = open.clone();
+        Callback::from(move |_| open.set(!*open))
+    };
+
+    let on_export = {
+        let stats = stats.clone();
+        let peer_ids: Vec<String> = props
+            .peer_connections
+            .iter()
+            .map(|(user_id, _)| user_id.clone())
+            .collect();
+        let name_of = name_of.clone();
+        let export_notice = export_notice.clone();
+
+        Callback::from(move |_| {
+            let user_agent = web_sys::window()
+                .and_then(|window| window.navigator().user_agent().ok())
+                .unwrap_or_default();
+            let peers: Vec<Value> = {
+                let stats = stats.borrow();
+                peer_ids
+                    .iter()
+                    .map(|user_id| {
+                        let peer = stats.get(user_id);
+                        serde_json::json!({
+                            "userId": user_id,
+                            "name": name_of(user_id),
+                            "samples": peer.map(|peer| &peer.samples),
+                            "report": peer.map(|peer| &peer.last_report),
+                        })
+                    })
+                    .collect()
+            };
+            let export = serde_json::json!({
+                "userAgent": user_agent,
+                "peers": peers,
+            });
+            let export_notice = export_notice.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                let text = match serde_json::to_string_pretty(&export) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        export_notice.set(Some(format!("Failed to export stats: {}", e)));
+                        return;
+                    }
+                };
+                match save_connection_stats(text).await {
+                    Ok(path) => export_notice.set(Some(format!("Saved to {}", path))),
+                    Err(e) => {
+                        log::error!("{}", e);
+                        export_notice.set(Some(e));
+                    }
+                }
+            });
+        })
+    };
+
+    let tables: Vec<(String, Vec<(&str, String)>)> = {
+        let stats = stats.borrow();
+        props
+            .peer_connections
+            .iter()
+            .map(|(user_id, _)| {
+                let latest = stats
+                    .get(user_id)
+                    .and_then(|peer| peer.samples.back().cloned())
+                    .unwrap_or_default();
+                (name_of(user_id), rows(&latest))
+            })
+            .collect()
+    };
+
+    html! {
+        <div class="connection-stats">
+            <button class="connection-stats-toggle" onclick={on_toggle}>
+                {if *open { "Hide stats" } else { "Stats" }}
+            </button>
+            if *open {
+                <div class="connection-stats-panel">
+                    if tables.is_empty() {
+                        <p>{"Not connected"}</p>
+                    }
+                    { for tables.into_iter().map(|(name, rows)| html! {
+                        <table>
+                            <caption>{name}</caption>
+                            { for rows.into_iter().map(|(label, value)| html! {
+                                <tr>
+                                    <th>{label}</th>
+                                    <td>{value}</td>
+                                </tr>
+                            }) }
+                        </table>
+                    }) }
+                    <button onclick={on_export}>{"Export JSON"}</button>
+                    if let Some(notice) = &*export_notice {
+                        <p class="connection-stats-notice">{notice}</p>
+                    }
+                </div>
+            }
+        </div>
+    }
+}
+
+/// The overlay rows for one peer's latest sample.
+fn rows(latest: &StatsSample) -> Vec<(&'static str, String)> {
+    let route = match (&latest.local_candidate, &latest.remote_candidate) {
+        (Some(local), Some(remote)) => format!("{} ↔ {}", local, remote),
+        _ => "—".to_string(),
+    };
+    let resolution = latest
+        .resolution
+        .map(|(width, height)| format!("{}×{}", width, height))
+        .unwrap_or_else(|| "—".to_string());
+    vec![
+        ("Inbound", show(latest.inbound_kbps, "kbps")),
+        ("Outbound", show(latest.outbound_kbps, "kbps")),
+        ("Bandwidth", show(latest.available_outgoing_kbps, "kbps")),
+        ("Frame rate", show(latest.frames_per_second, "fps")),
+        ("Resolution", resolution),
+        ("Round trip", show(latest.rtt_ms, "ms")),
+        ("Jitter", show(latest.jitter_ms, "ms")),
+        ("Packet loss", show(latest.packet_loss_percent, "%")),
+        ("Route", route),
+    ]
+}
+
+/// `value` rounded for display, a dash if the browser didn't report it.
+fn show(value: Option<f64>, unit: &str) -> String {
+    match value {
+        Some(value) if value >= 10.0 => format!("{:.0} {}", value, unit),
+        Some(value) => format!("{:.1} {}", value, unit),
+        None => "—".to_string(),
+    }
+}
@@ -0,0 +1,77 @@
+use qrcode::render::svg;
+use qrcode::{EcLevel, QrCode};
+use web_sys::HtmlTextAreaElement;
+use yew::prelude::*;
+
+/// Our side of an offer/answer exchange done by hand.
+#[derive(Clone, PartialEq, Default)]
+pub enum LocalCode {
+    #[default]
+    None,
+    /// Waiting for every ICE candidate before the code can be made
+    Gathering,
+    Ready(String),
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ManualSignalProps {
+    pub local: LocalCode,
+    /// A code pasted from the other side
+    pub on_apply: Callback<String>,
+}
+
+/// Shows our connection code as text and QR code, and takes the other side's.
+#[function_component(ManualSignal)]
+pub fn manual_signal(props: &ManualSignalProps) -> Html {
+    let input_ref = use_node_ref();
+
+    let on_apply = {
+        let input_ref = input_ref.clone();
+        let on_apply = props.on_apply.clone();
+        Callback::from(move |_| {
+            if let Some(input) = input_ref.cast::<HtmlTextAreaElement>() {
+                let code = input.value();
+                if !code.trim().is_empty() {
+                    on_apply.emit(code);
+                    input.set_value("");
+                }
+            }
+        })
+    };
+    let select_all = Callback::from(|e: FocusEvent| {
+        if let Some(output) = e.target_dyn_into::<HtmlTextAreaElement>() {
+            output.select();
+        }
+    });
+
+    html! {
+        <div class="manual-signal">
+            { match &props.local {
+                LocalCode::None => html! {
+                    <p>{"Share your screen or terminal to get a code for the other side, or paste theirs below."}</p>
+                },
+                LocalCode::Gathering => html! { <p>{"Gathering connection details…"}</p> },
+                LocalCode::Ready(code) => html! {
+                    <div class="manual-signal-code">
+                        <p>{"Give this code to the other side:"}</p>
+                        if let Some(qr) = qr_svg(code) {
+                            <div class="manual-signal-qr">{qr}</div>
+                        }
+                        <textarea readonly=true value={code.clone()} onfocus={select_all}></textarea>
+                    </div>
+                },
+            } }
+            <div class="manual-signal-input">
+                <textarea ref={input_ref} placeholder="Paste the other side's code"></textarea>
+                <button onclick={on_apply}>{"Connect"}</button>
+            </div>
+        </div>
+    }
+}
+
+/// `code` as a QR code, if it fits in one.
+fn qr_svg(code: &str) -> Option<Html> {
+    let qr = QrCode::with_error_correction_level(code.as_bytes(), EcLevel::L).ok()?;
+    let image = qr.render::<svg::Color>().min_dimensions(240, 240).build();
+    Some(Html::from_html_unchecked(AttrValue::from(image)))
+}
@@ -2,4 +2,8 @@ pub mod access_layout;
 pub mod sidebar;
 pub mod topbar;
 pub mod pixel_art;
-pub mod canvas_topbar;
\ No newline at end of file
+pub mod canvas_topbar;
+pub mod connection_stats;
+pub mod manual_signal;
+pub mod quality_controls;
+pub mod watching_viewers;
\ No newline at end of file
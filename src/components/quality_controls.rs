@@ -0,0 +1,98 @@
+use crate::services::quality::{QualityPreset, VideoQuality};
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+
+const HEIGHTS: [(&str, Option<u32>); 4] = [
+    ("Any resolution", None),
+    ("1080p", Some(1080)),
+    ("720p", Some(720)),
+    ("480p", Some(480)),
+];
+const FRAME_RATES: [(&str, Option<u32>); 5] = [
+    ("Any frame rate", None),
+    ("60 fps", Some(60)),
+    ("30 fps", Some(30)),
+    ("15 fps", Some(15)),
+    ("5 fps", Some(5)),
+];
+const BITRATES: [(&str, Option<u32>); 5] = [
+    ("Any bitrate", None),
+    ("4 Mbps", Some(4000)),
+    ("2 Mbps", Some(2000)),
+    ("1 Mbps", Some(1000)),
+    ("500 kbps", Some(500)),
+];
+
+#[derive(Properties, PartialEq)]
+pub struct QualityControlsProps {
+    /// The viewer picked another preset or cap
+    pub on_change: Callback<VideoQuality>,
+}
+
+/// Lets a viewer ask the host for a preset or caps on its screen video.
+#[function_component(QualityControls)]
+pub fn quality_controls(props: &QualityControlsProps) -> Html {
+    let quality = use_state(VideoQuality::default);
+
+    // Applies what `change` does to the selected value, and tells the host
+    let on_select = |change: fn(&mut VideoQuality, &str)| {
+        let quality = quality.clone();
+        let on_change = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let Some(select) = e.target_dyn_into::<HtmlSelectElement>() else {
+                return;
+            };
+            let mut next = *quality;
+            change(&mut next, &select.value());
+            quality.set(next);
+            on_change.emit(next);
+        })
+    };
+    let on_preset = on_select(|quality, value| {
+        if let Some(preset) = value
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| QualityPreset::ALL.get(i))
+        {
+            quality.preset = *preset;
+        }
+    });
+    let on_height = on_select(|quality, value| quality.max_height = value.parse().ok());
+    let on_frame_rate = on_select(|quality, value| quality.max_frame_rate = value.parse().ok());
+    let on_bitrate = on_select(|quality, value| quality.max_bitrate_kbps = value.parse().ok());
+
+    html! {
+        <div class="quality-controls">
+            <select onchange={on_preset} title="Quality preset">
+                { for QualityPreset::ALL.iter().enumerate().map(|(i, preset)| html! {
+                    <option value={i.to_string()} selected={*preset == quality.preset}>
+                        {preset.label()}
+                    </option>
+                }) }
+            </select>
+            { cap_select(&HEIGHTS, quality.max_height, on_height) }
+            { cap_select(&FRAME_RATES, quality.max_frame_rate, on_frame_rate) }
+            { cap_select(&BITRATES, quality.max_bitrate_kbps, on_bitrate) }
+        </div>
+    }
+}
+
+/// A select for one cap, valued with the cap's number or empty for none.
+fn cap_select(
+    options: &[(&'static str, Option<u32>)],
+    current: Option<u32>,
+    onchange: Callback<Event>,
+) -> Html {
+    html! {
+        <select {onchange}>
+            { for options.iter().map(|(label, value)| html! {
+                <option
+                    value={value.map(|v| v.to_string()).unwrap_or_default()}
+                    selected={*value == current}
+                >
+                    {*label}
+                </option>
+            }) }
+        </select>
+    }
+}
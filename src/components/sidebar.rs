@@ -1,4 +1,5 @@
 use crate::router::router::Route;
+use crate::services::share_requests::use_share_requests;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
@@ -10,6 +11,7 @@ pub struct SidebarProps {
 
 #[function_component(Sidebar)]
 pub fn sidebar(props: &SidebarProps) -> Html {
+    let pending_requests = use_share_requests().len();
     let sidebar_class = if props.sidebar_open {
         "sidebar active"
     } else {
@@ -29,7 +31,12 @@ pub fn sidebar(props: &SidebarProps) -> Html {
                     <Link<Route> to={Route::Viewers} classes="nav-item">{"Viewers"}</Link<Route>>
                     <Link<Route> to={Route::IncomingRequests} classes="nav-item">{"Incoming Requests"}</Link<Route>>
                     <Link<Route> to={Route::OutgoingRequests} classes="nav-item">{"Outgoing Requests"}</Link<Route>>
-                    <Link<Route> to={Route::ScreenShareRequests} classes="nav-item">{"Screen Share Requests"}</Link<Route>>
+                    <Link<Route> to={Route::ScreenShareRequests} classes="nav-item">
+                        {"Screen Share Requests"}
+                        if pending_requests > 0 {
+                            <span class="nav-badge">{pending_requests}</span>
+                        }
+                    </Link<Route>>
                 </div>
             </nav>
 
@@ -0,0 +1,115 @@
+use crate::services::api::get_connected_users;
+use crate::services::share_requests::use_share_requests;
+use crate::utils::auth::get_auth_token;
+use crate::utils::share_access::ShareAccess;
+use std::collections::HashMap;
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+
+/// A viewer the host has a peer connection with.
+#[derive(Clone, PartialEq)]
+pub struct WatchingViewer {
+    pub user_id: String,
+    /// The connection is up, as opposed to still being set up or brought back
+    pub connected: bool,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct WatchingViewersProps {
+    pub viewers: Vec<WatchingViewer>,
+    /// Hang up on a viewer
+    pub on_kick: Callback<String>,
+    /// Let a viewer with a pending request in, with the access it gets
+    pub on_let_in: Callback<(String, ShareAccess)>,
+}
+
+/// Host: who is watching, with a kick button each, and who asked to join.
+#[function_component(WatchingViewers)]
+pub fn watching_viewers(props: &WatchingViewersProps) -> Html {
+    let requests = use_share_requests();
+    let names = use_state(HashMap::<String, String>::new);
+
+    {
+        let names = names.clone();
+        use_effect_with((), move |_| {
+            spawn_local(async move {
+                let Some(token) = get_auth_token() else {
+                    return;
+                };
+                match get_connected_users(token).await {
+                    Ok(users) => {
+                        names.set(users.into_iter().map(|user| (user.id, user.name)).collect())
+                    }
+                    Err(e) => log::error!("Failed to fetch connected users: {}", e),
+                }
+            });
+            || ()
+        });
+    }
+    let name_of = |user_id: &str| {
+        names
+            .get(user_id)
+            .cloned()
+            .unwrap_or_else(|| user_id.to_string())
+    };
+
+    html! {
+        <div class="watching-viewers">
+            <h3>{"Watching"}</h3>
+            if props.viewers.is_empty() {
+                <p class="hint">{"No one is watching."}</p>
+            }
+            <ul>
+                { for props.viewers.iter().map(|viewer| {
+                    let user_id = viewer.user_id.clone();
+                    let on_kick = props.on_kick.clone();
+                    html! {
+                        <li>
+                            <span class="viewer-name">{name_of(&viewer.user_id)}</span>
+                            <span class="viewer-state">
+                                {if viewer.connected { "Watching" } else { "Connecting…" }}
+                            </span>
+                            <button
+                                class="btn-reject"
+                                onclick={Callback::from(move |_| on_kick.emit(user_id.clone()))}
+                                title="Hang up on this viewer"
+                            >
+                                {"Kick"}
+                            </button>
+                        </li>
+                    }
+                }) }
+            </ul>
+            if !requests.is_empty() {
+                <h3>{"Waiting to join"}</h3>
+                <ul>
+                    { for requests.iter().map(|req| {
+                        let user_id_view = req.from_user_id.clone();
+                        let user_id_type = req.from_user_id.clone();
+                        let on_let_in_view = props.on_let_in.clone();
+                        let on_let_in_type = props.on_let_in.clone();
+                        html! {
+                            <li>
+                                <span class="viewer-name">{name_of(&req.from_user_id)}</span>
+                                <button
+                                    class="btn-accept"
+                                    onclick={Callback::from(move |_| on_let_in_view.emit((user_id_view.clone(), ShareAccess::ReadOnly)))}
+                                    title="Let in; the viewer can watch but not type"
+                                >
+                                    {"✓ View only"}
+                                </button>
+                                <button
+                                    class="btn-accept"
+                                    onclick={Callback::from(move |_| on_let_in_type.emit((user_id_type.clone(), ShareAccess::Interactive)))}
+                                    title="Let in; the viewer can also type into shared terminals"
+                                >
+                                    {"✓ Can type"}
+                                </button>
+                            </li>
+                        }
+                    }) }
+                </ul>
+            }
+        </div>
+    }
+}
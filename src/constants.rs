@@ -2,6 +2,8 @@
 pub const STORAGE_KEY_AUTH_TOKEN: &str = "auth_token";
 pub const STORAGE_KEY_THEME: &str = "theme";
 pub const STORAGE_KEY_USER_EMAIL: &str = "user_email";
+pub const STORAGE_KEY_SHARE_ACCESS: &str = "share_access";
+pub const STORAGE_KEY_SHARE_SESSIONS: &str = "share_sessions";
 
 // Theme values
 pub const THEME_DARK: &str = "dark";
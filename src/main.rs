@@ -5,6 +5,7 @@ pub mod constants;
 pub mod pages;
 pub mod router;
 pub mod services;
+pub mod terminal;
 pub mod utils;
 
 fn main() {
@@ -1,217 +1,476 @@
 use crate::components::canvas_topbar::CanvasTopBar;
-use crate::services::api::{fetch_inbox, send_signal, SignalMessage};
-use crate::utils::auth::get_auth_token;
-use gloo::timers::callback::Interval;
+use crate::components::connection_stats::ConnectionStats;
+use crate::components::manual_signal::{LocalCode, ManualSignal};
+use crate::components::quality_controls::QualityControls;
+use crate::components::watching_viewers::{WatchingViewer, WatchingViewers};
+use crate::services::api::{
+    send_signal, start_recording, stop_recording, IceCandidate, Signal, SignalMessage,
+    TerminalRecordingError,
+};
+use crate::services::events::listen;
+use crate::services::quality::{self, QualityControl, VideoQuality};
+use crate::services::{ice, settings, share_requests, signal_bus};
+use crate::terminal::channel::{on_frame, send_frame};
+use crate::terminal::control::{Control, CONTROL_TIME_LIMITS};
+use crate::terminal::host::TerminalHost;
+use crate::terminal::protocol::{ChannelId, TerminalFrame, TERMINAL_CHANNEL_LABEL};
+use crate::terminal::tabs::TerminalTabs;
+use crate::utils::auth::{get_auth_token, get_user_email};
+use crate::utils::share_access::{get_share_access, ShareAccess};
+use crate::utils::share_session::{clear_approved_session, get_approved_session, new_session_id};
+use gloo::timers::callback::{Interval, Timeout};
 use js_sys::JSON;
-use serde_json::Value;
+use share_protocol::ManualDescription;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::future::Future;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsValue;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
-    HtmlVideoElement, MediaStream, MediaStreamTrack, RtcConfiguration, RtcIceCandidate,
-    RtcIceCandidateInit, RtcPeerConnection, RtcPeerConnectionIceEvent, RtcSdpType,
-    RtcSessionDescription, RtcSessionDescriptionInit, RtcTrackEvent,
+    ClipboardEvent, HtmlElement, HtmlVideoElement, MediaStream, MediaStreamTrack, RtcDataChannel,
+    RtcDataChannelEvent, RtcIceCandidate, RtcIceCandidateInit, RtcIceGatheringState,
+    RtcOfferOptions, RtcPeerConnection, RtcPeerConnectionIceEvent, RtcPeerConnectionState,
+    RtcRtpSender, RtcSdpType, RtcSessionDescription, RtcSessionDescriptionInit, RtcTrackEvent,
 };
 use yew::prelude::*;
-use yew_router::prelude::use_navigator;
+use yew_router::prelude::{use_navigator, Navigator};
+
+/// Local ICE candidates gathered within this window go out as one signal
+const ICE_BATCH_WINDOW_MS: u32 = 200;
+/// A manual code is made with whatever candidates were found by then
+const MANUAL_GATHER_TIMEOUT_MS: u32 = 5000;
+/// A disconnected connection often comes back on its own within this
+const DISCONNECTED_GRACE_MS: u32 = 3000;
+/// An ICE restart or new peer connection that hasn't connected by then is replaced
+const RECONNECT_ATTEMPT_TIMEOUT_MS: u32 = 10_000;
+/// How often the host fits its screen video to the viewer's request again, for auto mode
+const QUALITY_CHECK_INTERVAL_MS: u32 = 5000;
 
 #[derive(Properties, PartialEq)]
 pub struct CanvasPageProps {
     pub id: String,
     pub dark_mode: bool,
     pub toggle_theme: Callback<()>,
+    /// Offer and answer are passed along by hand instead of through the relay
+    #[prop_or_default]
+    pub manual: bool,
+    /// We share with the peer, whose request we approved, rather than watch it
+    #[prop_or_default]
+    pub host: bool,
+}
+
+/// What a peer connection's handlers work with, kept so that a replacement
+/// for a dropped connection is set up just like the first one.
+#[derive(Clone)]
+struct PeerContext {
+    user_id: String,
+    session_id: String,
+    manual: bool,
+    navigator: Navigator,
+    peer_connection: Rc<RefCell<Option<Rc<RefCell<RtcPeerConnection>>>>>,
+    remote_video_ref: NodeRef,
+    local_stream: Rc<RefCell<Option<MediaStream>>>,
+    video_quality: Rc<RefCell<QualityControl>>,
+    data_channel: Rc<RefCell<Option<RtcDataChannel>>>,
+    terminal_host: Rc<RefCell<Option<TerminalHost>>>,
+    terminal_tabs: Rc<RefCell<TerminalTabs>>,
+    terminal_area_ref: NodeRef,
+    reported_size: Rc<RefCell<Option<(u16, u16)>>>,
+    viewer_access: Rc<RefCell<ShareAccess>>,
+    viewer_control: Rc<RefCell<Control>>,
+    control_requested: UseStateHandle<bool>,
+    force_update: UseForceUpdateHandle,
+    pending_candidates: Rc<RefCell<Vec<IceCandidate>>>,
+    // A signal this peer never got, or why we lost it, shown until dismissed
+    error: Rc<RefCell<Option<String>>>,
+    reconnect: Rc<RefCell<Reconnect>>,
+}
+
+impl PeerContext {
+    /// The context of another peer of the same page: a connection, terminal
+    /// channel, video quality, error and reconnection of its own, the rest shared.
+    fn for_peer(&self, user_id: &str) -> Self {
+        PeerContext {
+            user_id: user_id.to_string(),
+            peer_connection: Rc::default(),
+            video_quality: Rc::default(),
+            data_channel: Rc::default(),
+            pending_candidates: Rc::default(),
+            error: Rc::default(),
+            reconnect: Rc::default(),
+            ..self.clone()
+        }
+    }
+
+    fn set_error(&self, error: Option<String>) {
+        *self.error.borrow_mut() = error;
+        self.force_update.force_update();
+    }
+
+    fn is_reconnecting(&self) -> bool {
+        self.reconnect.borrow().deadline.is_some()
+    }
+}
+
+/// The page's peers, each with a connection of its own: every viewer let in
+/// while we host, all fed from the one capture and terminal host, or just
+/// the host while we view.
+#[derive(Default)]
+struct PeerSessions {
+    peers: Vec<PeerContext>,
+}
+
+impl PeerSessions {
+    fn get(&self, user_id: &str) -> Option<PeerContext> {
+        self.peers
+            .iter()
+            .find(|ctx| ctx.user_id == user_id)
+            .cloned()
+    }
+
+    /// Every peer, cloned so the sessions aren't borrowed while they are served.
+    fn all(&self) -> Vec<PeerContext> {
+        self.peers.clone()
+    }
+
+    /// Adds a peer, in place of any earlier session with the same user.
+    fn insert(&mut self, ctx: PeerContext) {
+        self.peers.retain(|peer| peer.user_id != ctx.user_id);
+        self.peers.push(ctx);
+    }
+
+    fn remove(&mut self, user_id: &str) -> Option<PeerContext> {
+        let index = self.peers.iter().position(|ctx| ctx.user_id == user_id)?;
+        Some(self.peers.remove(index))
+    }
+}
+
+/// A dropped connection being brought back: an ICE restart first, then new
+/// peer connections, until the deadline from the ICE settings passes.
+#[derive(Default)]
+struct Reconnect {
+    // Gives up when it fires, set for as long as we are reconnecting
+    deadline: Option<Timeout>,
+    // The offering side's next attempt
+    attempt: Option<Timeout>,
+    // ICE was restarted on the current connection already
+    restarted: bool,
+}
+
+impl Reconnect {
+    /// Runs `attempt` in `delay_ms`, instead of whatever attempt was due.
+    fn schedule<F>(&mut self, ctx: &PeerContext, delay_ms: u32, attempt: fn(PeerContext) -> F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let ctx = ctx.clone();
+        self.attempt = Some(Timeout::new(delay_ms, move || {
+            // Not from inside the timeout, which the attempt may replace
+            wasm_bindgen_futures::spawn_local(attempt(ctx));
+        }));
+    }
 }
 
 #[function_component(CanvasPage)]
 pub fn canvas_page(props: &CanvasPageProps) -> Html {
     let theme_class = if props.dark_mode { "dark-theme" } else { "" };
-    let peer_connection: UseStateHandle<Option<Rc<RefCell<RtcPeerConnection>>>> =
-        use_state(|| None);
+    // Replaced by a new one when the connection drops for good
+    let peer_connection = use_mut_ref(|| Option::<Rc<RefCell<RtcPeerConnection>>>::None);
     let remote_video_ref = use_node_ref();
     let navigator = use_navigator().unwrap();
+    let data_channel = use_mut_ref(|| Option::<RtcDataChannel>::None);
+    let terminal_host = use_mut_ref(|| Option::<TerminalHost>::None);
+    let canvas_ref = use_node_ref();
+    // Area the remote terminal may fill, and the cols/rows we last told the host it fits
+    let terminal_area_ref = use_node_ref();
+    let reported_size = use_mut_ref(|| Option::<(u16, u16)>::None);
+    let terminal_tabs = use_mut_ref({
+        let canvas_ref = canvas_ref.clone();
+        move || TerminalTabs::new(canvas_ref)
+    });
+    // Tab the host has selected for recording and closing
+    let host_channel = use_state(|| Option::<ChannelId>::None);
+    let force_update = use_force_update();
+    // What the host lets us do in its terminal, as told by the approval signal and the host itself
+    let viewer_access = use_mut_ref(ShareAccess::default);
+    // Path of the .cast file for every shared tab being recorded
+    let recording = use_state(HashMap::<ChannelId, String>::new);
+    // The last recording the backend stopped because it couldn't write to it
+    let recording_error = use_state(|| Option::<TerminalRecordingError>::None);
+    // Who drives the host's terminals, as last told by the host
+    let viewer_control = use_mut_ref(Control::default);
+    // Viewer: waiting for the host to answer our control request, or why it didn't work out
+    let control_requested = use_state(|| false);
+    let control_notice = use_state(|| Option::<String>::None);
+    // Host: the viewer that asked for control, until we answer
+    let control_request = use_state(|| Option::<String>::None);
+    // Session of this mount's screen share request, signals for any other one are stale
+    let session_id = use_state(new_session_id);
+    // Peer candidates that arrived before its description, added once that is set
+    let pending_candidates = use_mut_ref(Vec::<IceCandidate>::new);
+    // Something that went wrong besides a peer's own errors, shown until dismissed
+    let signal_error = use_state(|| Option::<String>::None);
+    // Manual mode: our offer or answer for the other side
+    let manual_code = use_state(LocalCode::default);
+    // Host: the shared screen, added again to a replacement peer connection
+    let local_stream = use_mut_ref(|| Option::<MediaStream>::None);
+    // Host: what the viewer asked the screen video to be
+    let video_quality = use_mut_ref(QualityControl::default);
+    // A dropped connection being brought back
+    let reconnect = use_mut_ref(Reconnect::default);
+    let is_host = !props.manual && props.host;
+
+    let peer_context = PeerContext {
+        user_id: props.id.clone(),
+        session_id: (*session_id).clone(),
+        manual: props.manual,
+        navigator: navigator.clone(),
+        peer_connection: peer_connection.clone(),
+        remote_video_ref: remote_video_ref.clone(),
+        local_stream: local_stream.clone(),
+        video_quality: video_quality.clone(),
+        data_channel: data_channel.clone(),
+        terminal_host: terminal_host.clone(),
+        terminal_tabs: terminal_tabs.clone(),
+        terminal_area_ref: terminal_area_ref.clone(),
+        reported_size: reported_size.clone(),
+        viewer_access: viewer_access.clone(),
+        viewer_control: viewer_control.clone(),
+        control_requested: control_requested.clone(),
+        force_update: force_update.clone(),
+        pending_candidates: pending_candidates.clone(),
+        error: Rc::default(),
+        reconnect: reconnect.clone(),
+    };
+    // Everyone we are connected with, starting with the peer the page was opened for
+    let sessions = use_mut_ref({
+        let peer_context = peer_context.clone();
+        move || PeerSessions {
+            peers: vec![peer_context],
+        }
+    });
 
     // Helper function to process signal messages
-    let pc_state = peer_connection.clone();
+    let sessions_for_signals = sessions.clone();
     let navigator_for_signals = navigator.clone();
-    let target_user_id = props.id.clone();
+    let viewer_access_for_signals = viewer_access.clone();
+    let force_update_for_signals = force_update.clone();
+    let terminal_host_for_signals = terminal_host.clone();
+    let control_requested_for_signals = control_requested.clone();
+    let control_notice_for_signals = control_notice.clone();
+    let control_request_for_signals = control_request.clone();
+    let signal_error_for_signals = signal_error.clone();
 
     let process_signal_message = Callback::from(move |message: SignalMessage| {
-        let pc_state = pc_state.clone();
+        let Some(peer_context) = sessions_for_signals.borrow().get(&message.from_user_id) else {
+            log::info!(
+                "Dropping {} from {}, not connected with them",
+                message.signal.kind(),
+                message.from_user_id
+            );
+            return;
+        };
+        // Ours, or the one of the peer's request we approved as its host
+        let approved_session = get_approved_session(&peer_context.user_id);
+        if message.session_id != peer_context.session_id
+            && Some(&message.session_id) != approved_session.as_ref()
+        {
+            log::info!(
+                "Dropping {} for unknown session {}",
+                message.signal.kind(),
+                message.session_id
+            );
+            return;
+        }
+        if message.signal == Signal::Hangup {
+            log::info!("Peer hung up session {}", message.session_id);
+            signal_bus::close_session(&message.session_id);
+            clear_approved_session(&peer_context.user_id, &message.session_id);
+            sessions_for_signals
+                .borrow_mut()
+                .remove(&peer_context.user_id);
+            end_session(&peer_context);
+            if control_request_for_signals.as_deref() == Some(peer_context.user_id.as_str()) {
+                control_request_for_signals.set(None);
+            }
+            if !is_host {
+                signal_error_for_signals.set(Some("The host ended the session".to_string()));
+            }
+            force_update_for_signals.force_update();
+            return;
+        }
+
         let navigator_for_signals = navigator_for_signals.clone();
-        let target_user_id = target_user_id.clone();
+        let viewer_access = viewer_access_for_signals.clone();
+        let force_update = force_update_for_signals.clone();
+        let terminal_host = terminal_host_for_signals.clone();
+        let control_requested = control_requested_for_signals.clone();
+        let control_notice = control_notice_for_signals.clone();
+        let control_request = control_request_for_signals.clone();
 
         wasm_bindgen_futures::spawn_local(async move {
-            let pc_rc_option = match (*pc_state).as_ref() {
+            let pc_rc_option = match peer_context.peer_connection.borrow().clone() {
                 Some(pc) => pc,
                 None => {
                     log::warn!("RTCPeerConnection not initialized when processing signal");
                     return;
                 }
             };
-            let pc = pc_rc_option.borrow();
+            let pc = pc_rc_option.borrow().clone();
 
-            match message.signal_type.as_str() {
-                "offer" => {
-                    log::info!("Received offer: {:?}", message.payload);
-                    let sdp_text = message.payload["sdp"].as_str().unwrap_or_default();
-                    let sdp_init = RtcSessionDescriptionInit::new(RtcSdpType::Offer);
-                    sdp_init.set_sdp(sdp_text);
-
-                    if let Err(e) = JsFuture::from(pc.set_remote_description(&sdp_init)).await {
-                        log::error!("Failed to set remote description for offer: {:?}", e);
-                        return;
-                    }
-                    log::info!("Remote description set for offer.");
-
-                    match JsFuture::from(pc.create_answer()).await {
-                        Ok(answer_js) => {
-                            let answer = RtcSessionDescription::from(answer_js);
-                            let sdp_answer = answer.sdp();
-                            let sdp_answer_init =
-                                RtcSessionDescriptionInit::new(RtcSdpType::Answer);
-                            sdp_answer_init.set_sdp(&sdp_answer);
-
-                            if let Err(e) =
-                                JsFuture::from(pc.set_local_description(&sdp_answer_init)).await
-                            {
-                                log::error!("Failed to set local description for answer: {:?}", e);
+            match message.signal {
+                Signal::Offer {
+                    sdp,
+                    new_connection,
+                } => {
+                    log::info!("Received offer ({} bytes of SDP)", sdp.len());
+                    // The host gave up on the old connection, so do we
+                    let pc = if new_connection {
+                        match replace_peer_connection(&peer_context) {
+                            Some(pc_rc) => pc_rc.borrow().clone(),
+                            None => return,
+                        }
+                    } else {
+                        pc
+                    };
+                    let sdp_answer =
+                        match answer_offer(&pc, &peer_context.pending_candidates, &sdp).await {
+                            Ok(sdp_answer) => sdp_answer,
+                            Err(e) => {
+                                log::error!("{}", e);
                                 return;
                             }
-                            log::info!("Local description set for answer.");
-
-                            if let Some(token) = get_auth_token() {
-                                let mut payload_map = HashMap::new();
-                                payload_map.insert("sdp".to_string(), Value::String(sdp_answer));
-
-                                if let Err(e) = send_signal(
-                                    token,
-                                    target_user_id.clone(),
-                                    "answer".to_string(),
-                                    serde_json::to_value(payload_map).unwrap(),
-                                )
-                                .await
-                                {
-                                    log::error!("Failed to send answer: {:?}", e);
-                                }
-                            } else {
-                                log::error!("Not authenticated to send answer.");
-                                navigator_for_signals.push(&crate::router::router::Route::Login);
-                            }
-                        }
-                        Err(e) => log::error!("Failed to create answer: {:?}", e),
-                    }
-                }
-                "answer" => {
-                    log::info!("Received answer: {:?}", message.payload);
-                    let sdp_text = message.payload["sdp"].as_str().unwrap_or_default();
-                    let sdp_init = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
-                    sdp_init.set_sdp(sdp_text);
+                        };
 
-                    match JsFuture::from(pc.set_remote_description(&sdp_init)).await {
-                        Ok(_) => log::info!("Remote description set for answer."),
-                        Err(e) => {
-                            log::error!("Failed to set remote description for answer: {:?}", e)
+                    if let Some(token) = get_auth_token() {
+                        if let Err(e) = send_signal(
+                            token,
+                            peer_context.user_id.clone(),
+                            message.session_id.clone(),
+                            Signal::Answer { sdp: sdp_answer },
+                        )
+                        .await
+                        {
+                            log::error!("Failed to send answer: {:?}", e);
+                            peer_context
+                                .set_error(Some(format!("Failed to send the answer: {}", e)));
                         }
+                    } else {
+                        log::error!("Not authenticated to send answer.");
+                        navigator_for_signals.push(&crate::router::router::Route::Login);
                     }
                 }
-                "ice_candidate" => {
-                    log::info!("Received ICE candidate: {:?}", message.payload);
-                    let candidate_json_value = message.payload["candidate"].clone();
-
-                    let candidate_init = RtcIceCandidateInit::new("");
-                    if let Some(candidate) = candidate_json_value["candidate"].as_str() {
-                        candidate_init.set_candidate(candidate);
-                    }
-                    if let Some(sdp_m_line_index) = candidate_json_value["sdpMLineIndex"].as_f64() {
-                        candidate_init.set_sdp_m_line_index(Some(sdp_m_line_index as u16));
-                    }
-                    if let Some(sdp_mid) = candidate_json_value["sdpMid"].as_str() {
-                        candidate_init.set_sdp_mid(Some(sdp_mid));
+                Signal::Answer { sdp } => {
+                    log::info!("Received answer ({} bytes of SDP)", sdp.len());
+                    if let Err(e) = apply_answer(&pc, &peer_context.pending_candidates, &sdp).await
+                    {
+                        log::error!("{}", e);
                     }
-
-                    match RtcIceCandidate::new(&candidate_init) {
-                        Ok(candidate) => {
-                            match JsFuture::from(
-                                pc.add_ice_candidate_with_opt_rtc_ice_candidate(Some(&candidate)),
-                            )
-                            .await
-                            {
-                                Ok(_) => log::info!("ICE candidate added."),
-                                Err(e) => log::error!("Failed to add ICE candidate: {:?}", e),
-                            }
-                        }
-                        Err(e) => log::error!("Failed to create RtcIceCandidate: {:?}", e),
+                }
+                Signal::IceCandidate { candidate } => {
+                    receive_candidates(&pc, &peer_context.pending_candidates, vec![candidate])
+                        .await;
+                }
+                Signal::IceCandidates { candidates } => {
+                    receive_candidates(&pc, &peer_context.pending_candidates, candidates).await;
+                }
+                Signal::ScreenShareApproved { access } => {
+                    log::info!("Screen share approved with access: {:?}", access);
+                    *viewer_access.borrow_mut() = access;
+                    force_update.force_update();
+                }
+                Signal::RequestControl => {
+                    if terminal_host.borrow().is_some() {
+                        log::info!("Viewer {} requested control", peer_context.user_id);
+                        control_request.set(Some(peer_context.user_id.clone()));
+                    } else {
+                        // Nothing shared that could be controlled
+                        send_control_denied(peer_context.user_id.clone(), message.session_id).await;
                     }
                 }
-                _ => log::warn!("Unknown signal type: {}", message.signal_type),
+                Signal::VideoQuality { quality } => {
+                    log::info!("Viewer asked for video quality {:?}", quality);
+                    peer_context.video_quality.borrow_mut().request(quality);
+                    apply_video_quality(&peer_context).await;
+                }
+                Signal::ControlDenied => {
+                    log::info!("Control request denied");
+                    control_requested.set(false);
+                    control_notice.set(Some("The host declined your control request".to_string()));
+                }
+                other => log::warn!("Unexpected {} signal", other.kind()),
             }
         });
     });
 
-    // Polling for incoming signal messages
-    use_effect_with((), {
-        let navigator = navigator.clone();
-        let process_signal_message = process_signal_message.clone();
+    // The callback is rebuilt every render, messages go to the current one
+    let latest_signal_handler = use_mut_ref(|| process_signal_message.clone());
+    *latest_signal_handler.borrow_mut() = process_signal_message.clone();
 
-        move |_| {
-            let interval = Interval::new(1000, move || {
-                let navigator = navigator.clone();
-                let process_signal_message = process_signal_message.clone();
+    // Signals from the peers, delivered by the shared signal bus
+    use_effect_with((props.id.clone(), props.manual), {
+        let navigator = navigator.clone();
+        let sessions = sessions.clone();
 
-                wasm_bindgen_futures::spawn_local(async move {
-                    if let Some(token) = get_auth_token() {
-                        match fetch_inbox(token).await {
-                            Ok(messages) => {
-                                for message in messages {
-                                    process_signal_message.emit(message);
-                                }
-                            }
-                            Err(e) => {
-                                log::error!("Failed to fetch inbox: {:?}", e);
-                                if e.contains("Not authenticated") {
-                                    navigator.push(&crate::router::router::Route::Login);
-                                }
-                            }
-                        }
-                    } else {
-                        log::error!("Not authenticated to fetch inbox.");
+        move |(peer_id, manual)| {
+            // Nothing comes through the relay when signals are passed along by hand
+            let subscriptions = (!*manual).then(|| {
+                let subscription = signal_bus::subscribe(
+                    &[
+                        "offer",
+                        "answer",
+                        "ice_candidate",
+                        "ice_candidates",
+                        "screen_share_approved",
+                        "request_control",
+                        "control_denied",
+                        "video_quality",
+                        "hangup",
+                    ],
+                    // A host hears from every viewer it lets in
+                    (!is_host).then_some(peer_id.as_str()),
+                    move |message| latest_signal_handler.borrow().emit(message),
+                );
+                let errors = signal_bus::subscribe_errors(move |e| {
+                    if e.contains("Not authenticated") {
                         navigator.push(&crate::router::router::Route::Login);
                     }
                 });
+                let failures = signal_bus::subscribe_failures(move |failure| {
+                    if let Some(ctx) = sessions.borrow().get(&failure.to_user_id) {
+                        ctx.set_error(Some(failure.message()));
+                    }
+                });
+                (subscription, errors, failures)
             });
 
-            move || {
-                drop(interval);
-            }
+            move || drop(subscriptions)
         }
     });
 
-    // Send initial screen share request when component mounts
+    // Viewer: ask the peer to share when the page mounts
     use_effect_with((), {
-        let target_user_id = props.id.clone();
+        let request_target = props.id.clone();
+        let request_session = (*session_id).clone();
+        let manual = props.manual;
 
         move |_| {
-            let target_user_id = target_user_id.clone();
-
             wasm_bindgen_futures::spawn_local(async move {
+                if manual || is_host {
+                    return;
+                }
                 if let Some(token) = get_auth_token() {
-                    // Get current user info (you might want to add this to your auth utils)
-                    let mut payload = HashMap::new();
-                    payload.insert("userName".to_string(), serde_json::json!("Requesting User"));
-
-                    match send_signal(
-                        token,
-                        target_user_id.clone(),
-                        "request_screen_share".to_string(),
-                        serde_json::json!(payload),
-                    )
-                    .await
+                    // Who is asking, as the host may not know us by id
+                    let signal = Signal::RequestScreenShare {
+                        user_name: get_user_email().unwrap_or_default(),
+                    };
+
+                    match send_signal(token, request_target.clone(), request_session, signal).await
                     {
                         Ok(_) => {
-                            log::info!("Screen share request sent to user: {}", target_user_id);
+                            log::info!("Screen share request sent to user: {}", request_target);
                         }
                         Err(e) => {
                             log::error!("Failed to send screen share request: {:?}", e);
@@ -226,151 +485,37 @@ pub fn canvas_page(props: &CanvasPageProps) -> Html {
 
     // Initialize RTCPeerConnection
     use_effect_with((), {
-        let peer_connection_for_init = peer_connection.clone();
-        let remote_video_ref = remote_video_ref.clone();
-        let user_id = props.id.clone();
-        let navigator = navigator.clone();
+        let peer_context = peer_context.clone();
+        let sessions = sessions.clone();
 
         move |_| {
-            // Configure ICE servers
-            let ice_servers = js_sys::Array::new();
-            let ice_server_obj = js_sys::Object::new();
-            js_sys::Reflect::set(
-                &ice_server_obj,
-                &JsValue::from_str("urls"),
-                &JsValue::from_str("stun:stun.l.google.com:19302"),
-            )
-            .unwrap();
-            ice_servers.push(&ice_server_obj);
-
-            let config = RtcConfiguration::new();
-            config.set_ice_servers(&ice_servers);
-
-            // Create RTCPeerConnection with configuration
-            if let Ok(pc) = RtcPeerConnection::new_with_configuration(&config) {
-                let pc_rc = Rc::new(RefCell::new(pc));
-                peer_connection_for_init.set(Some(pc_rc.clone()));
-
-                // Handle ICE candidates
-                {
-                    let user_id_clone = user_id.clone();
-                    let navigator_clone = navigator.clone();
-
-                    let on_ice_candidate =
-                        Closure::wrap(Box::new(move |event: RtcPeerConnectionIceEvent| {
-                            if let Some(candidate) = event.candidate() {
-                                let json_candidate = JSON::stringify(&candidate.to_json())
-                                    .expect("Failed to stringify candidate");
-                                let json_candidate_str = json_candidate
-                                    .as_string()
-                                    .expect("Failed to convert JsString to String");
-                                let user_id_inner = user_id_clone.clone();
-                                let navigator_inner = navigator_clone.clone();
-
-                                wasm_bindgen_futures::spawn_local(async move {
-                                    if let Some(token) = get_auth_token() {
-                                        let mut payload: HashMap<String, serde_json::Value> =
-                                            HashMap::new();
-                                        payload.insert(
-                                            "candidate".to_string(),
-                                            serde_json::from_str(&json_candidate_str).unwrap(),
-                                        );
-
-                                        if let Err(e) = send_signal(
-                                            token,
-                                            user_id_inner.clone(),
-                                            "ice_candidate".to_string(),
-                                            serde_json::to_value(payload).unwrap(),
-                                        )
-                                        .await
-                                        {
-                                            log::error!("Failed to send ICE candidate: {:?}", e);
-                                        }
-                                    } else {
-                                        log::error!("Not authenticated to send ICE candidate.");
-                                        navigator_inner.push(&crate::router::router::Route::Login);
-                                    }
-                                });
-                            }
-                        })
-                            as Box<dyn FnMut(RtcPeerConnectionIceEvent)>);
-
-                    pc_rc
-                        .borrow()
-                        .set_onicecandidate(Some(on_ice_candidate.as_ref().unchecked_ref()));
-                    on_ice_candidate.forget();
-                }
-
-                // Connection state changes
-                {
-                    let pc_for_cb = pc_rc.clone();
-                    let on_connection_state_change = Closure::wrap(Box::new(move || {
-                        let pc = pc_for_cb.borrow();
-                        log::info!("Connection state: {:?}", pc.connection_state());
-                    })
-                        as Box<dyn FnMut()>);
-
-                    pc_rc.borrow().set_onconnectionstatechange(Some(
-                        on_connection_state_change.as_ref().unchecked_ref(),
-                    ));
-                    on_connection_state_change.forget();
-                }
-
-                // ICE gathering state changes
-                {
-                    let pc_for_cb = pc_rc.clone();
-                    let on_ice_gathering_state_change = Closure::wrap(Box::new(move || {
-                        let pc = pc_for_cb.borrow();
-                        log::info!("ICE gathering state: {:?}", pc.ice_gathering_state());
-                    })
-                        as Box<dyn FnMut()>);
-
-                    pc_rc.borrow().set_onicegatheringstatechange(Some(
-                        on_ice_gathering_state_change.as_ref().unchecked_ref(),
-                    ));
-                    on_ice_gathering_state_change.forget();
-                }
-
-                // Incoming tracks
-                {
-                    let remote_video_ref = remote_video_ref.clone();
-                    let on_track = Closure::wrap(Box::new(move |event: RtcTrackEvent| {
-                        let stream_js_value = event.streams().get(0);
-                        if !stream_js_value.is_undefined() && !stream_js_value.is_null() {
-                            let media_stream = MediaStream::from(stream_js_value);
-                            if let Some(video_element) = remote_video_ref.cast::<HtmlVideoElement>()
-                            {
-                                video_element.set_src_object(Some(&media_stream));
-                            }
-                        }
-                    })
-                        as Box<dyn FnMut(RtcTrackEvent)>);
-
-                    pc_rc
-                        .borrow()
-                        .set_ontrack(Some(on_track.as_ref().unchecked_ref()));
-                    on_track.forget();
-                }
-            } else {
-                log::error!("Failed to create RTCPeerConnection");
-            }
+            create_peer_connection(&peer_context);
 
             // Cleanup - same closure type for all paths
-            let pc_for_cleanup = peer_connection_for_init.clone();
             move || {
-                if let Some(pc_rc) = (*pc_for_cleanup).as_ref() {
-                    pc_rc.borrow().close();
+                peer_context.terminal_host.borrow_mut().take();
+                // Every peer is hung up on its own session
+                let peers = std::mem::take(&mut sessions.borrow_mut().peers);
+                for ctx in peers {
+                    hang_up(&ctx);
+                }
+                // The capture ends with the page
+                if let Some(stream) = peer_context.local_stream.borrow_mut().take() {
+                    for track in stream.get_tracks().iter() {
+                        MediaStreamTrack::from(track).stop();
+                    }
                 }
-                pc_for_cleanup.set(None);
             }
         }
     });
 
     // Screen sharing handler
     let on_share_screen = {
-        let peer_connection = peer_connection.clone();
-        let target_user_id = props.id.clone();
-        let navigator = navigator.clone();
+        let sessions = sessions.clone();
+        let local_stream = local_stream.clone();
+        let signal_error = signal_error.clone();
+        let manual = props.manual;
+        let manual_code = manual_code.clone();
 
         Callback::from(move |_| {
             let window = match web_sys::window() {
@@ -390,7 +535,9 @@ pub fn canvas_page(props: &CanvasPageProps) -> Html {
             };
 
             // Call getDisplayMedia directly in user gesture
-            let promise = match media_devices.get_display_media() {
+            let promise = match media_devices
+                .get_display_media_with_constraints(&quality::display_media_constraints())
+            {
                 Ok(p) => p,
                 Err(e) => {
                     log::error!("Failed to request display media: {:?}", e);
@@ -398,77 +545,36 @@ pub fn canvas_page(props: &CanvasPageProps) -> Html {
                 }
             };
 
-            let peer_connection = peer_connection.clone();
-            let target_user_id = target_user_id.clone();
-            let navigator = navigator.clone();
+            let sessions = sessions.clone();
+            let local_stream = local_stream.clone();
+            let signal_error = signal_error.clone();
+            let manual_code = manual_code.clone();
 
             wasm_bindgen_futures::spawn_local(async move {
                 match JsFuture::from(promise).await {
                     Ok(js_value) => {
                         let media_stream = MediaStream::from(js_value);
                         log::info!("Got display media stream: {:?}", media_stream);
+                        *local_stream.borrow_mut() = Some(media_stream.clone());
 
-                        if let Some(pc_rc) = (*peer_connection).as_ref() {
-                            // Add tracks to peer connection with empty streams array
-                            let empty_streams = js_sys::Array::new();
-
-                            for track in media_stream.get_tracks().iter() {
-                                let media_stream_track = MediaStreamTrack::from(track);
-                                // add_track requires 3 arguments: track, stream, and streams array
-                                pc_rc.borrow_mut().add_track(
-                                    &media_stream_track,
-                                    &media_stream,
-                                    &empty_streams,
+                        // Every viewer is sent the one capture
+                        let peers = sessions.borrow().all();
+                        for ctx in peers {
+                            let Some(pc_rc) = ctx.peer_connection.borrow().clone() else {
+                                log::error!(
+                                    "RTCPeerConnection not initialized when sharing screen."
                                 );
-                            }
+                                continue;
+                            };
+                            add_stream_tracks(&pc_rc.borrow(), &media_stream);
 
-                            // Create offer and send
-                            let pc = pc_rc.borrow();
-                            match JsFuture::from(pc.create_offer()).await {
-                                Ok(offer_js) => {
-                                    let offer = RtcSessionDescription::from(offer_js);
-                                    let sdp_offer = offer.sdp();
-                                    let sdp_offer_init =
-                                        RtcSessionDescriptionInit::new(RtcSdpType::Offer);
-                                    sdp_offer_init.set_sdp(&sdp_offer);
-
-                                    match JsFuture::from(pc.set_local_description(&sdp_offer_init))
-                                        .await
-                                    {
-                                        Ok(_) => {
-                                            log::info!("Local description set for offer.");
-                                            if let Some(token) = get_auth_token() {
-                                                let mut payload_map = HashMap::new();
-                                                payload_map.insert(
-                                                    "sdp".to_string(),
-                                                    Value::String(sdp_offer),
-                                                );
-                                                if let Err(e) = send_signal(
-                                                    token,
-                                                    target_user_id.clone(),
-                                                    "offer".to_string(),
-                                                    serde_json::to_value(payload_map).unwrap(),
-                                                )
-                                                .await
-                                                {
-                                                    log::error!("Failed to send offer: {:?}", e);
-                                                }
-                                            } else {
-                                                log::error!("Not authenticated to send offer.");
-                                                navigator
-                                                    .push(&crate::router::router::Route::Login);
-                                            }
-                                        }
-                                        Err(e) => log::error!(
-                                            "Failed to set local description for offer: {:?}",
-                                            e
-                                        ),
-                                    }
-                                }
-                                Err(e) => log::error!("Failed to create offer: {:?}", e),
+                            if manual {
+                                show_manual_offer(&pc_rc, &manual_code, &signal_error).await;
+                            } else {
+                                send_offer(&ctx, &pc_rc).await;
                             }
-                        } else {
-                            log::error!("RTCPeerConnection not initialized when sharing screen.");
+                            // Whatever the viewer asked for before we shared
+                            apply_video_quality(&ctx).await;
                         }
                     }
                     Err(e) => log::error!("Failed to get display media: {:?}", e),
@@ -477,14 +583,1491 @@ pub fn canvas_page(props: &CanvasPageProps) -> Html {
         })
     };
 
-    html! {
-        <div class={theme_class}>
-            <CanvasTopBar dark_mode={props.dark_mode} toggle_theme={props.toggle_theme.clone()} />
-            <div class="canvas-container">
-                <video ref={remote_video_ref} autoplay=true playsinline=true></video>
-                <canvas id="canvas"></canvas>
-                <button onclick={on_share_screen}>{"Share Screen"}</button>
-            </div>
-        </div>
+    // Keep the host informed about how much room we have for its terminal
+    use_effect_with((), {
+        let data_channel = data_channel.clone();
+        let terminal_host = terminal_host.clone();
+        let terminal_tabs = terminal_tabs.clone();
+        let terminal_area_ref = terminal_area_ref.clone();
+        let reported_size = reported_size.clone();
+
+        move |_| {
+            let on_resize = Closure::wrap(Box::new(move || {
+                if terminal_host.borrow().is_some() {
+                    return;
+                }
+                if let Some(channel) = data_channel.borrow().as_ref() {
+                    report_terminal_size(
+                        channel,
+                        &terminal_area_ref,
+                        &terminal_tabs.borrow(),
+                        &reported_size,
+                    );
+                }
+            }) as Box<dyn FnMut()>);
+
+            let window = web_sys::window();
+            if let Some(window) = &window {
+                let _ = window
+                    .add_event_listener_with_callback("resize", on_resize.as_ref().unchecked_ref());
+            }
+
+            move || {
+                if let Some(window) = &window {
+                    let _ = window.remove_event_listener_with_callback(
+                        "resize",
+                        on_resize.as_ref().unchecked_ref(),
+                    );
+                }
+            }
+        }
+    });
+
+    // Terminal sharing handler
+    let on_share_terminal = {
+        let sessions = sessions.clone();
+        let terminal_host = terminal_host.clone();
+        let host_channel = host_channel.clone();
+        let force_update = force_update.clone();
+        let signal_error = signal_error.clone();
+        let manual = props.manual;
+        let manual_code = manual_code.clone();
+
+        Callback::from(move |_| {
+            if terminal_host.borrow().is_some() {
+                log::warn!("Terminal is already being shared.");
+                return;
+            }
+
+            let sessions = sessions.clone();
+            let terminal_host = terminal_host.clone();
+            let host_channel = host_channel.clone();
+            let force_update = force_update.clone();
+            let signal_error = signal_error.clone();
+            let manual_code = manual_code.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                let on_change = move || force_update.force_update();
+                let host = match TerminalHost::start(80, 24, on_change).await {
+                    Ok(host) => host,
+                    Err(e) => {
+                        log::error!("Failed to start terminal: {}", e);
+                        return;
+                    }
+                };
+
+                // A channel to every viewer, with the access it was approved for
+                let mut offers = Vec::new();
+                let peers = sessions.borrow().all();
+                for ctx in peers {
+                    let Some(pc_rc) = ctx.peer_connection.borrow().clone() else {
+                        log::error!("RTCPeerConnection not initialized when sharing terminal.");
+                        continue;
+                    };
+                    let channel = pc_rc.borrow().create_data_channel(TERMINAL_CHANNEL_LABEL);
+                    *ctx.data_channel.borrow_mut() = Some(channel.clone());
+                    host.add_viewer(&ctx.user_id, channel, get_share_access(&ctx.user_id));
+                    offers.push((ctx, pc_rc));
+                }
+                log::info!("Sharing terminal with {} peer(s)", offers.len());
+                host_channel.set(host.channels().first().copied());
+                *terminal_host.borrow_mut() = Some(host);
+
+                for (ctx, pc_rc) in offers {
+                    if manual {
+                        show_manual_offer(&pc_rc, &manual_code, &signal_error).await;
+                    } else {
+                        send_offer(&ctx, &pc_rc).await;
+                    }
+                }
+            });
+        })
+    };
+
+    // Another shell on the already shared data channel
+    let on_new_tab = {
+        let terminal_host = terminal_host.clone();
+        let host_channel = host_channel.clone();
+
+        Callback::from(move |_| {
+            let Some(open) = terminal_host
+                .borrow()
+                .as_ref()
+                .map(|host| host.open_channel())
+            else {
+                log::warn!("No terminal is being shared.");
+                return;
+            };
+            let host_channel = host_channel.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                match open.await {
+                    Ok(channel) => host_channel.set(Some(channel)),
+                    Err(e) => log::error!("Failed to open terminal tab: {}", e),
+                }
+            });
+        })
+    };
+
+    let on_close_tab = {
+        let terminal_host = terminal_host.clone();
+        let host_channel = host_channel.clone();
+        let recording = recording.clone();
+
+        Callback::from(move |_| {
+            let Some(channel) = *host_channel else {
+                return;
+            };
+            if let Some(host) = terminal_host.borrow().as_ref() {
+                // Killing the shell also ends its recording
+                host.close_channel(channel);
+                host_channel.set(host.channels().first().copied());
+                let mut paths = (*recording).clone();
+                if paths.remove(&channel).is_some() {
+                    recording.set(paths);
+                }
+            }
+        })
+    };
+
+    let on_toggle_recording = {
+        let terminal_host = terminal_host.clone();
+        let host_channel = host_channel.clone();
+        let recording = recording.clone();
+
+        Callback::from(move |_| {
+            let Some(channel) = *host_channel else {
+                return;
+            };
+            let Some(id) = terminal_host
+                .borrow()
+                .as_ref()
+                .and_then(|host| host.terminal_id(channel))
+            else {
+                log::warn!("No terminal is being shared.");
+                return;
+            };
+            let recording = recording.clone();
+            let is_recording = recording.contains_key(&channel);
+
+            wasm_bindgen_futures::spawn_local(async move {
+                let mut paths = (*recording).clone();
+                if is_recording {
+                    match stop_recording(id).await {
+                        Ok(path) => {
+                            log::info!("Recording saved to {}", path);
+                            paths.remove(&channel);
+                            recording.set(paths);
+                        }
+                        Err(e) => log::error!("{}", e),
+                    }
+                } else {
+                    match start_recording(id).await {
+                        Ok(path) => {
+                            log::info!("Recording terminal to {}", path);
+                            paths.insert(channel, path);
+                            recording.set(paths);
+                        }
+                        Err(e) => log::error!("{}", e),
+                    }
+                }
+            });
+        })
+    };
+
+    // Viewer: ask the host to let us type
+    let on_request_control = {
+        let target_user_id = props.id.clone();
+        let session_id = session_id.clone();
+        let control_requested = control_requested.clone();
+        let control_notice = control_notice.clone();
+
+        Callback::from(move |_| {
+            let target_user_id = target_user_id.clone();
+            let session = signal_session(&target_user_id, &session_id);
+            let control_requested = control_requested.clone();
+            let control_notice = control_notice.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                let Some(token) = get_auth_token() else {
+                    return;
+                };
+                match send_signal(token, target_user_id, session, Signal::RequestControl).await {
+                    Ok(_) => {
+                        control_requested.set(true);
+                        control_notice.set(None);
+                    }
+                    Err(e) => {
+                        log::error!("Failed to request control: {:?}", e);
+                        control_notice.set(Some(format!("Failed to request control: {}", e)));
+                    }
+                }
+            });
+        })
+    };
+
+    // Host: answer the viewer's control request
+    let on_grant_control = {
+        let terminal_host = terminal_host.clone();
+        let control_request = control_request.clone();
+
+        Callback::from(move |seconds: Option<u32>| {
+            let Some(viewer) = (*control_request).clone() else {
+                return;
+            };
+            if let Some(host) = terminal_host.borrow().as_ref() {
+                host.grant_control(&viewer, seconds);
+            }
+            control_request.set(None);
+        })
+    };
+
+    let on_deny_control = {
+        let session_id = session_id.clone();
+        let control_request = control_request.clone();
+
+        Callback::from(move |_| {
+            let Some(viewer) = (*control_request).clone() else {
+                return;
+            };
+            control_request.set(None);
+            let session = signal_session(&viewer, &session_id);
+            wasm_bindgen_futures::spawn_local(send_control_denied(viewer, session));
+        })
+    };
+
+    let on_take_back_control = {
+        let terminal_host = terminal_host.clone();
+
+        Callback::from(move |_| {
+            if let Some(host) = terminal_host.borrow().as_ref() {
+                host.take_control_back();
+            }
+        })
+    };
+
+    // Tick the control countdown while someone holds time-limited control
+    use_effect_with((), {
+        let terminal_host = terminal_host.clone();
+        let viewer_control = viewer_control.clone();
+        let force_update = force_update.clone();
+
+        move |_| {
+            let interval = Interval::new(1000, move || {
+                let host_control = terminal_host.borrow().as_ref().map(|host| host.control());
+                let control = host_control.unwrap_or(*viewer_control.borrow());
+                if control.seconds_left().is_some() {
+                    force_update.force_update();
+                }
+            });
+
+            move || {
+                drop(interval);
+            }
+        }
+    });
+
+    // Host: auto quality follows each viewer's bandwidth estimate as it changes
+    use_effect_with((), {
+        let sessions = sessions.clone();
+
+        move |_| {
+            let interval = Interval::new(QUALITY_CHECK_INTERVAL_MS, move || {
+                let peers = sessions.borrow().all();
+                wasm_bindgen_futures::spawn_local(async move {
+                    for ctx in peers {
+                        apply_video_quality(&ctx).await;
+                    }
+                });
+            });
+
+            move || drop(interval)
+        }
+    });
+
+    // Host: a recording that failed to write has already been stopped by the backend
+    use_effect_with((), {
+        let recording_error = recording_error.clone();
+
+        move |_| {
+            // None once unmounted, so a listener that comes up late is dropped right away
+            let listener = Rc::new(RefCell::new(Some(None)));
+            {
+                let listener = listener.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let listening = listen(
+                        "terminal-recording-error",
+                        move |failure: TerminalRecordingError| {
+                            log::error!("{}: {}", failure.path, failure.error);
+                            recording_error.set(Some(failure));
+                        },
+                    )
+                    .await;
+                    if let Some(slot) = listener.borrow_mut().as_mut() {
+                        *slot = Some(listening);
+                    }
+                });
+            }
+
+            move || drop(listener.take())
+        }
+    });
+    use_effect_with((*recording_error).clone(), {
+        let recording = recording.clone();
+        let signal_error = signal_error.clone();
+
+        move |failure| {
+            if let Some(failure) = failure {
+                let mut paths = (*recording).clone();
+                paths.retain(|_, path| *path != failure.path);
+                recording.set(paths);
+                signal_error.set(Some(format!("Recording stopped: {}", failure.error)));
+            }
+        }
+    });
+
+    // Viewer: ask the host for other video quality
+    let on_quality_change = {
+        let target_user_id = props.id.clone();
+        let session_id = session_id.clone();
+        let signal_error = signal_error.clone();
+
+        Callback::from(move |quality: VideoQuality| {
+            let target_user_id = target_user_id.clone();
+            let session = signal_session(&target_user_id, &session_id);
+            let signal_error = signal_error.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                let Some(token) = get_auth_token() else {
+                    return;
+                };
+                let signal = Signal::VideoQuality { quality };
+                if let Err(e) = send_signal(token, target_user_id, session, signal).await {
+                    log::error!("Failed to request video quality: {:?}", e);
+                    signal_error.set(Some(format!("Failed to request video quality: {}", e)));
+                }
+            });
+        })
+    };
+
+    // Host: hang up on one viewer, leaving the others be
+    let on_kick = {
+        let sessions = sessions.clone();
+        let control_request = control_request.clone();
+        let force_update = force_update.clone();
+
+        Callback::from(move |user_id: String| {
+            let Some(ctx) = sessions.borrow_mut().remove(&user_id) else {
+                return;
+            };
+            log::info!("Kicking viewer {}", user_id);
+            hang_up(&ctx);
+            if control_request.as_deref() == Some(user_id.as_str()) {
+                control_request.set(None);
+            }
+            force_update.force_update();
+        })
+    };
+
+    // Host: let a viewer that asked in while we are sharing
+    let on_let_in = {
+        let sessions = sessions.clone();
+        let peer_context = peer_context.clone();
+        let signal_error = signal_error.clone();
+
+        Callback::from(move |(user_id, access): (String, ShareAccess)| {
+            let sessions = sessions.clone();
+            let ctx = peer_context.for_peer(&user_id);
+            let signal_error = signal_error.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                match share_requests::approve(&user_id, access).await {
+                    Ok(()) => connect_viewer(&sessions, ctx).await,
+                    Err(e) => {
+                        log::error!("Failed to send approval: {:?}", e);
+                        signal_error.set(Some(format!("Failed to approve: {}", e)));
+                    }
+                }
+            });
+        })
+    };
+
+    // Keystrokes typed into the remote terminal
+    let on_terminal_keydown = {
+        let data_channel = data_channel.clone();
+        let terminal_tabs = terminal_tabs.clone();
+        let viewer_access = viewer_access.clone();
+
+        Callback::from(move |event: KeyboardEvent| {
+            // The host drops our input anyway, don't bother sending it
+            if !viewer_access.borrow().can_type() {
+                return;
+            }
+            // Leave browser shortcuts alone, Ctrl+V in particular has to reach on_terminal_paste
+            if event.meta_key() || (event.ctrl_key() && event.key().eq_ignore_ascii_case("v")) {
+                return;
+            }
+            let tabs = terminal_tabs.borrow();
+            let (Some(channel), Some(view)) = (tabs.active(), tabs.active_view()) else {
+                return;
+            };
+            let Some(data) = view.key_input(&event) else {
+                return;
+            };
+            event.prevent_default();
+            send_terminal_input(&data_channel.borrow(), channel, data);
+        })
+    };
+
+    let on_terminal_paste = {
+        let data_channel = data_channel.clone();
+        let terminal_tabs = terminal_tabs.clone();
+        let viewer_access = viewer_access.clone();
+
+        Callback::from(move |event: Event| {
+            if !viewer_access.borrow().can_type() {
+                return;
+            }
+            let Some(event) = event.dyn_ref::<ClipboardEvent>() else {
+                return;
+            };
+            let text = event
+                .clipboard_data()
+                .and_then(|data| data.get_data("text").ok())
+                .unwrap_or_default();
+            event.prevent_default();
+            let tabs = terminal_tabs.borrow();
+            if let (Some(channel), Some(view)) = (tabs.active(), tabs.active_view()) {
+                if !text.is_empty() {
+                    let data = view.paste_input(&text);
+                    send_terminal_input(&data_channel.borrow(), channel, data);
+                }
+            }
+        })
+    };
+
+    let is_viewing_terminal = data_channel.borrow().is_some() && terminal_host.borrow().is_none();
+    let host_channels = terminal_host
+        .borrow()
+        .as_ref()
+        .map(|host| host.channels())
+        .unwrap_or_default();
+    let is_sharing_terminal = terminal_host.borrow().is_some();
+    let is_recording = host_channel.is_some_and(|channel| recording.contains_key(&channel));
+    let access = *viewer_access.borrow();
+    let control = *viewer_control.borrow();
+    let host_control = terminal_host
+        .borrow()
+        .as_ref()
+        .map(|host| host.control())
+        .unwrap_or_default();
+    let viewer_tabs = terminal_tabs.borrow().labels();
+    let is_viewer = !props.manual && !is_host;
+    let peer_connections: Vec<(String, RtcPeerConnection)> = sessions
+        .borrow()
+        .peers
+        .iter()
+        .filter_map(|ctx| {
+            let pc_rc = ctx.peer_connection.borrow().clone()?;
+            let pc = pc_rc.borrow().clone();
+            Some((ctx.user_id.clone(), pc))
+        })
+        .collect();
+    let watching: Vec<WatchingViewer> = peer_connections
+        .iter()
+        .map(|(user_id, pc)| WatchingViewer {
+            user_id: user_id.clone(),
+            connected: pc.connection_state() == RtcPeerConnectionState::Connected,
+        })
+        .collect();
+    let active_tab = terminal_tabs.borrow().active();
+
+    let reconnecting = sessions.borrow().peers.iter().any(PeerContext::is_reconnecting);
+    let peer_errors: Vec<(String, String)> = sessions
+        .borrow()
+        .peers
+        .iter()
+        .filter_map(|ctx| Some((ctx.user_id.clone(), ctx.error.borrow().clone()?)))
+        .collect();
+
+    let on_dismiss_signal_error = {
+        let signal_error = signal_error.clone();
+        Callback::from(move |_| signal_error.set(None))
+    };
+
+    let on_dismiss_peer_error = {
+        let sessions = sessions.clone();
+        Callback::from(move |user_id: String| {
+            if let Some(ctx) = sessions.borrow().get(&user_id) {
+                ctx.set_error(None);
+            }
+        })
+    };
+
+    // Manual mode: the other side's offer, answered with a code of ours, or its answer to ours
+    let on_manual_code = {
+        let peer_connection = peer_connection.clone();
+        let pending_candidates = pending_candidates.clone();
+        let manual_code = manual_code.clone();
+        let signal_error = signal_error.clone();
+
+        Callback::from(move |code: String| {
+            let description = match ManualDescription::from_blob(&code) {
+                Ok(description) => description,
+                Err(e) => {
+                    signal_error.set(Some(e));
+                    return;
+                }
+            };
+            let Some(pc_rc) = peer_connection.borrow().clone() else {
+                log::error!("RTCPeerConnection not initialized when applying a code.");
+                return;
+            };
+            let pending_candidates = pending_candidates.clone();
+            let manual_code = manual_code.clone();
+            let signal_error = signal_error.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                let pc = pc_rc.borrow().clone();
+                let result = match description {
+                    ManualDescription::Offer { sdp } => {
+                        manual_code.set(LocalCode::Gathering);
+                        async {
+                            answer_offer(&pc, &pending_candidates, &sdp).await?;
+                            let sdp = gather_candidates(&pc).await?;
+                            Ok(LocalCode::Ready(
+                                ManualDescription::Answer { sdp }.to_blob(),
+                            ))
+                        }
+                        .await
+                    }
+                    // Our offer is done with once answered
+                    ManualDescription::Answer { sdp } => {
+                        apply_answer(&pc, &pending_candidates, &sdp)
+                            .await
+                            .map(|_| LocalCode::None)
+                    }
+                };
+                match result {
+                    Ok(local) => manual_code.set(local),
+                    Err(e) => {
+                        log::error!("{}", e);
+                        manual_code.set(LocalCode::None);
+                        signal_error.set(Some(e));
+                    }
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class={theme_class}>
+            <CanvasTopBar dark_mode={props.dark_mode} toggle_theme={props.toggle_theme.clone()} />
+            <div class="canvas-container">
+                <video ref={remote_video_ref} autoplay=true playsinline=true></video>
+                <div class="terminal-view">
+                    if is_viewing_terminal {
+                        <div class="terminal-tabs">
+                            { for viewer_tabs.into_iter().map(|(channel, label)| {
+                                let terminal_tabs = terminal_tabs.clone();
+                                let force_update = force_update.clone();
+                                let onclick = Callback::from(move |_| {
+                                    terminal_tabs.borrow_mut().select(channel);
+                                    force_update.force_update();
+                                });
+                                html! {
+                                    <button
+                                        class={classes!("terminal-tab", (active_tab == Some(channel)).then_some("active"))}
+                                        {onclick}
+                                    >
+                                        {label}
+                                    </button>
+                                }
+                            }) }
+                        </div>
+                        <div class="terminal-title">
+                            <span class={classes!("terminal-access", access.can_type().then_some("interactive"))}>
+                                {access.label()}
+                            </span>
+                            <span class="terminal-control">{control.label(false)}</span>
+                            if !access.can_type() && !control.is_yours() {
+                                <button
+                                    class="btn-request-control"
+                                    onclick={on_request_control}
+                                    disabled={*control_requested}
+                                >
+                                    {if *control_requested { "Control requested…" } else { "Request control" }}
+                                </button>
+                            }
+                        </div>
+                        if let Some(notice) = &*control_notice {
+                            <div class="terminal-control-notice">{notice}</div>
+                        }
+                    }
+                    <div class="terminal-screen" ref={terminal_area_ref}>
+                        <canvas
+                            id="canvas"
+                            ref={canvas_ref}
+                            tabindex="0"
+                            onkeydown={on_terminal_keydown}
+                            onpaste={on_terminal_paste}
+                        ></canvas>
+                    </div>
+                </div>
+                if props.manual {
+                    <ManualSignal local={(*manual_code).clone()} on_apply={on_manual_code} />
+                }
+                <ConnectionStats {peer_connections} />
+                if is_host {
+                    <WatchingViewers viewers={watching} on_kick={on_kick} on_let_in={on_let_in} />
+                }
+                if is_viewer {
+                    <QualityControls on_change={on_quality_change} />
+                }
+                if reconnecting {
+                    <div class="reconnecting">{"Reconnecting…"}</div>
+                }
+                if let Some(error) = &*signal_error {
+                    <div class="signal-error">
+                        <span>{error}</span>
+                        <button onclick={on_dismiss_signal_error}>{"Dismiss"}</button>
+                    </div>
+                }
+                { for peer_errors.into_iter().map(|(user_id, error)| {
+                    let on_dismiss = on_dismiss_peer_error.reform(move |_| user_id.clone());
+                    html! {
+                        <div class="signal-error">
+                            <span>{error}</span>
+                            <button onclick={on_dismiss}>{"Dismiss"}</button>
+                        </div>
+                    }
+                }) }
+                <button onclick={on_share_screen}>{"Share Screen"}</button>
+                <button onclick={on_share_terminal}>{"Share Terminal"}</button>
+                if is_sharing_terminal {
+                    <div class="terminal-tabs">
+                        { for host_channels.into_iter().map(|channel| {
+                            let selected = *host_channel == Some(channel);
+                            let host_channel = host_channel.clone();
+                            let onclick = Callback::from(move |_| host_channel.set(Some(channel)));
+                            html! {
+                                <button
+                                    class={classes!("terminal-tab", selected.then_some("active"))}
+                                    {onclick}
+                                >
+                                    {format!("Shell {}", channel)}
+                                    if recording.contains_key(&channel) {
+                                        <span class="recording-indicator">{"●"}</span>
+                                    }
+                                </button>
+                            }
+                        }) }
+                        <button class="terminal-tab" onclick={on_new_tab}>{"+"}</button>
+                    </div>
+                    <button onclick={on_close_tab}>{"Close Tab"}</button>
+                    <button onclick={on_toggle_recording}>
+                        {if is_recording { "Stop Recording" } else { "Record" }}
+                    </button>
+                    <span class="terminal-control">{host_control.label(true)}</span>
+                    if host_control.is_viewer() {
+                        <button class="btn-reject" onclick={on_take_back_control}>
+                            {"Take back control"}
+                        </button>
+                    }
+                }
+                if let Some(viewer) = &*control_request {
+                    <div class="user-card control-request">
+                        <div class="user-card-header">
+                            <h3 class="user-name">{"Control request"}</h3>
+                            <span class="request-badge">{"🔔 Wants control"}</span>
+                        </div>
+                        <div class="user-card-body">
+                            <p class="user-detail-id">{"User ID: "}{viewer}</p>
+                            <p class="request-message">{"wants to type into your terminal"}</p>
+                        </div>
+                        <div class="user-card-footer button-group">
+                            { for CONTROL_TIME_LIMITS.iter().map(|&(seconds, label)| {
+                                let on_grant_control = on_grant_control.clone();
+                                html! {
+                                    <button
+                                        class="btn-accept"
+                                        onclick={Callback::from(move |_| on_grant_control.emit(seconds))}
+                                    >
+                                        {format!("✓ {}", label)}
+                                    </button>
+                                }
+                            }) }
+                            <button class="btn-reject" onclick={on_deny_control}>
+                                {"✗ Deny"}
+                            </button>
+                        </div>
+                    </div>
+                }
+                if let Some(path) = host_channel.and_then(|channel| recording.get(&channel)) {
+                    <span class="recording-indicator" title={path.clone()}>{"● REC"}</span>
+                }
+            </div>
+        </div>
+    }
+}
+
+/// Sets up a peer connection with the ICE servers loaded so far and makes it
+/// the page's current one.
+fn create_peer_connection(ctx: &PeerContext) -> Option<Rc<RefCell<RtcPeerConnection>>> {
+    let pc = match RtcPeerConnection::new_with_configuration(&ice::rtc_configuration()) {
+        Ok(pc) => pc,
+        Err(e) => {
+            log::error!("Failed to create RTCPeerConnection: {:?}", e);
+            return None;
+        }
+    };
+    let pc_rc = Rc::new(RefCell::new(pc));
+    *ctx.peer_connection.borrow_mut() = Some(pc_rc.clone());
+    // Candidates for an earlier connection don't apply to this one
+    ctx.pending_candidates.borrow_mut().clear();
+
+    // TURN credentials may have run out since, fresh ones apply to the next gathering
+    {
+        let pc_rc = pc_rc.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(config) = ice::refresh().await {
+                if let Err(e) = pc_rc.borrow().set_configuration_with_configuration(&config) {
+                    log::error!("Failed to apply ICE servers: {:?}", e);
+                }
+            }
+        });
+    }
+
+    // Handle ICE candidates, batched so a burst of them is one signal
+    {
+        let user_id_clone = ctx.user_id.clone();
+        let session_id_clone = ctx.session_id.clone();
+        let navigator_clone = ctx.navigator.clone();
+        let manual = ctx.manual;
+        let outgoing = Rc::new(RefCell::new(Vec::<IceCandidate>::new()));
+
+        let on_ice_candidate = Closure::wrap(Box::new(move |event: RtcPeerConnectionIceEvent| {
+            // Manual codes carry all candidates in their description instead
+            if manual {
+                return;
+            }
+            if let Some(candidate) = event.candidate() {
+                let json_candidate =
+                    JSON::stringify(&candidate.to_json()).expect("Failed to stringify candidate");
+                let json_candidate_str = json_candidate
+                    .as_string()
+                    .expect("Failed to convert JsString to String");
+                let candidate: IceCandidate = match serde_json::from_str(&json_candidate_str) {
+                    Ok(candidate) => candidate,
+                    Err(e) => {
+                        log::error!("Invalid ICE candidate: {}", e);
+                        return;
+                    }
+                };
+
+                // The first candidate of a batch starts the window
+                let first = outgoing.borrow().is_empty();
+                outgoing.borrow_mut().push(candidate);
+                if !first {
+                    return;
+                }
+                let outgoing = outgoing.clone();
+                let user_id_inner = user_id_clone.clone();
+                let session_id_inner = session_id_clone.clone();
+                let navigator_inner = navigator_clone.clone();
+                Timeout::new(ICE_BATCH_WINDOW_MS, move || {
+                    let candidates = std::mem::take(&mut *outgoing.borrow_mut());
+                    let session_inner = signal_session(&user_id_inner, &session_id_inner);
+
+                    wasm_bindgen_futures::spawn_local(async move {
+                        if let Some(token) = get_auth_token() {
+                            if let Err(e) = send_signal(
+                                token,
+                                user_id_inner,
+                                session_inner,
+                                Signal::IceCandidates { candidates },
+                            )
+                            .await
+                            {
+                                log::error!("Failed to send ICE candidates: {:?}", e);
+                            }
+                        } else {
+                            log::error!("Not authenticated to send ICE candidates.");
+                            navigator_inner.push(&crate::router::router::Route::Login);
+                        }
+                    });
+                })
+                .forget();
+            }
+        })
+            as Box<dyn FnMut(RtcPeerConnectionIceEvent)>);
+
+        pc_rc
+            .borrow()
+            .set_onicecandidate(Some(on_ice_candidate.as_ref().unchecked_ref()));
+        on_ice_candidate.forget();
+    }
+
+    // Connection state changes, a dropped connection is brought back
+    {
+        let ctx = ctx.clone();
+        let pc_for_cb = pc_rc.clone();
+        let on_connection_state_change = Closure::wrap(Box::new(move || {
+            on_connection_state(&ctx, &pc_for_cb.borrow());
+        }) as Box<dyn FnMut()>);
+
+        pc_rc
+            .borrow()
+            .set_onconnectionstatechange(Some(on_connection_state_change.as_ref().unchecked_ref()));
+        on_connection_state_change.forget();
+    }
+
+    // ICE gathering state changes
+    {
+        let pc_for_cb = pc_rc.clone();
+        let on_ice_gathering_state_change = Closure::wrap(Box::new(move || {
+            let pc = pc_for_cb.borrow();
+            log::info!("ICE gathering state: {:?}", pc.ice_gathering_state());
+        }) as Box<dyn FnMut()>);
+
+        pc_rc.borrow().set_onicegatheringstatechange(Some(
+            on_ice_gathering_state_change.as_ref().unchecked_ref(),
+        ));
+        on_ice_gathering_state_change.forget();
+    }
+
+    // Incoming tracks
+    {
+        let remote_video_ref = ctx.remote_video_ref.clone();
+        let on_track = Closure::wrap(Box::new(move |event: RtcTrackEvent| {
+            let stream_js_value = event.streams().get(0);
+            if !stream_js_value.is_undefined() && !stream_js_value.is_null() {
+                let media_stream = MediaStream::from(stream_js_value);
+                if let Some(video_element) = remote_video_ref.cast::<HtmlVideoElement>() {
+                    video_element.set_src_object(Some(&media_stream));
+                }
+            }
+        }) as Box<dyn FnMut(RtcTrackEvent)>);
+
+        pc_rc
+            .borrow()
+            .set_ontrack(Some(on_track.as_ref().unchecked_ref()));
+        on_track.forget();
+    }
+
+    // Terminal channel opened by the host
+    {
+        let data_channel = ctx.data_channel.clone();
+        let terminal_tabs = ctx.terminal_tabs.clone();
+        let terminal_area_ref = ctx.terminal_area_ref.clone();
+        let reported_size = ctx.reported_size.clone();
+        let viewer_access = ctx.viewer_access.clone();
+        let viewer_control = ctx.viewer_control.clone();
+        let control_requested = ctx.control_requested.clone();
+        let force_update = ctx.force_update.clone();
+        let on_data_channel = Closure::wrap(Box::new(move |event: RtcDataChannelEvent| {
+            let channel = event.channel();
+            if channel.label() != TERMINAL_CHANNEL_LABEL {
+                log::warn!("Ignoring unknown data channel: {}", channel.label());
+                return;
+            }
+            log::info!("Terminal channel received.");
+
+            let terminal_tabs = terminal_tabs.clone();
+            let terminal_area_ref = terminal_area_ref.clone();
+            let reported_size = reported_size.clone();
+            let viewer_access = viewer_access.clone();
+            let viewer_control = viewer_control.clone();
+            let control_requested = control_requested.clone();
+            let force_update_on_frame = force_update.clone();
+            let frame_channel = channel.clone();
+            on_frame(&channel, move |frame| match frame {
+                // First frame from the host, so the channel is open: tell it our size
+                TerminalFrame::Access { access } => {
+                    *viewer_access.borrow_mut() = access;
+                    report_terminal_size(
+                        &frame_channel,
+                        &terminal_area_ref,
+                        &terminal_tabs.borrow(),
+                        &reported_size,
+                    );
+                    force_update_on_frame.force_update();
+                }
+                TerminalFrame::Size { cols, rows } => {
+                    terminal_tabs.borrow_mut().resize(cols, rows);
+                }
+                TerminalFrame::Control { state } => {
+                    *viewer_control.borrow_mut() = Control::from_state(state);
+                    control_requested.set(false);
+                    force_update_on_frame.force_update();
+                }
+                TerminalFrame::Snapshot { channel, snapshot } => {
+                    if let Err(e) = terminal_tabs.borrow_mut().restore(channel, *snapshot) {
+                        log::error!("Failed to restore terminal snapshot: {}", e);
+                    }
+                    force_update_on_frame.force_update();
+                }
+                TerminalFrame::Open { channel } => {
+                    terminal_tabs.borrow_mut().open(channel);
+                    force_update_on_frame.force_update();
+                }
+                TerminalFrame::Close { channel } => {
+                    terminal_tabs.borrow_mut().close(channel);
+                    force_update_on_frame.force_update();
+                }
+                TerminalFrame::Output { channel, data } => {
+                    let title_changed = terminal_tabs.borrow_mut().feed(channel, &data);
+                    if title_changed {
+                        force_update_on_frame.force_update();
+                    }
+                }
+                other => {
+                    log::warn!("Unexpected terminal frame from host: {:?}", other)
+                }
+            });
+            *data_channel.borrow_mut() = Some(channel);
+            force_update.force_update();
+        }) as Box<dyn FnMut(RtcDataChannelEvent)>);
+
+        pc_rc
+            .borrow()
+            .set_ondatachannel(Some(on_data_channel.as_ref().unchecked_ref()));
+        on_data_channel.forget();
+    }
+
+    Some(pc_rc)
+}
+
+/// Closes the current peer connection and sets up another one in its place.
+fn replace_peer_connection(ctx: &PeerContext) -> Option<Rc<RefCell<RtcPeerConnection>>> {
+    let old = ctx.peer_connection.borrow_mut().take();
+    if let Some(old) = old {
+        close_peer_connection(&old.borrow());
+    }
+    create_peer_connection(ctx)
+}
+
+/// Closes `pc` and stops the tracks it sent. Its handlers are dropped first,
+/// so its closing isn't taken for the connection dropping again.
+fn close_peer_connection(pc: &RtcPeerConnection) {
+    pc.set_onicecandidate(None);
+    pc.set_onconnectionstatechange(None);
+    pc.set_onicegatheringstatechange(None);
+    pc.set_ontrack(None);
+    pc.set_ondatachannel(None);
+    // Clones of the capture, the capture itself goes on for the other viewers
+    for sender in pc.get_senders().iter() {
+        let sender: RtcRtpSender = sender.unchecked_into();
+        if let Some(track) = sender.track() {
+            track.stop();
+        }
+    }
+    pc.close();
+}
+
+/// Ends the session with one peer: stops reconnecting, stops sharing the
+/// terminals with it and closes its connection.
+fn end_session(ctx: &PeerContext) {
+    if std::mem::take(&mut *ctx.reconnect.borrow_mut())
+        .deadline
+        .is_some()
+    {
+        ctx.force_update.force_update();
+    }
+    if let Some(host) = ctx.terminal_host.borrow().as_ref() {
+        host.remove_viewer(&ctx.user_id);
+    }
+    if let Some(channel) = ctx.data_channel.borrow_mut().take() {
+        channel.close();
+    }
+    if let Some(pc_rc) = ctx.peer_connection.borrow_mut().take() {
+        close_peer_connection(&pc_rc.borrow());
+    }
+}
+
+/// Ends the session with one peer and tells it so, on the session we approved
+/// if we are its host. The session is closed and its approval forgotten.
+fn hang_up(ctx: &PeerContext) {
+    end_session(ctx);
+    if ctx.manual {
+        return;
+    }
+    let session = signal_session(&ctx.user_id, &ctx.session_id);
+    // Late answers and candidates for it must not reach the next one
+    signal_bus::close_session(&session);
+    clear_approved_session(&ctx.user_id, &session);
+
+    let user_id = ctx.user_id.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        let Some(token) = get_auth_token() else {
+            return;
+        };
+        if let Err(e) = send_signal(token, user_id, session, Signal::Hangup).await {
+            log::error!("Failed to hang up: {:?}", e);
+        }
+    });
+}
+
+/// Host: sets up a connection with a viewer we just let in, and offers it
+/// whatever is shared already.
+async fn connect_viewer(sessions: &RefCell<PeerSessions>, ctx: PeerContext) {
+    // Let in again after leaving, the old session is done with
+    let old = sessions.borrow_mut().remove(&ctx.user_id);
+    if let Some(old) = old {
+        end_session(&old);
+    }
+    let Some(pc_rc) = create_peer_connection(&ctx) else {
+        return;
+    };
+    sessions.borrow_mut().insert(ctx.clone());
+    ctx.force_update.force_update();
+
+    let pc = pc_rc.borrow().clone();
+    if !share_with(&ctx, &pc) {
+        // Nothing to offer yet, sharing offers it to everyone
+        return;
+    }
+    send_offer(&ctx, &pc_rc).await;
+    apply_video_quality(&ctx).await;
+}
+
+/// Adds what we share to `pc`, a new connection with the peer of `ctx`: the
+/// screen, and a channel to the terminal host. False if nothing is shared.
+fn share_with(ctx: &PeerContext, pc: &RtcPeerConnection) -> bool {
+    let mut shared = false;
+    if let Some(stream) = ctx.local_stream.borrow().as_ref() {
+        add_stream_tracks(pc, stream);
+        ctx.video_quality.borrow_mut().reset();
+        shared = true;
+    }
+    if let Some(host) = ctx.terminal_host.borrow().as_ref() {
+        let channel = pc.create_data_channel(TERMINAL_CHANNEL_LABEL);
+        *ctx.data_channel.borrow_mut() = Some(channel.clone());
+        host.add_viewer(&ctx.user_id, channel, get_share_access(&ctx.user_id));
+        shared = true;
+    }
+    shared
+}
+
+/// Reacts to the connection dropping or coming back. The side that made the
+/// offer brings it back, the other one answers whatever it offers next.
+fn on_connection_state(ctx: &PeerContext, pc: &RtcPeerConnection) {
+    let state = pc.connection_state();
+    log::info!("Connection state with {}: {:?}", ctx.user_id, state);
+    // The host's list of viewers shows who is connected
+    ctx.force_update.force_update();
+    match state {
+        RtcPeerConnectionState::Connected => {
+            let mut reconnect = ctx.reconnect.borrow_mut();
+            if reconnect.deadline.is_some() {
+                log::info!("Reconnected");
+                *reconnect = Reconnect::default();
+            }
+        }
+        RtcPeerConnectionState::Disconnected | RtcPeerConnectionState::Failed => {
+            // No one to pass a new offer along
+            if ctx.manual {
+                return;
+            }
+            start_reconnecting(ctx);
+            let is_offerer = pc
+                .local_description()
+                .is_some_and(|description| description.type_() == RtcSdpType::Offer);
+            if !is_offerer {
+                return;
+            }
+
+            let failed = state == RtcPeerConnectionState::Failed;
+            let mut reconnect = ctx.reconnect.borrow_mut();
+            if reconnect.restarted {
+                // ICE restarts are done with on this connection, it has to be replaced
+                if failed {
+                    reconnect.schedule(ctx, 0, replace_connection);
+                }
+            } else if failed {
+                reconnect.schedule(ctx, 0, restart_ice);
+            } else if reconnect.attempt.is_none() {
+                reconnect.schedule(ctx, DISCONNECTED_GRACE_MS, restart_ice);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Starts the clock on giving up on the peer of `ctx`, unless that is
+/// already running. The banner shows while any peer is reconnecting.
+fn start_reconnecting(ctx: &PeerContext) {
+    let mut reconnect = ctx.reconnect.borrow_mut();
+    if reconnect.deadline.is_some() {
+        return;
+    }
+    log::info!("Connection lost, reconnecting");
+    let ctx_for_deadline = ctx.clone();
+    reconnect.deadline = Some(Timeout::new(settings::reconnect_deadline_ms(), move || {
+        // Not from inside the timeout, which giving up drops
+        wasm_bindgen_futures::spawn_local(async move { give_up(&ctx_for_deadline) });
+    }));
+    ctx.set_error(None);
+}
+
+fn give_up(ctx: &PeerContext) {
+    log::warn!("Couldn't reconnect to {}, giving up", ctx.user_id);
+    *ctx.reconnect.borrow_mut() = Reconnect::default();
+    ctx.set_error(Some(
+        "Lost the connection to the peer and couldn't get it back".to_string(),
+    ));
+}
+
+/// Restarts ICE on the current connection, which is all a network change
+/// needs. Replaces the connection if that doesn't work out in time.
+async fn restart_ice(ctx: PeerContext) {
+    let Some(pc_rc) = ctx.peer_connection.borrow().clone() else {
+        return;
+    };
+    if ctx.reconnect.borrow().deadline.is_none() {
+        return;
+    }
+    log::info!("Restarting ICE");
+    ctx.reconnect.borrow_mut().restarted = true;
+    // Candidates held back for the old generation don't apply to the new one
+    ctx.pending_candidates.borrow_mut().clear();
+    let pc = pc_rc.borrow().clone();
+    let delay = if send_reconnect_offer(&ctx, &pc, false).await {
+        RECONNECT_ATTEMPT_TIMEOUT_MS
+    } else {
+        0
+    };
+    ctx.reconnect
+        .borrow_mut()
+        .schedule(&ctx, delay, replace_connection);
+}
+
+/// Replaces the peer connection with a new one carrying the same screen and
+/// terminals, for when the old one can't be saved. Tried again until it
+/// connects or the deadline passes.
+async fn replace_connection(ctx: PeerContext) {
+    if ctx.reconnect.borrow().deadline.is_none() {
+        return;
+    }
+    log::info!("Replacing the peer connection");
+    let Some(pc_rc) = replace_peer_connection(&ctx) else {
+        return;
+    };
+    let pc = pc_rc.borrow().clone();
+    share_with(&ctx, &pc);
+    send_reconnect_offer(&ctx, &pc, true).await;
+    ctx.reconnect
+        .borrow_mut()
+        .schedule(&ctx, RECONNECT_ATTEMPT_TIMEOUT_MS, replace_connection);
+    apply_video_quality(&ctx).await;
+}
+
+/// Offers `pc` to the peer again: an ICE restart of the connection it
+/// already has, or the first offer of a new one. False if it didn't go out.
+async fn send_reconnect_offer(
+    ctx: &PeerContext,
+    pc: &RtcPeerConnection,
+    new_connection: bool,
+) -> bool {
+    let sdp = match set_local_offer(pc, !new_connection).await {
+        Ok(sdp) => sdp,
+        Err(e) => {
+            log::error!("{}", e);
+            return false;
+        }
+    };
+    let Some(token) = get_auth_token() else {
+        log::error!("Not authenticated to send offer.");
+        ctx.navigator.push(&crate::router::router::Route::Login);
+        return false;
+    };
+    let session = signal_session(&ctx.user_id, &ctx.session_id);
+    match send_signal(
+        token,
+        ctx.user_id.clone(),
+        session,
+        Signal::Offer {
+            sdp,
+            new_connection,
+        },
+    )
+    .await
+    {
+        Ok(()) => true,
+        Err(e) => {
+            // Likely the same outage, the next attempt tries again
+            log::error!("Failed to send offer: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Fits the shared screen to what the viewer asked for, if a screen is shared.
+async fn apply_video_quality(ctx: &PeerContext) {
+    if ctx.local_stream.borrow().is_none() {
+        return;
+    }
+    let Some(pc_rc) = ctx.peer_connection.borrow().clone() else {
+        return;
+    };
+    let pc = pc_rc.borrow().clone();
+    if let Err(e) = quality::apply(&ctx.video_quality, &pc).await {
+        log::error!("{}", e);
+    }
+}
+
+/// Adds every track of the shared `stream` to `pc`, as clones so that caps
+/// applied for one viewer leave the others' video alone.
+fn add_stream_tracks(pc: &RtcPeerConnection, stream: &MediaStream) {
+    // add_track requires 3 arguments: track, stream, and streams array
+    let empty_streams = js_sys::Array::new();
+    for track in stream.get_tracks().iter() {
+        let track = MediaStreamTrack::clone(&MediaStreamTrack::from(track));
+        pc.add_track(&track, stream, &empty_streams);
+    }
+}
+
+fn send_terminal_input(data_channel: &Option<RtcDataChannel>, channel: ChannelId, data: String) {
+    if let Some(data_channel) = data_channel {
+        if let Err(e) = send_frame(data_channel, &TerminalFrame::Input { channel, data }) {
+            log::error!("{}", e);
+        }
+    }
+}
+
+/// Tells the viewer the host won't hand over control.
+async fn send_control_denied(target_user_id: String, session_id: String) {
+    let Some(token) = get_auth_token() else {
+        return;
+    };
+    if let Err(e) = send_signal(token, target_user_id, session_id, Signal::ControlDenied).await {
+        log::error!("Failed to deny control: {:?}", e);
+    }
+}
+
+/// Tells the host how many cols/rows fit into the terminal area, if that
+/// changed since the last report.
+fn report_terminal_size(
+    channel: &RtcDataChannel,
+    area: &NodeRef,
+    tabs: &TerminalTabs,
+    reported: &RefCell<Option<(u16, u16)>>,
+) {
+    let Some(area) = area.cast::<HtmlElement>() else {
+        return;
+    };
+    let Some((cols, rows)) = tabs.fit(area.client_width() as f64, area.client_height() as f64)
+    else {
+        return;
+    };
+    if *reported.borrow() == Some((cols, rows)) {
+        return;
+    }
+    match send_frame(channel, &TerminalFrame::Resize { cols, rows }) {
+        Ok(()) => *reported.borrow_mut() = Some((cols, rows)),
+        Err(e) => log::error!("{}", e),
+    }
+}
+
+/// Adds the peer's candidates, or keeps them in `pending` until its
+/// description is set.
+async fn receive_candidates(
+    pc: &RtcPeerConnection,
+    pending: &RefCell<Vec<IceCandidate>>,
+    candidates: Vec<IceCandidate>,
+) {
+    log::info!("Received {} ICE candidate(s)", candidates.len());
+    if pc.remote_description().is_none() {
+        pending.borrow_mut().extend(candidates);
+        return;
+    }
+    add_remote_candidates(pc, candidates).await;
+}
+
+async fn add_remote_candidates(pc: &RtcPeerConnection, candidates: Vec<IceCandidate>) {
+    for candidate in candidates {
+        let candidate_init = RtcIceCandidateInit::new(&candidate.candidate);
+        candidate_init.set_sdp_m_line_index(candidate.sdp_m_line_index);
+        candidate_init.set_sdp_mid(candidate.sdp_mid.as_deref());
+        // Ties it to an ICE generation, candidates of one before a restart are dropped
+        if let Some(ufrag) = &candidate.username_fragment {
+            let _ = js_sys::Reflect::set(
+                &candidate_init,
+                &JsValue::from_str("usernameFragment"),
+                &JsValue::from_str(ufrag),
+            );
+        }
+
+        match RtcIceCandidate::new(&candidate_init) {
+            Ok(candidate) => {
+                match JsFuture::from(
+                    pc.add_ice_candidate_with_opt_rtc_ice_candidate(Some(&candidate)),
+                )
+                .await
+                {
+                    Ok(_) => log::info!("ICE candidate added."),
+                    Err(e) => log::error!("Failed to add ICE candidate: {:?}", e),
+                }
+            }
+            Err(e) => log::error!("Failed to create RtcIceCandidate: {:?}", e),
+        }
+    }
+}
+
+/// The session signals to `peer_id` go out with: the one of its request we
+/// approved when we are its host, our own otherwise.
+fn signal_session(peer_id: &str, own_session: &str) -> String {
+    get_approved_session(peer_id).unwrap_or_else(|| own_session.to_string())
+}
+
+/// Creates an offer for everything currently attached to the peer connection
+/// and sends it to the peer of `ctx`.
+async fn send_offer(ctx: &PeerContext, pc_rc: &Rc<RefCell<RtcPeerConnection>>) {
+    let pc = pc_rc.borrow().clone();
+    let sdp_offer = match set_local_offer(&pc, false).await {
+        Ok(sdp_offer) => sdp_offer,
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+    };
+    if let Some(token) = get_auth_token() {
+        if let Err(e) = send_signal(
+            token,
+            ctx.user_id.clone(),
+            signal_session(&ctx.user_id, &ctx.session_id),
+            Signal::Offer {
+                sdp: sdp_offer,
+                new_connection: false,
+            },
+        )
+        .await
+        {
+            log::error!("Failed to send offer: {:?}", e);
+            ctx.set_error(Some(format!("Failed to send the offer: {}", e)));
+        }
+    } else {
+        log::error!("Not authenticated to send offer.");
+        ctx.navigator.push(&crate::router::router::Route::Login);
+    }
+}
+
+/// Like [`send_offer`] for signals passed along by hand: waits for every
+/// candidate, then shows the offer as a code for the other side.
+async fn show_manual_offer(
+    pc_rc: &Rc<RefCell<RtcPeerConnection>>,
+    manual_code: &UseStateHandle<LocalCode>,
+    signal_error: &UseStateHandle<Option<String>>,
+) {
+    let pc = pc_rc.borrow().clone();
+    manual_code.set(LocalCode::Gathering);
+    let code = async {
+        set_local_offer(&pc, false).await?;
+        let sdp = gather_candidates(&pc).await?;
+        Ok::<_, String>(ManualDescription::Offer { sdp }.to_blob())
+    }
+    .await;
+    match code {
+        Ok(code) => manual_code.set(LocalCode::Ready(code)),
+        Err(e) => {
+            log::error!("{}", e);
+            manual_code.set(LocalCode::None);
+            signal_error.set(Some(e));
+        }
+    }
+}
+
+/// Creates an offer, with new ICE credentials if `ice_restart`, and makes it
+/// our local description, returning its SDP.
+async fn set_local_offer(pc: &RtcPeerConnection, ice_restart: bool) -> Result<String, String> {
+    let options = RtcOfferOptions::new();
+    options.set_ice_restart(ice_restart);
+    let offer = JsFuture::from(pc.create_offer_with_rtc_offer_options(&options))
+        .await
+        .map_err(|e| format!("Failed to create offer: {:?}", e))?;
+    let sdp_offer = RtcSessionDescription::from(offer).sdp();
+    let sdp_offer_init = RtcSessionDescriptionInit::new(RtcSdpType::Offer);
+    sdp_offer_init.set_sdp(&sdp_offer);
+
+    JsFuture::from(pc.set_local_description(&sdp_offer_init))
+        .await
+        .map_err(|e| format!("Failed to set local description for offer: {:?}", e))?;
+    log::info!("Local description set for offer.");
+    Ok(sdp_offer)
+}
+
+/// Takes the peer's offer, adds the candidates that came before it and
+/// makes an answer our local description, returning its SDP.
+async fn answer_offer(
+    pc: &RtcPeerConnection,
+    pending_candidates: &RefCell<Vec<IceCandidate>>,
+    sdp: &str,
+) -> Result<String, String> {
+    let sdp_init = RtcSessionDescriptionInit::new(RtcSdpType::Offer);
+    sdp_init.set_sdp(sdp);
+    JsFuture::from(pc.set_remote_description(&sdp_init))
+        .await
+        .map_err(|e| format!("Failed to set remote description for offer: {:?}", e))?;
+    log::info!("Remote description set for offer.");
+    let early = std::mem::take(&mut *pending_candidates.borrow_mut());
+    add_remote_candidates(pc, early).await;
+
+    let answer = JsFuture::from(pc.create_answer())
+        .await
+        .map_err(|e| format!("Failed to create answer: {:?}", e))?;
+    let sdp_answer = RtcSessionDescription::from(answer).sdp();
+    let sdp_answer_init = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
+    sdp_answer_init.set_sdp(&sdp_answer);
+    JsFuture::from(pc.set_local_description(&sdp_answer_init))
+        .await
+        .map_err(|e| format!("Failed to set local description for answer: {:?}", e))?;
+    log::info!("Local description set for answer.");
+    Ok(sdp_answer)
+}
+
+/// Takes the peer's answer to our offer and adds the candidates that came
+/// before it.
+async fn apply_answer(
+    pc: &RtcPeerConnection,
+    pending_candidates: &RefCell<Vec<IceCandidate>>,
+    sdp: &str,
+) -> Result<(), String> {
+    let sdp_init = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
+    sdp_init.set_sdp(sdp);
+    JsFuture::from(pc.set_remote_description(&sdp_init))
+        .await
+        .map_err(|e| format!("Failed to set remote description for answer: {:?}", e))?;
+    log::info!("Remote description set for answer.");
+    let early = std::mem::take(&mut *pending_candidates.borrow_mut());
+    add_remote_candidates(pc, early).await;
+    Ok(())
+}
+
+/// Waits until ICE gathering is complete, or [`MANUAL_GATHER_TIMEOUT_MS`]
+/// passed, and returns the local description with every candidate in it.
+async fn gather_candidates(pc: &RtcPeerConnection) -> Result<String, String> {
+    if pc.ice_gathering_state() != RtcIceGatheringState::Complete {
+        let done = js_sys::Promise::new(&mut |resolve, _| {
+            let pc_for_cb = pc.clone();
+            let resolve_on_complete = resolve.clone();
+            let on_change = Closure::wrap(Box::new(move || {
+                if pc_for_cb.ice_gathering_state() == RtcIceGatheringState::Complete {
+                    let _ = resolve_on_complete.call0(&JsValue::NULL);
+                }
+            }) as Box<dyn FnMut()>);
+            let _ = pc.add_event_listener_with_callback(
+                "icegatheringstatechange",
+                on_change.as_ref().unchecked_ref(),
+            );
+            on_change.forget();
+            Timeout::new(MANUAL_GATHER_TIMEOUT_MS, move || {
+                let _ = resolve.call0(&JsValue::NULL);
+            })
+            .forget();
+        });
+        let _ = JsFuture::from(done).await;
     }
+    pc.local_description()
+        .map(|description| description.sdp())
+        .ok_or_else(|| "No local description to share".to_string())
 }
@@ -1,6 +1,6 @@
 use crate::components::access_layout::AccessLayout;
 use crate::router::router::Route;
-use crate::services::api::{self, UserResponse};
+use crate::services::api::{self, LanPeer, UserResponse};
 use crate::utils::auth::get_auth_token;
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
@@ -15,20 +15,28 @@ pub struct ConnectedUsersProps {
 #[function_component(ConnectedUsers)]
 pub fn connected_users(props: &ConnectedUsersProps) -> Html {
     let connected_users = use_state(|| Vec::<UserResponse>::new());
+    // Found on the local network, reachable even when the backend isn't
+    let lan_peers = use_state(Vec::<LanPeer>::new);
     let loading = use_state(|| false);
     let error = use_state(|| Option::<String>::None);
 
     let refresh = {
         let connected_users = connected_users.clone();
+        let lan_peers = lan_peers.clone();
         let loading = loading.clone();
         let error = error.clone();
         Callback::from(move |_| {
             let connected_users = connected_users.clone();
+            let lan_peers = lan_peers.clone();
             let loading = loading.clone();
             let error = error.clone();
             loading.set(true);
             spawn_local(async move {
                 if let Some(token) = get_auth_token() {
+                    match api::get_lan_peers(token.clone()).await {
+                        Ok(peers) => lan_peers.set(peers),
+                        Err(e) => log::error!("Failed to fetch local peers: {}", e),
+                    }
                     match api::get_connected_users(token).await {
                         Ok(users) => {
                             connected_users.set(users);
@@ -57,6 +65,11 @@ pub fn connected_users(props: &ConnectedUsersProps) -> Html {
             <section>
                 <h2>{"Connected Users"}</h2>
                 {"Users whose device can be accessed by you"}
+                <p>
+                    <Link<Route> to={Route::ManualCanvas}>
+                        {"No server? Connect by exchanging codes"}
+                    </Link<Route>>
+                </p>
                 {if *loading {
                     html! { <p>{"Loading connected users..."}</p> }
                 } else if let Some(err) = &*error {
@@ -89,6 +102,31 @@ pub fn connected_users(props: &ConnectedUsersProps) -> Html {
                     }
                 }}
             </section>
+            if lan_peers.iter().any(|peer| !connected_users.iter().any(|user| user.id == peer.user_id)) {
+                <section>
+                    <h2>{"On This Network"}</h2>
+                    {"Found nearby, reachable even while the server is down"}
+                    <div class="connected-users-grid">
+                        { for lan_peers.iter()
+                            .filter(|peer| !connected_users.iter().any(|user| user.id == peer.user_id))
+                            .map(|peer| html! {
+                                <div class="user-card">
+                                    <div class="user-card-header">
+                                        <h3 class="user-name">{&peer.name}</h3>
+                                    </div>
+                                    <div class="user-card-body">
+                                        <p class="user-detail-id">{"ID: "}{&peer.user_id}</p>
+                                    </div>
+                                    <div class="user-card-footer">
+                                        <Link<Route> to={Route::Canvas { id: peer.user_id.clone() }}>
+                                            <div class="btn-accept">{"Connect"}</div>
+                                        </Link<Route>>
+                                    </div>
+                                </div>
+                            }) }
+                    </div>
+                </section>
+            }
         </AccessLayout>
     }
 }
@@ -1,10 +1,14 @@
 use crate::components::access_layout::AccessLayout;
-use crate::services::api::{fetch_inbox, get_connected_users, send_signal};
+use crate::router::router::Route;
+use crate::services::api::{get_connected_users, send_signal, Signal};
+use crate::services::share_requests::{self, use_share_requests};
+use crate::services::signal_bus;
 use crate::utils::auth::get_auth_token;
-use gloo::timers::callback::Interval;
+use crate::utils::share_access::ShareAccess;
 use std::collections::HashMap;
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
+use yew_router::prelude::use_navigator;
 
 #[derive(Properties, PartialEq)]
 pub struct ScreenShareRequestsProps {
@@ -12,15 +16,10 @@ pub struct ScreenShareRequestsProps {
     pub toggle_theme: Callback<()>,
 }
 
-#[derive(Clone, PartialEq)]
-struct ScreenShareRequest {
-    from_user_id: String,
-    from_user_name: String,
-}
-
 #[function_component(ScreenShareRequests)]
 pub fn screen_share_requests(props: &ScreenShareRequestsProps) -> Html {
-    let requests = use_state(|| Vec::<ScreenShareRequest>::new());
+    let navigator = use_navigator().unwrap();
+    let requests = use_share_requests();
     let connected_users = use_state(|| HashMap::<String, String>::new()); // ID -> Name map
     let loading = use_state(|| false);
     let error = use_state(|| Option::<String>::None);
@@ -47,101 +46,37 @@ pub fn screen_share_requests(props: &ScreenShareRequestsProps) -> Html {
         });
     }
 
-    // Poll for incoming screen share requests
+    // An approval the viewer never acknowledged
     {
-        let requests = requests.clone();
-        let connected_users = connected_users.clone();
         let error = error.clone();
 
         use_effect_with((), move |_| {
-            let requests = requests.clone();
-            let connected_users = connected_users.clone();
-            let error = error.clone();
-
-            let interval = Interval::new(2000, move || {
-                let requests = requests.clone();
-                let connected_users = connected_users.clone();
-                let error = error.clone();
-
-                spawn_local(async move {
-                    if let Some(token) = get_auth_token() {
-                        match fetch_inbox(token).await {
-                            Ok(messages) => {
-                                let mut screen_requests = Vec::new();
-
-                                for msg in messages {
-                                    // Look for "request_screen_share" signals
-                                    if msg.signal_type == "request_screen_share" {
-                                        // Look up name from connected users map
-                                        let name = connected_users
-                                            .get(&msg.from_user_id)
-                                            .cloned()
-                                            .unwrap_or_else(|| "Unknown User".to_string());
-
-                                        screen_requests.push(ScreenShareRequest {
-                                            from_user_id: msg.from_user_id,
-                                            from_user_name: name,
-                                        });
-                                    }
-                                }
-
-                                if !screen_requests.is_empty() {
-                                    requests.set(screen_requests);
-                                }
-                            }
-                            Err(e) => {
-                                log::error!("Failed to fetch inbox: {:?}", e);
-                                error.set(Some(format!("Failed to check for requests: {}", e)));
-                            }
-                        }
-                    }
-                });
+            let failures = signal_bus::subscribe_failures(move |failure| {
+                if failure.signal_type == "screen_share_approved" {
+                    error.set(Some(failure.message()));
+                }
             });
 
             move || {
-                drop(interval);
+                drop(failures);
             }
         });
     }
 
+    // Share with the viewer once it was told, on the page we host it from
     let on_approve = {
-        let requests = requests.clone();
         let error = error.clone();
 
-        Callback::from(move |user_id: String| {
-            let requests = requests.clone();
+        Callback::from(move |(user_id, access): (String, ShareAccess)| {
             let error = error.clone();
-            let user_id_clone = user_id.clone();
+            let navigator = navigator.clone();
 
             spawn_local(async move {
-                if let Some(token) = get_auth_token() {
-                    // Send approval signal
-                    let mut payload = HashMap::new();
-                    payload.insert("approved".to_string(), serde_json::json!(true));
-
-                    match send_signal(
-                        token,
-                        user_id_clone.clone(),
-                        "screen_share_approved".to_string(),
-                        serde_json::json!(payload),
-                    )
-                    .await
-                    {
-                        Ok(_) => {
-                            log::info!("Screen share approved for user: {}", user_id_clone);
-                            // Remove the request from the list
-                            requests.set(
-                                (*requests)
-                                    .iter()
-                                    .filter(|r| r.from_user_id != user_id_clone)
-                                    .cloned()
-                                    .collect(),
-                            );
-                        }
-                        Err(e) => {
-                            log::error!("Failed to send approval: {:?}", e);
-                            error.set(Some(format!("Failed to approve: {}", e)));
-                        }
+                match share_requests::approve(&user_id, access).await {
+                    Ok(()) => navigator.push(&Route::HostCanvas { id: user_id }),
+                    Err(e) => {
+                        log::error!("Failed to send approval: {:?}", e);
+                        error.set(Some(format!("Failed to approve: {}", e)));
                     }
                 }
             });
@@ -149,38 +84,29 @@ pub fn screen_share_requests(props: &ScreenShareRequestsProps) -> Html {
     };
 
     let on_reject = {
-        let requests = requests.clone();
         let error = error.clone();
 
         Callback::from(move |user_id: String| {
-            let requests = requests.clone();
             let error = error.clone();
             let user_id_clone = user_id.clone();
+            let Some(session_id) = share_requests::session_of(&user_id) else {
+                return;
+            };
 
             spawn_local(async move {
                 if let Some(token) = get_auth_token() {
                     // Send rejection signal
-                    let mut payload = HashMap::new();
-                    payload.insert("approved".to_string(), serde_json::json!(false));
-
                     match send_signal(
                         token,
                         user_id_clone.clone(),
-                        "screen_share_rejected".to_string(),
-                        serde_json::json!(payload),
+                        session_id,
+                        Signal::ScreenShareRejected,
                     )
                     .await
                     {
                         Ok(_) => {
                             log::info!("Screen share rejected for user: {}", user_id_clone);
-                            // Remove the request from the list
-                            requests.set(
-                                (*requests)
-                                    .iter()
-                                    .filter(|r| r.from_user_id != user_id_clone)
-                                    .cloned()
-                                    .collect(),
-                            );
+                            share_requests::remove(&user_id_clone);
                         }
                         Err(e) => {
                             log::error!("Failed to send rejection: {:?}", e);
@@ -213,15 +139,19 @@ pub fn screen_share_requests(props: &ScreenShareRequestsProps) -> Html {
                     html! {
                         <div class="connected-users-grid">
                             { for requests.iter().map(|req| {
-                                let user_id_approve = req.from_user_id.clone();
+                                let user_id_view = req.from_user_id.clone();
+                                let user_id_type = req.from_user_id.clone();
                                 let user_id_reject = req.from_user_id.clone();
-                                let on_approve = on_approve.clone();
+                                let on_approve_view = on_approve.clone();
+                                let on_approve_type = on_approve.clone();
                                 let on_reject = on_reject.clone();
 
                                 html! {
                                     <div class="user-card screen-share-request">
                                         <div class="user-card-header">
-                                            <h3 class="user-name">{&req.from_user_name}</h3>
+                                            <h3 class="user-name">
+                                                {connected_users.get(&req.from_user_id).cloned().unwrap_or_else(|| "Unknown User".to_string())}
+                                            </h3>
                                             <span class="request-badge">{"🔔 Screen Share Request"}</span>
                                         </div>
                                         <div class="user-card-body">
@@ -230,10 +160,18 @@ pub fn screen_share_requests(props: &ScreenShareRequestsProps) -> Html {
                                         </div>
                                         <div class="user-card-footer button-group">
                                             <button
-                                                onclick={Callback::from(move |_| on_approve.emit(user_id_approve.clone()))}
+                                                onclick={Callback::from(move |_| on_approve_view.emit((user_id_view.clone(), ShareAccess::ReadOnly)))}
+                                                class="btn-accept"
+                                                title="Approve; the viewer can watch but not type"
+                                            >
+                                                {"✓ View only"}
+                                            </button>
+                                            <button
+                                                onclick={Callback::from(move |_| on_approve_type.emit((user_id_type.clone(), ShareAccess::Interactive)))}
                                                 class="btn-accept"
+                                                title="Approve; the viewer can also type into shared terminals"
                                             >
-                                                {"✓ Approve & Share"}
+                                                {"✓ Can type"}
                                             </button>
                                             <button
                                                 onclick={Callback::from(move |_| on_reject.emit(user_id_reject.clone()))}
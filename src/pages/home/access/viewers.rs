@@ -1,6 +1,7 @@
 use crate::components::access_layout::AccessLayout;
 use crate::services::api::{self, UserResponse};
 use crate::utils::auth::get_auth_token;
+use crate::utils::share_access::{get_share_access, set_share_access, ShareAccess};
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 
@@ -15,6 +16,7 @@ pub fn viewers(props: &ViewersProps) -> Html {
     let viewers = use_state(|| Vec::<UserResponse>::new());
     let loading = use_state(|| false);
     let error = use_state(|| Option::<String>::None);
+    let force_update = use_force_update();
 
     let refresh = {
         let viewers = viewers.clone();
@@ -42,6 +44,19 @@ pub fn viewers(props: &ViewersProps) -> Html {
         })
     };
 
+    // Flip a viewer between read-only and interactive terminal access
+    let toggle_access = {
+        let force_update = force_update.clone();
+        Callback::from(move |viewer_id: String| {
+            let access = match get_share_access(&viewer_id) {
+                ShareAccess::ReadOnly => ShareAccess::Interactive,
+                ShareAccess::Interactive => ShareAccess::ReadOnly,
+            };
+            set_share_access(&viewer_id, access);
+            force_update.force_update();
+        })
+    };
+
     use_effect_with((), {
         let refresh = refresh.clone();
         move |_| {
@@ -65,6 +80,9 @@ pub fn viewers(props: &ViewersProps) -> Html {
                     html! {
                         <div class="connected-users-grid"> // Reusing the same grid style
                             { for viewers.iter().map(|viewer| {
+                                let access = get_share_access(&viewer.id);
+                                let viewer_id = viewer.id.clone();
+                                let toggle_access = toggle_access.clone();
                                 html! {
                                     <div class="user-card"> // Reusing the same card style
                                         <div class="user-card-header">
@@ -73,6 +91,15 @@ pub fn viewers(props: &ViewersProps) -> Html {
                                         <div class="user-card-body">
                                             <p class="user-detail-id">{"ID: "}{&viewer.id}</p>
                                             <p class="user-detail-email">{"Email: "}{&viewer.email}</p>
+                                            <p class="user-detail-access">{"Terminal access: "}{access.label()}</p>
+                                        </div>
+                                        <div class="user-card-footer">
+                                            <button
+                                                onclick={Callback::from(move |_| toggle_access.emit(viewer_id.clone()))}
+                                                class="btn-cancel"
+                                            >
+                                                {if access.can_type() { "Make view only" } else { "Allow typing" }}
+                                            </button>
                                         </div>
                                     </div>
                                 }
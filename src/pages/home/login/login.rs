@@ -1,7 +1,7 @@
 use crate::components::pixel_art::pixel_art::PixelArt;
 use crate::router::router::Route;
 use crate::services::api;
-use crate::utils::auth::store_auth_token;
+use crate::utils::auth::{store_auth_token, store_user_email};
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 use yew_router::prelude::*;
@@ -46,6 +46,7 @@ pub fn login() -> Html {
             }
 
             let email_val = (*email).clone();
+            let user_email = email_val.clone();
             let password_val = (*password).clone();
             let error = error.clone();
             let navigator = navigator.clone();
@@ -54,6 +55,7 @@ pub fn login() -> Html {
                 match api::login(email_val, password_val).await {
                     Ok(response) => {
                         store_auth_token(&response.token);
+                        store_user_email(&user_email);
                         navigator.push(&Route::Access);
                     }
                     Err(_) => {
@@ -31,6 +31,10 @@ pub enum Route {
     ScreenShareRequests,
     #[at("/canvas/:id")]
     Canvas { id: String },
+    #[at("/canvas/:id/host")]
+    HostCanvas { id: String },
+    #[at("/manual-canvas")]
+    ManualCanvas,
     #[not_found]
     #[at("/404")]
     NotFound,
@@ -57,6 +61,12 @@ pub fn switch(routes: Route, dark_mode: bool, toggle_theme: Callback<()>) -> Htm
         Route::Canvas { id } => {
             html! { <CanvasPage id={id} dark_mode={dark_mode} toggle_theme={toggle_theme} /> }
         }
+        Route::HostCanvas { id } => {
+            html! { <CanvasPage id={id} host=true dark_mode={dark_mode} toggle_theme={toggle_theme} /> }
+        }
+        Route::ManualCanvas => html! {
+            <CanvasPage id={String::new()} manual=true dark_mode={dark_mode} toggle_theme={toggle_theme} />
+        },
         Route::NotFound => html! { <h1>{ "404" }</h1> },
     }
 }
@@ -1,5 +1,4 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -47,6 +46,14 @@ pub struct UserResponse {
     pub email: String,
 }
 
+/// Another app found on the local network, see `get_lan_peers`.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LanPeer {
+    pub user_id: String,
+    pub name: String,
+}
+
 #[derive(Serialize)]
 pub struct TokenArg {
     pub token: String,
@@ -60,15 +67,30 @@ pub struct RequestAccessArgs {
 }
 
 // WebRTC Signaling structures
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SignalMessage {
-    #[serde(rename = "fromUserId")]
-    pub from_user_id: String,
-    #[serde(rename = "toUserId")]
+pub use share_protocol::{IceCandidate, IceConfig, Signal, SignalMessage};
+
+/// A signal of ours the recipient never acknowledged, as the `signal-failure` event carries it.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryFailure {
     pub to_user_id: String,
-    #[serde(rename = "type")]
+    pub session_id: String,
     pub signal_type: String,
-    pub payload: HashMap<String, serde_json::Value>,
+    pub reason: String,
+}
+
+impl DeliveryFailure {
+    /// What to tell the user about it
+    pub fn message(&self) -> String {
+        let what = match self.signal_type.as_str() {
+            "offer" => "The connection offer",
+            "answer" => "The connection answer",
+            "screen_share_approved" => "The approval",
+            "hangup" => "The hang-up",
+            _ => "A signal",
+        };
+        format!("{} could not be delivered ({})", what, self.reason)
+    }
 }
 
 // API service functions
@@ -106,6 +128,39 @@ pub async fn get_connected_users(token: String) -> Result<Vec<UserResponse>, Str
         .map_err(|e| format!("Failed to get connected users: {}", e))
 }
 
+/// Apps on the local network, which can be reached without the backend.
+pub async fn get_lan_peers(token: String) -> Result<Vec<LanPeer>, String> {
+    let args = serde_wasm_bindgen::to_value(&TokenArg { token })
+        .map_err(|e| format!("Failed to serialize token arg: {}", e))?;
+
+    let result = invoke("get_lan_peers", args).await;
+
+    serde_wasm_bindgen::from_value(result).map_err(|e| format!("Failed to get local peers: {}", e))
+}
+
+/// ICE servers to set up peer connections with, TURN credentials included.
+pub async fn get_ice_config(token: String) -> Result<IceConfig, String> {
+    let args = serde_wasm_bindgen::to_value(&TokenArg { token })
+        .map_err(|e| format!("Failed to serialize token arg: {}", e))?;
+
+    let result = invoke("get_ice_config", args).await;
+
+    serde_wasm_bindgen::from_value(result).map_err(|e| format!("Failed to get ICE servers: {}", e))
+}
+
+/// How connections behave once they are up, see `get_connection_settings`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ConnectionSettings {
+    pub reconnect_deadline_secs: u64,
+}
+
+pub async fn get_connection_settings() -> Result<ConnectionSettings, String> {
+    let result = invoke("get_connection_settings", JsValue::NULL).await;
+
+    serde_wasm_bindgen::from_value(result)
+        .map_err(|e| format!("Failed to get connection settings: {}", e))
+}
+
 pub async fn get_viewers(token: String) -> Result<Vec<UserResponse>, String> {
     let args = serde_wasm_bindgen::to_value(&TokenArg { token })
         .map_err(|e| format!("Failed to serialize token arg: {}", e))?;
@@ -215,22 +270,21 @@ pub async fn cancel_request(token: String, request_id: String) -> Result<(), Str
 pub struct SendSignalArgs {
     pub token: String,
     pub to_user_id: String,
-    #[serde(rename = "type")]
-    pub signal_type: String,
-    pub payload: serde_json::Value,
+    pub session_id: String,
+    pub signal: Signal,
 }
 
 pub async fn send_signal(
     token: String,
     to_user_id: String,
-    signal_type: String,
-    payload: serde_json::Value,
+    session_id: String,
+    signal: Signal,
 ) -> Result<(), String> {
     let args = serde_wasm_bindgen::to_value(&SendSignalArgs {
         token,
         to_user_id,
-        signal_type,
-        payload,
+        session_id,
+        signal,
     })
     .map_err(|e| format!("Failed to serialize send_signal args: {}", e))?;
 
@@ -245,12 +299,155 @@ pub async fn send_signal(
     }
 }
 
-pub async fn fetch_inbox(token: String) -> Result<Vec<SignalMessage>, String> {
+/// Has the backend receive signals for the user `token` belongs to and emit
+/// them as events, see `signal_bus`.
+pub async fn watch_inbox(token: String) -> Result<(), String> {
     let args = serde_wasm_bindgen::to_value(&TokenArg { token })
-        .map_err(|e| format!("Failed to serialize fetch_inbox args: {}", e))?;
+        .map_err(|e| format!("Failed to serialize watch_inbox args: {}", e))?;
+
+    let result = invoke("watch_inbox", args).await;
+
+    if result.is_undefined() || result.is_null() {
+        Ok(())
+    } else {
+        Err(format!("Failed to watch inbox: {:?}", result))
+    }
+}
+
+// Terminal sessions
+#[derive(Serialize)]
+pub struct StartTerminalArgs {
+    pub cols: u16,
+    pub rows: u16,
+    pub shell: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct WriteTerminalArgs {
+    pub id: String,
+    pub data: String,
+}
+
+#[derive(Serialize)]
+pub struct ResizeTerminalArgs {
+    pub id: String,
+    pub cols: u16,
+    pub rows: u16,
+}
 
-    let result = invoke("fetch_inbox", args).await;
+#[derive(Serialize)]
+pub struct TerminalIdArg {
+    pub id: String,
+}
+
+/// Payload of the `terminal-output` event
+#[derive(Deserialize, Clone, Debug)]
+pub struct TerminalOutput {
+    pub id: String,
+    pub data: String,
+}
 
-    serde_wasm_bindgen::from_value::<Vec<SignalMessage>>(result)
-        .map_err(|e| format!("Failed to fetch inbox: {}", e))
+/// Payload of the `terminal-exit` event
+#[derive(Deserialize, Clone, Debug)]
+pub struct TerminalExit {
+    pub id: String,
+    pub code: Option<u32>,
+}
+
+/// Payload of the `terminal-recording-error` event
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct TerminalRecordingError {
+    pub id: String,
+    pub path: String,
+    pub error: String,
+}
+
+pub async fn start_terminal(cols: u16, rows: u16, shell: Option<String>) -> Result<String, String> {
+    let args = serde_wasm_bindgen::to_value(&StartTerminalArgs { cols, rows, shell })
+        .map_err(|e| format!("Failed to serialize start_terminal args: {}", e))?;
+
+    let result = invoke("start_terminal", args).await;
+
+    serde_wasm_bindgen::from_value(result).map_err(|e| format!("Failed to start terminal: {}", e))
+}
+
+pub async fn write_terminal(id: String, data: String) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&WriteTerminalArgs { id, data })
+        .map_err(|e| format!("Failed to serialize write_terminal args: {}", e))?;
+
+    let result = invoke("write_terminal", args).await;
+
+    if result.is_undefined() || result.is_null() {
+        Ok(())
+    } else {
+        serde_wasm_bindgen::from_value(result)
+            .map(|s: String| Err(format!("Write terminal failed: {}", s)))
+            .unwrap_or_else(|_| Err("Write terminal failed with unknown error".to_string()))
+    }
+}
+
+pub async fn resize_terminal(id: String, cols: u16, rows: u16) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&ResizeTerminalArgs { id, cols, rows })
+        .map_err(|e| format!("Failed to serialize resize_terminal args: {}", e))?;
+
+    let result = invoke("resize_terminal", args).await;
+
+    if result.is_undefined() || result.is_null() {
+        Ok(())
+    } else {
+        serde_wasm_bindgen::from_value(result)
+            .map(|s: String| Err(format!("Resize terminal failed: {}", s)))
+            .unwrap_or_else(|_| Err("Resize terminal failed with unknown error".to_string()))
+    }
+}
+
+pub async fn kill_terminal(id: String) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&TerminalIdArg { id })
+        .map_err(|e| format!("Failed to serialize kill_terminal args: {}", e))?;
+
+    let result = invoke("kill_terminal", args).await;
+
+    if result.is_undefined() || result.is_null() {
+        Ok(())
+    } else {
+        serde_wasm_bindgen::from_value(result)
+            .map(|s: String| Err(format!("Kill terminal failed: {}", s)))
+            .unwrap_or_else(|_| Err("Kill terminal failed with unknown error".to_string()))
+    }
+}
+
+/// Starts recording a terminal and returns the path of the `.cast` file
+pub async fn start_recording(id: String) -> Result<String, String> {
+    let args = serde_wasm_bindgen::to_value(&TerminalIdArg { id })
+        .map_err(|e| format!("Failed to serialize start_recording args: {}", e))?;
+
+    let result = invoke("start_recording", args).await;
+
+    serde_wasm_bindgen::from_value(result).map_err(|e| format!("Failed to start recording: {}", e))
+}
+
+/// Stops recording a terminal and returns the path of the finished recording
+pub async fn stop_recording(id: String) -> Result<String, String> {
+    let args = serde_wasm_bindgen::to_value(&TerminalIdArg { id })
+        .map_err(|e| format!("Failed to serialize stop_recording args: {}", e))?;
+
+    let result = invoke("stop_recording", args).await;
+
+    serde_wasm_bindgen::from_value(result).map_err(|e| format!("Failed to stop recording: {}", e))
+}
+
+#[derive(Serialize)]
+pub struct StatsReportArg {
+    pub report: String,
+}
+
+/// Saves connection stats for a bug report and returns the path of the file
+pub async fn save_connection_stats(report: String) -> Result<String, String> {
+    let args = serde_wasm_bindgen::to_value(&StatsReportArg { report })
+        .map_err(|e| format!("Failed to serialize save_connection_stats args: {}", e))?;
+
+    let result = invoke("save_connection_stats", args).await;
+
+    serde_wasm_bindgen::from_value(result)
+        .map_err(|e| format!("Failed to save connection stats: {}", e))
 }
@@ -0,0 +1,50 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = ["window", "__TAURI__", "event"], js_name = listen)]
+    async fn tauri_listen(event: &str, handler: &JsValue) -> JsValue;
+}
+
+#[derive(Deserialize)]
+struct TauriEvent<T> {
+    payload: T,
+}
+
+/// A subscription to a backend event. Unsubscribes when dropped.
+pub struct EventListener {
+    unlisten: Option<js_sys::Function>,
+    _handler: Closure<dyn FnMut(JsValue)>,
+}
+
+impl Drop for EventListener {
+    fn drop(&mut self) {
+        if let Some(unlisten) = self.unlisten.take() {
+            let _ = unlisten.call0(&JsValue::NULL);
+        }
+    }
+}
+
+/// Listens for a Tauri event emitted by the backend and hands its payload to `handler`.
+pub async fn listen<T, F>(event: &str, mut handler: F) -> EventListener
+where
+    T: DeserializeOwned + 'static,
+    F: FnMut(T) + 'static,
+{
+    let event_name = event.to_string();
+    let closure = Closure::wrap(Box::new(move |js_event: JsValue| {
+        match serde_wasm_bindgen::from_value::<TauriEvent<T>>(js_event) {
+            Ok(event) => handler(event.payload),
+            Err(e) => log::error!("Failed to parse {} event: {}", event_name, e),
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let unlisten = tauri_listen(event, closure.as_ref()).await;
+
+    EventListener {
+        unlisten: unlisten.dyn_into().ok(),
+        _handler: closure,
+    }
+}
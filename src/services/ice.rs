@@ -0,0 +1,79 @@
+use super::api::{get_ice_config, IceConfig};
+use crate::utils::auth::get_auth_token;
+use std::cell::RefCell;
+use wasm_bindgen::JsValue;
+use web_sys::RtcConfiguration;
+
+thread_local! {
+    // The public STUN server until the backend tells us better
+    static ICE_CONFIG: RefCell<IceConfig> = RefCell::new(IceConfig::default());
+}
+
+/// Loads the ICE servers in the background, so the first peer connection
+/// doesn't start out without TURN.
+pub fn start() {
+    wasm_bindgen_futures::spawn_local(async {
+        refresh().await;
+    });
+}
+
+/// The configuration for a new peer connection, with the ICE servers last loaded.
+pub fn rtc_configuration() -> RtcConfiguration {
+    ICE_CONFIG.with(|config| to_rtc_configuration(&config.borrow()))
+}
+
+/// Loads the ICE servers again, TURN credentials run out. None if they
+/// couldn't be loaded and the last ones still apply.
+pub async fn refresh() -> Option<RtcConfiguration> {
+    let token = get_auth_token()?;
+    match get_ice_config(token).await {
+        Ok(config) => {
+            let rtc_config = to_rtc_configuration(&config);
+            ICE_CONFIG.with(|current| *current.borrow_mut() = config);
+            Some(rtc_config)
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            None
+        }
+    }
+}
+
+fn to_rtc_configuration(config: &IceConfig) -> RtcConfiguration {
+    let ice_servers = js_sys::Array::new();
+    for server in &config.servers {
+        let ice_server_obj = js_sys::Object::new();
+        let urls: js_sys::Array = server
+            .urls
+            .iter()
+            .map(|url| JsValue::from_str(url))
+            .collect();
+        let _ = js_sys::Reflect::set(&ice_server_obj, &JsValue::from_str("urls"), &urls);
+        if let Some(username) = &server.username {
+            let _ = js_sys::Reflect::set(
+                &ice_server_obj,
+                &JsValue::from_str("username"),
+                &JsValue::from_str(username),
+            );
+        }
+        if let Some(credential) = &server.credential {
+            let _ = js_sys::Reflect::set(
+                &ice_server_obj,
+                &JsValue::from_str("credential"),
+                &JsValue::from_str(credential),
+            );
+        }
+        ice_servers.push(&ice_server_obj);
+    }
+
+    let rtc_config = RtcConfiguration::new();
+    rtc_config.set_ice_servers(&ice_servers);
+    if config.relay_only {
+        let _ = js_sys::Reflect::set(
+            &rtc_config,
+            &JsValue::from_str("iceTransportPolicy"),
+            &JsValue::from_str("relay"),
+        );
+    }
+    rtc_config
+}
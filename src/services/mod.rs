@@ -1,4 +1,11 @@
 pub mod api;
+pub mod events;
+pub mod ice;
+pub mod quality;
+pub mod settings;
+pub mod share_requests;
+pub mod signal_bus;
+pub mod stats;
 
 // Re-export for convenience
 pub use api::*;
@@ -0,0 +1,209 @@
+use super::stats::{read_stats, summarize};
+pub use share_protocol::{QualityPreset, VideoQuality};
+use std::cell::RefCell;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    DisplayMediaStreamConstraints, MediaStreamTrack, MediaTrackConstraints, RtcPeerConnection,
+    RtcRtpEncodingParameters, RtcRtpSender,
+};
+
+/// Frame rate a screen is captured at until a viewer asks for something else
+const DEFAULT_FRAME_RATE: u32 = 30;
+/// Auto mode's caps by bandwidth estimate: below `kbps`, at most `height`
+/// lines at `frame_rate`. Above the last step nothing is capped.
+const AUTO_STEPS: [(f64, u32, u32); 3] = [(300.0, 480, 10), (1000.0, 720, 15), (2500.0, 1080, 30)];
+
+/// Limits the shared screen's video is held to.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+struct Caps {
+    max_height: Option<u32>,
+    max_frame_rate: Option<u32>,
+    max_bitrate_kbps: Option<u32>,
+    // MediaStreamTrack.contentHint, what the encoder optimizes for
+    content_hint: &'static str,
+    // RTCRtpSendParameters.degradationPreference, what gives when bandwidth runs short
+    degradation: &'static str,
+}
+
+impl Caps {
+    fn for_quality(quality: &VideoQuality, estimate_kbps: Option<f64>) -> Self {
+        let preset = match quality.preset {
+            QualityPreset::Auto => estimate_kbps.map(auto_caps).unwrap_or_default(),
+            QualityPreset::TextClarity => Caps {
+                max_frame_rate: Some(15),
+                content_hint: "text",
+                degradation: "maintain-resolution",
+                ..Caps::default()
+            },
+            QualityPreset::Motion => Caps {
+                max_height: Some(720),
+                max_frame_rate: Some(30),
+                content_hint: "motion",
+                degradation: "maintain-framerate",
+                ..Caps::default()
+            },
+            QualityPreset::Custom => Caps::default(),
+        };
+        Caps {
+            max_height: tighter(preset.max_height, quality.max_height),
+            max_frame_rate: tighter(preset.max_frame_rate, quality.max_frame_rate),
+            max_bitrate_kbps: tighter(preset.max_bitrate_kbps, quality.max_bitrate_kbps),
+            ..preset
+        }
+    }
+}
+
+fn auto_caps(estimate_kbps: f64) -> Caps {
+    let step = AUTO_STEPS.iter().find(|(kbps, _, _)| estimate_kbps < *kbps);
+    Caps {
+        max_height: step.map(|(_, height, _)| *height),
+        max_frame_rate: step.map(|(_, _, frame_rate)| *frame_rate),
+        degradation: "balanced",
+        ..Caps::default()
+    }
+}
+
+fn tighter(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The host's side of a viewer's quality request: what was asked for, and
+/// the caps last applied for it.
+#[derive(Default)]
+pub struct QualityControl {
+    requested: VideoQuality,
+    applied: Option<Caps>,
+}
+
+impl QualityControl {
+    pub fn request(&mut self, quality: VideoQuality) {
+        self.requested = quality;
+    }
+
+    /// Forgets what was applied, for a new connection whose senders start out uncapped.
+    pub fn reset(&mut self) {
+        self.applied = None;
+    }
+}
+
+/// Fits the screen video sent on `pc` to the request, if that calls for
+/// other caps than last time. Auto mode goes by the current bandwidth
+/// estimate, so it's worth calling again every now and then.
+pub async fn apply(
+    control: &RefCell<QualityControl>,
+    pc: &RtcPeerConnection,
+) -> Result<(), String> {
+    let quality = control.borrow().requested;
+    let estimate_kbps = if quality.preset == QualityPreset::Auto {
+        summarize(&read_stats(pc).await?, None).available_outgoing_kbps
+    } else {
+        None
+    };
+    let caps = Caps::for_quality(&quality, estimate_kbps);
+    if control.borrow().applied == Some(caps) {
+        return Ok(());
+    }
+
+    for sender in pc.get_senders().iter() {
+        let sender: RtcRtpSender = sender.unchecked_into();
+        match sender.track() {
+            Some(track) if track.kind() == "video" => apply_caps(&sender, &track, &caps).await?,
+            _ => {}
+        }
+    }
+    log::info!("Applied video caps {:?}", caps);
+    control.borrow_mut().applied = Some(caps);
+    Ok(())
+}
+
+async fn apply_caps(
+    sender: &RtcRtpSender,
+    track: &MediaStreamTrack,
+    caps: &Caps,
+) -> Result<(), String> {
+    let _ = js_sys::Reflect::set(
+        track,
+        &JsValue::from_str("contentHint"),
+        &JsValue::from_str(caps.content_hint),
+    );
+
+    // Capturing fewer frames saves the host the work of encoding them
+    let constraints = MediaTrackConstraints::new();
+    constraints.set_frame_rate(&max_constraint(
+        caps.max_frame_rate.unwrap_or(DEFAULT_FRAME_RATE),
+    ));
+    let applied = track
+        .apply_constraints_with_constraints(&constraints)
+        .map_err(|e| format!("Failed to constrain screen capture: {:?}", e))?;
+    JsFuture::from(applied)
+        .await
+        .map_err(|e| format!("Failed to constrain screen capture: {:?}", e))?;
+
+    // Resolution is scaled down by the encoder, so the capture stays sharp for the next request
+    let captured_height = track.get_settings().get_height().unwrap_or_default() as f32;
+    let scale = caps
+        .max_height
+        .map(|height| (captured_height / height as f32).max(1.0))
+        .unwrap_or(1.0);
+    let parameters = sender.get_parameters();
+    for encoding in parameters.get_encodings().unwrap_or_default().iter() {
+        let encoding: RtcRtpEncodingParameters = encoding.unchecked_into();
+        encoding.set_scale_resolution_down_by(scale);
+        match caps.max_bitrate_kbps {
+            Some(kbps) => encoding.set_max_bitrate(kbps.saturating_mul(1000)),
+            None => {
+                let _ =
+                    js_sys::Reflect::delete_property(&encoding, &JsValue::from_str("maxBitrate"));
+            }
+        }
+        match caps.max_frame_rate {
+            Some(frame_rate) => {
+                let _ = js_sys::Reflect::set(
+                    &encoding,
+                    &JsValue::from_str("maxFramerate"),
+                    &JsValue::from(frame_rate),
+                );
+            }
+            None => {
+                let _ =
+                    js_sys::Reflect::delete_property(&encoding, &JsValue::from_str("maxFramerate"));
+            }
+        }
+    }
+    if !caps.degradation.is_empty() {
+        let _ = js_sys::Reflect::set(
+            &parameters,
+            &JsValue::from_str("degradationPreference"),
+            &JsValue::from_str(caps.degradation),
+        );
+    }
+    JsFuture::from(sender.set_parameters_with_parameters(&parameters))
+        .await
+        .map_err(|e| format!("Failed to set video parameters: {:?}", e))?;
+    Ok(())
+}
+
+/// What the screen is first captured with, before any viewer asked for less.
+pub fn display_media_constraints() -> DisplayMediaStreamConstraints {
+    let video = MediaTrackConstraints::new();
+    video.set_frame_rate(&max_constraint(DEFAULT_FRAME_RATE));
+    let constraints = DisplayMediaStreamConstraints::new();
+    constraints.set_video_media_track_constraints(&video);
+    constraints.set_audio_bool(false);
+    constraints
+}
+
+/// A `{ max: value }` constraint.
+fn max_constraint(value: u32) -> JsValue {
+    let constraint = js_sys::Object::new();
+    let _ = js_sys::Reflect::set(
+        &constraint,
+        &JsValue::from_str("max"),
+        &JsValue::from(value),
+    );
+    constraint.into()
+}
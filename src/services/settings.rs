@@ -0,0 +1,28 @@
+use super::api::get_connection_settings;
+use std::cell::Cell;
+
+// Until the backend tells us otherwise, matching its default
+const DEFAULT_RECONNECT_DEADLINE_SECS: u64 = 60;
+
+thread_local! {
+    static RECONNECT_DEADLINE_SECS: Cell<u64> = const { Cell::new(DEFAULT_RECONNECT_DEADLINE_SECS) };
+}
+
+/// Loads the connection settings in the background. They only change
+/// with the settings file, so once is enough.
+pub fn start() {
+    wasm_bindgen_futures::spawn_local(async {
+        match get_connection_settings().await {
+            Ok(settings) => {
+                RECONNECT_DEADLINE_SECS.with(|secs| secs.set(settings.reconnect_deadline_secs))
+            }
+            Err(e) => log::error!("{}", e),
+        }
+    });
+}
+
+/// How long to try bringing back a dropped connection before giving up.
+pub fn reconnect_deadline_ms() -> u32 {
+    let secs = RECONNECT_DEADLINE_SECS.with(Cell::get);
+    u32::try_from(secs.saturating_mul(1000)).unwrap_or(u32::MAX)
+}
@@ -0,0 +1,138 @@
+use super::api::{send_signal, Signal};
+use super::signal_bus::{self, SignalSubscription};
+use crate::utils::auth::get_auth_token;
+use crate::utils::share_access::{set_share_access, ShareAccess};
+use crate::utils::share_session::set_approved_session;
+use std::cell::RefCell;
+use std::rc::Rc;
+use yew::prelude::*;
+
+type Watcher = Rc<dyn Fn()>;
+
+/// A viewer waiting for us to answer its screen share request.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ScreenShareRequest {
+    pub from_user_id: String,
+    pub session_id: String,
+}
+
+#[derive(Default)]
+struct Requests {
+    pending: Vec<ScreenShareRequest>,
+    watchers: Vec<(u64, Watcher)>,
+    next_id: u64,
+    subscription: Option<SignalSubscription>,
+}
+
+thread_local! {
+    static REQUESTS: RefCell<Requests> = RefCell::new(Requests::default());
+}
+
+/// Starts collecting screen share requests. Called once when the app starts,
+/// so they are noticed whichever page is open.
+pub fn start() {
+    if REQUESTS.with(|requests| requests.borrow().subscription.is_some()) {
+        return;
+    }
+    let subscription = signal_bus::subscribe(&["request_screen_share", "hangup"], None, |msg| {
+        update(|pending| {
+            if msg.signal == Signal::Hangup {
+                // The viewer left before we answered
+                pending.retain(|r| r.session_id != msg.session_id);
+            } else if let Some(request) = pending
+                .iter_mut()
+                .find(|r| r.from_user_id == msg.from_user_id)
+            {
+                // Asking twice doesn't make two requests, the latest session is the live one
+                request.session_id = msg.session_id;
+            } else {
+                pending.push(ScreenShareRequest {
+                    from_user_id: msg.from_user_id,
+                    session_id: msg.session_id,
+                });
+            }
+        })
+    });
+    REQUESTS.with(|requests| requests.borrow_mut().subscription = Some(subscription));
+}
+
+/// Requests still waiting for an answer, oldest first.
+pub fn pending() -> Vec<ScreenShareRequest> {
+    REQUESTS.with(|requests| requests.borrow().pending.clone())
+}
+
+/// Session of the pending request from `user_id`, which the answer belongs to.
+pub fn session_of(user_id: &str) -> Option<String> {
+    REQUESTS.with(|requests| {
+        requests
+            .borrow()
+            .pending
+            .iter()
+            .find(|r| r.from_user_id == user_id)
+            .map(|r| r.session_id.clone())
+    })
+}
+
+/// Forgets the request from `user_id` once it's answered.
+pub fn remove(user_id: &str) {
+    update(|pending| pending.retain(|r| r.from_user_id != user_id));
+}
+
+/// Lets `user_id` in with `access`, and forgets its request once the viewer
+/// was told.
+pub async fn approve(user_id: &str, access: ShareAccess) -> Result<(), String> {
+    let session_id =
+        session_of(user_id).ok_or_else(|| format!("No pending request from {}", user_id))?;
+
+    let token = get_auth_token().ok_or_else(|| "Not authenticated".to_string())?;
+    send_signal(
+        token,
+        user_id.to_string(),
+        session_id.clone(),
+        Signal::ScreenShareApproved { access },
+    )
+    .await?;
+    // Remember the grant so the host enforces it once the viewer connects,
+    // and the session it joins. Not before the viewer was told, or a failed
+    // approval would still let it in.
+    set_share_access(user_id, access);
+    set_approved_session(user_id, &session_id);
+    log::info!("Screen share approved for user: {} ({:?})", user_id, access);
+    remove(user_id);
+    Ok(())
+}
+
+fn update(change: impl FnOnce(&mut Vec<ScreenShareRequest>)) {
+    // Watchers re-render, so the store can't stay borrowed while they run
+    let watchers: Vec<Watcher> = REQUESTS.with(|requests| {
+        let mut requests = requests.borrow_mut();
+        change(&mut requests.pending);
+        requests
+            .watchers
+            .iter()
+            .map(|(_, watcher)| watcher.clone())
+            .collect()
+    });
+    for watcher in watchers {
+        watcher();
+    }
+}
+
+/// The pending requests, re-rendering the component whenever they change.
+#[hook]
+pub fn use_share_requests() -> Vec<ScreenShareRequest> {
+    let force_update = use_force_update();
+    use_effect_with((), move |_| {
+        let id = REQUESTS.with(|requests| {
+            let mut requests = requests.borrow_mut();
+            requests.next_id += 1;
+            let id = requests.next_id;
+            requests
+                .watchers
+                .push((id, Rc::new(move || force_update.force_update())));
+            id
+        });
+        move || REQUESTS.with(|requests| requests.borrow_mut().watchers.retain(|(w, _)| *w != id))
+    });
+    pending()
+}
@@ -0,0 +1,282 @@
+use super::api::{watch_inbox, DeliveryFailure, SignalMessage};
+use super::events::{listen, EventListener};
+use crate::utils::auth::get_auth_token;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use wasm_bindgen_futures::spawn_local;
+
+// Emitted by the backend's inbox task
+const EVENT_SIGNAL: &str = "signal";
+const EVENT_SIGNAL_FAILURE: &str = "signal-failure";
+const EVENT_INBOX_ERROR: &str = "inbox-error";
+const EVENT_KEY_TRUSTED: &str = "key-trusted";
+// Messages nobody has subscribed to yet are kept this long for whoever subscribes next
+const UNCLAIMED_TTL_MS: f64 = 60_000.0;
+const MAX_UNCLAIMED: usize = 100;
+// Closed sessions are remembered this long, the peer stops retrying its signals well before
+const CLOSED_TTL_MS: f64 = 5.0 * 60_000.0;
+const MAX_CLOSED: usize = 100;
+
+type Handler = Rc<RefCell<dyn FnMut(SignalMessage)>>;
+type ErrorHandler = Rc<RefCell<dyn FnMut(&str)>>;
+type FailureHandler = Rc<RefCell<dyn FnMut(&DeliveryFailure)>>;
+
+struct Subscriber {
+    id: u64,
+    types: Vec<String>,
+    from_user_id: Option<String>,
+    handler: Handler,
+}
+
+impl Subscriber {
+    fn wants(&self, message: &SignalMessage) -> bool {
+        self.types.iter().any(|t| t == message.signal.kind())
+            && self
+                .from_user_id
+                .as_ref()
+                .is_none_or(|from| *from == message.from_user_id)
+    }
+}
+
+#[derive(Default)]
+struct Bus {
+    subscribers: Vec<Subscriber>,
+    error_handlers: Vec<(u64, ErrorHandler)>,
+    failure_handlers: Vec<(u64, FailureHandler)>,
+    next_id: u64,
+    // Received messages nobody wanted yet, with the time they arrived
+    unclaimed: VecDeque<(f64, SignalMessage)>,
+    // Sessions hung up by either side, with the time they were closed, whatever
+    // still arrives for them is stale
+    closed: VecDeque<(f64, String)>,
+    started: bool,
+    listeners: Vec<EventListener>,
+}
+
+impl Bus {
+    fn is_closed(&mut self, session_id: &str) -> bool {
+        let now = js_sys::Date::now();
+        self.closed
+            .retain(|(closed, _)| now - closed < CLOSED_TTL_MS);
+        self.closed.iter().any(|(_, id)| id == session_id)
+    }
+}
+
+thread_local! {
+    static BUS: RefCell<Bus> = RefCell::new(Bus::default());
+}
+
+/// A registration with the signal bus. Unsubscribes when dropped.
+pub struct SignalSubscription {
+    id: u64,
+}
+
+impl Drop for SignalSubscription {
+    fn drop(&mut self) {
+        BUS.with(|bus| {
+            let mut bus = bus.borrow_mut();
+            bus.subscribers.retain(|s| s.id != self.id);
+            bus.error_handlers.retain(|(id, _)| *id != self.id);
+            bus.failure_handlers.retain(|(id, _)| *id != self.id);
+        });
+    }
+}
+
+/// Starts taking the signals the backend's inbox task emits. Called once
+/// when the app starts, so nothing is missed whichever page is open.
+pub fn start() {
+    if BUS.with(|bus| std::mem::replace(&mut bus.borrow_mut().started, true)) {
+        return;
+    }
+    spawn_local(async {
+        let listeners = vec![
+            listen(EVENT_SIGNAL, dispatch).await,
+            listen(EVENT_SIGNAL_FAILURE, |failure: DeliveryFailure| {
+                report_failure(&failure)
+            })
+            .await,
+            listen(EVENT_INBOX_ERROR, |e: String| report_error(&e)).await,
+            listen(EVENT_KEY_TRUSTED, |user_id: String| {
+                log::info!("Trusting signal key of {}", user_id)
+            })
+            .await,
+        ];
+        BUS.with(|bus| bus.borrow_mut().listeners = listeners);
+        watch();
+    });
+}
+
+/// Asks the backend to receive for the logged in user, e.g. when the app
+/// starts with a stored token. Logging in does this on its own.
+pub fn watch() {
+    let Some(token) = get_auth_token() else {
+        return;
+    };
+    spawn_local(async move {
+        if let Err(e) = watch_inbox(token).await {
+            log::error!("{}", e);
+        }
+    });
+}
+
+/// Hands every received message of one of `types` to `handler`, optionally
+/// only those sent by `from_user_id`, so pages don't take each other's
+/// messages. Matching messages that arrived while nobody was subscribed are
+/// delivered right away.
+pub fn subscribe(
+    types: &[&str],
+    from_user_id: Option<&str>,
+    handler: impl FnMut(SignalMessage) + 'static,
+) -> SignalSubscription {
+    let subscriber = Subscriber {
+        id: next_id(),
+        types: types.iter().map(|t| t.to_string()).collect(),
+        from_user_id: from_user_id.map(str::to_string),
+        handler: Rc::new(RefCell::new(handler)),
+    };
+    let id = subscriber.id;
+
+    let waiting = BUS.with(|bus| {
+        let mut bus = bus.borrow_mut();
+        let now = js_sys::Date::now();
+        bus.unclaimed
+            .retain(|(received, _)| now - received < UNCLAIMED_TTL_MS);
+        let (waiting, rest): (Vec<_>, Vec<_>) = bus
+            .unclaimed
+            .drain(..)
+            .partition(|(_, message)| subscriber.wants(message));
+        bus.unclaimed = rest.into();
+
+        let handler = subscriber.handler.clone();
+        bus.subscribers.push(subscriber);
+        (handler, waiting)
+    });
+
+    let (handler, waiting) = waiting;
+    for (_, message) in waiting {
+        (handler.borrow_mut())(message);
+    }
+    SignalSubscription { id }
+}
+
+/// Calls `handler` whenever the backend fails to fetch the inbox, e.g. to
+/// send the user back to the login page.
+pub fn subscribe_errors(handler: impl FnMut(&str) + 'static) -> SignalSubscription {
+    let id = next_id();
+    BUS.with(|bus| {
+        bus.borrow_mut()
+            .error_handlers
+            .push((id, Rc::new(RefCell::new(handler))))
+    });
+    SignalSubscription { id }
+}
+
+/// Calls `handler` for every signal of ours that was never acknowledged,
+/// e.g. to tell the user the handshake is stuck.
+pub fn subscribe_failures(handler: impl FnMut(&DeliveryFailure) + 'static) -> SignalSubscription {
+    let id = next_id();
+    BUS.with(|bus| {
+        bus.borrow_mut()
+            .failure_handlers
+            .push((id, Rc::new(RefCell::new(handler))))
+    });
+    SignalSubscription { id }
+}
+
+/// Drops every message for `session_id` from now on, including those
+/// already waiting for a subscriber.
+pub fn close_session(session_id: &str) {
+    BUS.with(|bus| {
+        let mut bus = bus.borrow_mut();
+        bus.unclaimed
+            .retain(|(_, message)| message.session_id != session_id);
+        if bus.is_closed(session_id) {
+            return;
+        }
+        if bus.closed.len() >= MAX_CLOSED {
+            bus.closed.pop_front();
+        }
+        bus.closed
+            .push_back((js_sys::Date::now(), session_id.to_string()));
+    });
+}
+
+fn next_id() -> u64 {
+    BUS.with(|bus| {
+        let mut bus = bus.borrow_mut();
+        bus.next_id += 1;
+        bus.next_id
+    })
+}
+
+fn report_failure(failure: &DeliveryFailure) {
+    log::error!(
+        "{} to {} was not delivered: {}",
+        failure.signal_type,
+        failure.to_user_id,
+        failure.reason
+    );
+    let handlers: Vec<FailureHandler> = BUS.with(|bus| {
+        bus.borrow()
+            .failure_handlers
+            .iter()
+            .map(|(_, handler)| handler.clone())
+            .collect()
+    });
+    for handler in handlers {
+        (handler.borrow_mut())(failure);
+    }
+}
+
+fn report_error(e: &str) {
+    log::error!("Failed to fetch inbox: {:?}", e);
+    let handlers: Vec<ErrorHandler> = BUS.with(|bus| {
+        bus.borrow()
+            .error_handlers
+            .iter()
+            .map(|(_, handler)| handler.clone())
+            .collect()
+    });
+    for handler in handlers {
+        (handler.borrow_mut())(e);
+    }
+}
+
+fn dispatch(message: SignalMessage) {
+    // Handlers may subscribe or unsubscribe, so the bus can't stay borrowed while they run
+    let handlers: Vec<Handler> = BUS.with(|bus| {
+        let mut bus = bus.borrow_mut();
+        if bus.is_closed(&message.session_id) {
+            log::info!(
+                "Dropping {} from {}, session {} is closed",
+                message.signal.kind(),
+                message.from_user_id,
+                message.session_id
+            );
+            return Vec::new();
+        }
+        let handlers: Vec<Handler> = bus
+            .subscribers
+            .iter()
+            .filter(|s| s.wants(&message))
+            .map(|s| s.handler.clone())
+            .collect();
+        if handlers.is_empty() {
+            log::info!(
+                "Keeping {} from {} until someone subscribes",
+                message.signal.kind(),
+                message.from_user_id
+            );
+            if bus.unclaimed.len() >= MAX_UNCLAIMED {
+                bus.unclaimed.pop_front();
+            }
+            bus.unclaimed
+                .push_back((js_sys::Date::now(), message.clone()));
+        }
+        handlers
+    });
+    for handler in handlers {
+        (handler.borrow_mut())(message.clone());
+    }
+}
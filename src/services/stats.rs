@@ -0,0 +1,139 @@
+use js_sys::JSON;
+use serde::Serialize;
+use serde_json::Value;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::RtcPeerConnection;
+
+/// What one reading of `getStats()` says about a peer connection, with rates
+/// worked out against the reading before it.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsSample {
+    /// Milliseconds since the epoch, as stamped by the browser
+    pub timestamp: f64,
+    pub inbound_kbps: Option<f64>,
+    pub outbound_kbps: Option<f64>,
+    /// How much the browser thinks it could send, what auto quality goes by
+    pub available_outgoing_kbps: Option<f64>,
+    pub frames_per_second: Option<f64>,
+    pub resolution: Option<(u32, u32)>,
+    pub rtt_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    /// Of the video packets since the connection started
+    pub packet_loss_percent: Option<f64>,
+    /// Types of the candidate pair in use: host, srflx, prflx or relay
+    pub local_candidate: Option<String>,
+    pub remote_candidate: Option<String>,
+    // Byte counters of the pair in use, which the next sample's rates come from
+    #[serde(skip)]
+    bytes_received: f64,
+    #[serde(skip)]
+    bytes_sent: f64,
+}
+
+/// Every stat in the report of `pc`, as the browser writes it.
+pub async fn read_stats(pc: &RtcPeerConnection) -> Result<Vec<Value>, String> {
+    let report = JsFuture::from(pc.get_stats())
+        .await
+        .map_err(|e| format!("Failed to get connection stats: {:?}", e))?;
+    let entries = js_sys::try_iter(&report)
+        .map_err(|e| format!("Failed to read connection stats: {:?}", e))?
+        .ok_or_else(|| "Connection stats are not iterable".to_string())?;
+
+    let mut stats = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read connection stats: {:?}", e))?;
+        // Entries are [id, stat] pairs
+        let stat = js_sys::Array::from(&entry).get(1);
+        let Some(json) = JSON::stringify(&stat)
+            .ok()
+            .and_then(|json| json.as_string())
+        else {
+            continue;
+        };
+        match serde_json::from_str(&json) {
+            Ok(stat) => stats.push(stat),
+            Err(e) => log::warn!("Skipping unreadable stat: {}", e),
+        }
+    }
+    Ok(stats)
+}
+
+/// Boils `report` down to the numbers worth watching.
+pub fn summarize(report: &[Value], previous: Option<&StatsSample>) -> StatsSample {
+    let by_id = |id: &str| report.iter().find(|stat| stat["id"] == id);
+    let of_type = |kind: &'static str| report.iter().filter(move |stat| stat["type"] == kind);
+    let video = |kind: &'static str| of_type(kind).find(|stat| stat["kind"] == "video");
+
+    let mut sample = StatsSample {
+        timestamp: report
+            .iter()
+            .find_map(|stat| stat["timestamp"].as_f64())
+            .unwrap_or_default(),
+        ..StatsSample::default()
+    };
+
+    // The pair ICE settled on: named by the transport, or marked on the pair itself in Firefox
+    let pair = of_type("transport")
+        .find_map(|transport| transport["selectedCandidatePairId"].as_str())
+        .and_then(by_id)
+        .or_else(|| {
+            of_type("candidate-pair").find(|pair| {
+                pair["selected"] == true
+                    || (pair["nominated"] == true && pair["state"] == "succeeded")
+            })
+        });
+    if let Some(pair) = pair {
+        sample.bytes_received = pair["bytesReceived"].as_f64().unwrap_or_default();
+        sample.bytes_sent = pair["bytesSent"].as_f64().unwrap_or_default();
+        sample.available_outgoing_kbps = pair["availableOutgoingBitrate"]
+            .as_f64()
+            .map(|bps| bps / 1000.0);
+        sample.rtt_ms = pair["currentRoundTripTime"]
+            .as_f64()
+            .map(|secs| secs * 1000.0);
+        let candidate_type = |field: &str| {
+            pair[field]
+                .as_str()
+                .and_then(by_id)
+                .and_then(|candidate| candidate["candidateType"].as_str())
+                .map(str::to_string)
+        };
+        sample.local_candidate = candidate_type("localCandidateId");
+        sample.remote_candidate = candidate_type("remoteCandidateId");
+    }
+
+    if let Some(previous) = previous {
+        let secs = (sample.timestamp - previous.timestamp) / 1000.0;
+        // Counters start over on a new peer connection
+        let kbps = |bytes: f64, before: f64| (bytes - before).max(0.0) * 8.0 / 1000.0 / secs;
+        if secs > 0.0 {
+            sample.inbound_kbps = Some(kbps(sample.bytes_received, previous.bytes_received));
+            sample.outbound_kbps = Some(kbps(sample.bytes_sent, previous.bytes_sent));
+        }
+    }
+
+    // A viewer's video comes in, a host's goes out
+    let inbound = video("inbound-rtp");
+    if let Some(video) = inbound.or_else(|| video("outbound-rtp")) {
+        sample.frames_per_second = video["framesPerSecond"].as_f64();
+        if let (Some(width), Some(height)) =
+            (video["frameWidth"].as_u64(), video["frameHeight"].as_u64())
+        {
+            sample.resolution = Some((width as u32, height as u32));
+        }
+    }
+    if let Some(inbound) = inbound {
+        sample.jitter_ms = inbound["jitter"].as_f64().map(|secs| secs * 1000.0);
+        let lost = inbound["packetsLost"].as_f64().unwrap_or_default();
+        let received = inbound["packetsReceived"].as_f64().unwrap_or_default();
+        if lost + received > 0.0 {
+            sample.packet_loss_percent = Some(lost.max(0.0) / (lost + received) * 100.0);
+        }
+    } else if let Some(remote) = video("remote-inbound-rtp") {
+        // What the viewer reported back about our video
+        sample.jitter_ms = remote["jitter"].as_f64().map(|secs| secs * 1000.0);
+        sample.packet_loss_percent = remote["fractionLost"].as_f64().map(|f| f * 100.0);
+    }
+    sample
+}
@@ -0,0 +1,38 @@
+use super::protocol::TerminalFrame;
+use share_protocol::FrameParts;
+use wasm_bindgen::prelude::*;
+use web_sys::{MessageEvent, RtcDataChannel, RtcDataChannelState};
+
+/// Sends a frame if the channel is open, returning an error otherwise.
+/// Long frames, like snapshots, are sent in parts.
+pub fn send_frame(channel: &RtcDataChannel, frame: &TerminalFrame) -> Result<(), String> {
+    if channel.ready_state() != RtcDataChannelState::Open {
+        return Err("Terminal channel is not open".to_string());
+    }
+    for message in frame.to_messages() {
+        channel
+            .send_with_str(&message)
+            .map_err(|e| format!("Failed to send terminal frame: {:?}", e))?;
+    }
+    Ok(())
+}
+
+/// Calls `handler` with every frame received on the channel, once all its
+/// parts are in if it was sent in parts.
+pub fn on_frame(channel: &RtcDataChannel, mut handler: impl FnMut(TerminalFrame) + 'static) {
+    let mut parts = FrameParts::default();
+    let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+        let Some(text) = event.data().as_string() else {
+            log::warn!("Ignoring non-text terminal frame");
+            return;
+        };
+        match parts.receive(&text) {
+            Ok(Some(frame)) => handler(frame),
+            Ok(None) => {}
+            Err(e) => log::error!("{}", e),
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+
+    channel.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+}
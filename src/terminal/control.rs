@@ -0,0 +1,132 @@
+use super::protocol::ControlState;
+
+/// Time limits the host can approve a control request with, `None` for no limit
+pub const CONTROL_TIME_LIMITS: [(Option<u32>, &str); 3] = [
+    (Some(5 * 60), "5 min"),
+    (Some(15 * 60), "15 min"),
+    (None, "No limit"),
+];
+
+/// Who is driving the shared terminals, with the deadline in local
+/// `Date.now()` milliseconds while a viewer has control. `yours` is set on
+/// the viewer holding it, never on the host.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Control {
+    #[default]
+    Host,
+    Viewer {
+        until: Option<f64>,
+        yours: bool,
+    },
+}
+
+impl Control {
+    /// A viewer holding control, as the host sees it.
+    pub fn viewer(seconds: Option<u32>) -> Self {
+        Control::Viewer {
+            until: deadline(seconds),
+            yours: false,
+        }
+    }
+
+    pub fn from_state(state: ControlState) -> Self {
+        match state {
+            ControlState::Host => Control::Host,
+            ControlState::Viewer {
+                seconds_left,
+                yours,
+            } => Control::Viewer {
+                until: deadline(seconds_left),
+                yours,
+            },
+        }
+    }
+
+    /// What a viewer is told, `yours` when it's the one holding control.
+    pub fn to_state(self, yours: bool) -> ControlState {
+        match self {
+            Control::Host => ControlState::Host,
+            Control::Viewer { .. } => ControlState::Viewer {
+                seconds_left: self.seconds_left(),
+                yours,
+            },
+        }
+    }
+
+    pub fn is_viewer(self) -> bool {
+        matches!(self, Control::Viewer { .. })
+    }
+
+    /// Whether the viewer this was sent to holds control.
+    pub fn is_yours(self) -> bool {
+        matches!(self, Control::Viewer { yours: true, .. })
+    }
+
+    /// Seconds until control returns to the host, if it was given for a limited time.
+    pub fn seconds_left(self) -> Option<u32> {
+        match self {
+            Control::Viewer {
+                until: Some(until), ..
+            } => Some(((until - js_sys::Date::now()) / 1000.0).ceil().max(0.0) as u32),
+            _ => None,
+        }
+    }
+
+    /// "Host has control", "Viewer has control (4:59 left)" and so on, from
+    /// the point of view of `host` or a viewer.
+    pub fn label(self, host: bool) -> String {
+        let holder = match (self, host) {
+            (Control::Host, true) | (Control::Viewer { yours: true, .. }, false) => {
+                "You have control"
+            }
+            (Control::Host, false) => "Host has control",
+            (Control::Viewer { .. }, true) => "Viewer has control",
+            (Control::Viewer { .. }, false) => "Another viewer has control",
+        };
+        match self.seconds_left() {
+            Some(seconds) => format!("{} ({}:{:02} left)", holder, seconds / 60, seconds % 60),
+            None => holder.to_string(),
+        }
+    }
+}
+
+fn deadline(seconds: Option<u32>) -> Option<f64> {
+    seconds.map(|seconds| js_sys::Date::now() + seconds as f64 * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewer_state(yours: bool) -> ControlState {
+        ControlState::Viewer {
+            seconds_left: None,
+            yours,
+        }
+    }
+
+    #[test]
+    fn only_the_holder_is_told_it_has_control() {
+        let holder = Control::from_state(viewer_state(true));
+        let other = Control::from_state(viewer_state(false));
+        assert_eq!(holder.label(false), "You have control");
+        assert_eq!(other.label(false), "Another viewer has control");
+        assert!(holder.is_yours() && !other.is_yours());
+        assert!(other.is_viewer());
+    }
+
+    #[test]
+    fn labels_the_host_side() {
+        assert_eq!(Control::Host.label(true), "You have control");
+        assert_eq!(Control::Host.label(false), "Host has control");
+        assert_eq!(Control::viewer(None).label(true), "Viewer has control");
+    }
+
+    #[test]
+    fn tells_each_viewer_whether_it_holds_control() {
+        let control = Control::viewer(None);
+        assert_eq!(control.to_state(true), viewer_state(true));
+        assert_eq!(control.to_state(false), viewer_state(false));
+        assert_eq!(Control::Host.to_state(true), ControlState::Host);
+    }
+}
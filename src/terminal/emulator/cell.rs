@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Color {
+    #[default]
+    Default,
+    /// One of the 256 xterm palette entries
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Resolves the color to RGB, or `None` for the terminal default.
+    pub fn to_rgb(self) -> Option<(u8, u8, u8)> {
+        match self {
+            Color::Default => None,
+            Color::Indexed(index) => Some(palette_rgb(index)),
+            Color::Rgb(r, g, b) => Some((r, g, b)),
+        }
+    }
+}
+
+/// Text attributes set through SGR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Attrs(u16);
+
+impl Attrs {
+    pub const BOLD: Attrs = Attrs(1 << 0);
+    pub const DIM: Attrs = Attrs(1 << 1);
+    pub const ITALIC: Attrs = Attrs(1 << 2);
+    pub const UNDERLINE: Attrs = Attrs(1 << 3);
+    pub const BLINK: Attrs = Attrs(1 << 4);
+    pub const INVERSE: Attrs = Attrs(1 << 5);
+    pub const HIDDEN: Attrs = Attrs(1 << 6);
+    pub const STRIKETHROUGH: Attrs = Attrs(1 << 7);
+
+    pub fn contains(self, other: Attrs) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Attrs) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Attrs) {
+        self.0 &= !other.0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub attrs: Attrs,
+}
+
+/// One character cell of the screen.
+///
+/// Wide (CJK, emoji) characters take two cells: the character itself with
+/// `width == 2`, followed by a spacer cell with `width == 0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub width: u8,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            width: 1,
+            style: Style::default(),
+        }
+    }
+}
+
+impl Cell {
+    /// An empty cell that keeps the background of `style`, as erase operations do.
+    pub fn blank(style: Style) -> Self {
+        Self {
+            ch: ' ',
+            width: 1,
+            style: Style {
+                fg: Color::Default,
+                bg: style.bg,
+                attrs: Attrs::default(),
+            },
+        }
+    }
+
+    pub fn spacer(style: Style) -> Self {
+        Self {
+            ch: ' ',
+            width: 0,
+            style,
+        }
+    }
+
+    pub fn is_spacer(&self) -> bool {
+        self.width == 0
+    }
+}
+
+// The 16 base colors, close to xterm's defaults
+const BASE_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 49, 49),
+    (13, 188, 121),
+    (229, 229, 16),
+    (36, 114, 200),
+    (188, 63, 188),
+    (17, 168, 205),
+    (229, 229, 229),
+    (102, 102, 102),
+    (241, 76, 76),
+    (35, 209, 139),
+    (245, 245, 67),
+    (59, 142, 234),
+    (214, 112, 214),
+    (41, 184, 219),
+    (255, 255, 255),
+];
+
+/// RGB value of an xterm 256-color palette entry.
+pub fn palette_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => BASE_COLORS[index as usize],
+        16..=231 => {
+            let levels = [0, 95, 135, 175, 215, 255];
+            let i = index - 16;
+            (
+                levels[(i / 36) as usize],
+                levels[((i / 6) % 6) as usize],
+                levels[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
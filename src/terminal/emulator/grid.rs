@@ -0,0 +1,190 @@
+use super::cell::{Cell, Style};
+
+pub type Row = Vec<Cell>;
+
+/// A fixed-size screen of character cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    cols: usize,
+    rows: usize,
+    lines: Vec<Row>,
+}
+
+impl Grid {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            cols,
+            rows,
+            lines: vec![blank_row(cols, Style::default()); rows],
+        }
+    }
+
+    /// Builds a grid from existing lines, padding or cutting them to size.
+    pub fn from_lines(cols: usize, rows: usize, mut lines: Vec<Row>) -> Self {
+        lines.resize(rows, Row::new());
+        for line in &mut lines {
+            line.resize(cols, Cell::default());
+            if line.last().is_some_and(|last| last.width == 2) {
+                line[cols - 1] = Cell::default();
+            }
+        }
+        Self { cols, rows, lines }
+    }
+
+    pub fn row(&self, row: usize) -> &Row {
+        &self.lines[row]
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> &Cell {
+        &self.lines[row][col]
+    }
+
+    /// Text of a row with trailing blanks trimmed.
+    pub fn line_text(&self, row: usize) -> String {
+        row_text(&self.lines[row])
+    }
+
+    /// Writes `cell` at the given position, cleaning up any wide character it
+    /// partially overwrites.
+    pub fn set(&mut self, row: usize, col: usize, cell: Cell) {
+        self.clear_wide_at(row, col);
+        if cell.width == 2 && col + 1 < self.cols {
+            self.clear_wide_at(row, col + 1);
+            self.lines[row][col + 1] = Cell::spacer(cell.style);
+        }
+        self.lines[row][col] = cell;
+    }
+
+    /// Resizes the grid, keeping content anchored to the top-left corner.
+    /// Returns the lines pushed off the top when rows shrink.
+    pub fn resize(&mut self, cols: usize, rows: usize, keep_bottom: usize) -> Vec<Row> {
+        let mut removed = Vec::new();
+        if rows < self.rows {
+            // Prefer dropping lines from the top so the line at `keep_bottom` stays visible
+            let overflow = (keep_bottom + 1).saturating_sub(rows).min(self.rows - rows);
+            removed = self.lines.drain(..overflow).collect();
+            self.lines.truncate(rows);
+        }
+        for line in &mut self.lines {
+            line.resize(cols, Cell::default());
+            if let Some(last) = line.last_mut() {
+                if last.width == 2 {
+                    *last = Cell::default();
+                }
+            }
+        }
+        self.lines.resize(rows, blank_row(cols, Style::default()));
+        self.cols = cols;
+        self.rows = rows;
+        removed
+    }
+
+    /// Scrolls the lines between `top` and `bottom` (inclusive) up by `count`,
+    /// returning the lines that scrolled out.
+    pub fn scroll_up(&mut self, top: usize, bottom: usize, count: usize, style: Style) -> Vec<Row> {
+        let count = count.min(bottom + 1 - top);
+        let removed: Vec<Row> = self.lines.drain(top..top + count).collect();
+        for _ in 0..count {
+            self.lines
+                .insert(bottom + 1 - count, blank_row(self.cols, style));
+        }
+        removed
+    }
+
+    /// Scrolls the lines between `top` and `bottom` (inclusive) down by `count`.
+    pub fn scroll_down(&mut self, top: usize, bottom: usize, count: usize, style: Style) {
+        let count = count.min(bottom + 1 - top);
+        self.lines.drain(bottom + 1 - count..=bottom);
+        for _ in 0..count {
+            self.lines.insert(top, blank_row(self.cols, style));
+        }
+    }
+
+    /// Inserts `count` blanks at `col`, shifting the rest of the row right.
+    pub fn insert_blanks(&mut self, row: usize, col: usize, count: usize, style: Style) {
+        self.clear_wide_at(row, col);
+        let cols = self.cols;
+        let line = &mut self.lines[row];
+        let count = count.min(cols - col);
+        line.truncate(cols - count);
+        for _ in 0..count {
+            line.insert(col, Cell::blank(style));
+        }
+        if let Some(last) = line.last_mut() {
+            if last.width == 2 {
+                *last = Cell::blank(style);
+            }
+        }
+    }
+
+    /// Deletes `count` cells at `col`, shifting the rest of the row left.
+    pub fn delete_chars(&mut self, row: usize, col: usize, count: usize, style: Style) {
+        self.clear_wide_at(row, col);
+        let cols = self.cols;
+        let count = count.min(cols - col);
+        self.clear_wide_at(row, (col + count).min(cols - 1));
+        let line = &mut self.lines[row];
+        line.drain(col..col + count);
+        line.resize(cols, Cell::blank(style));
+    }
+
+    /// Blanks the cells from `start` up to, but not including, `end`.
+    pub fn erase(&mut self, row: usize, start: usize, end: usize, style: Style) {
+        let end = end.min(self.cols);
+        if start >= end {
+            return;
+        }
+        self.clear_wide_at(row, start);
+        self.clear_wide_at(row, end - 1);
+        for cell in &mut self.lines[row][start..end] {
+            *cell = Cell::blank(style);
+        }
+    }
+
+    pub fn erase_all(&mut self, style: Style) {
+        for row in 0..self.rows {
+            self.erase(row, 0, self.cols, style);
+        }
+    }
+
+    /// If the cell at `col` is half of a wide character, blanks the other half.
+    fn clear_wide_at(&mut self, row: usize, col: usize) {
+        let line = &mut self.lines[row];
+        match line[col].width {
+            0 if col > 0 => line[col - 1] = Cell::blank(line[col - 1].style),
+            2 if col + 1 < line.len() => line[col + 1] = Cell::blank(line[col + 1].style),
+            _ => {}
+        }
+    }
+}
+
+pub fn blank_row(cols: usize, style: Style) -> Row {
+    vec![Cell::blank(style); cols]
+}
+
+/// Drops trailing default cells, so stored rows don't keep the width they
+/// had when they were on screen.
+pub fn trim_row(row: &mut Row) {
+    let len = row
+        .iter()
+        .rposition(|cell| *cell != Cell::default())
+        .map_or(0, |last| last + 1);
+    row.truncate(len);
+}
+
+pub fn row_text(row: &Row) -> String {
+    let text: String = row
+        .iter()
+        .filter(|c| !c.is_spacer())
+        .map(|c| c.ch)
+        .collect();
+    text.trim_end().to_string()
+}
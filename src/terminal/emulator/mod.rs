@@ -0,0 +1,789 @@
+pub mod cell;
+pub mod grid;
+pub mod parser;
+pub mod renderer;
+pub mod snapshot;
+
+#[cfg(test)]
+mod tests;
+
+use cell::{Attrs, Cell, Color, Style};
+use grid::{Grid, Row};
+use parser::{Action, CsiSequence, Parser};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use unicode_width::UnicodeWidthChar;
+
+const TAB_WIDTH: usize = 8;
+/// Lines kept above the primary screen once they scroll out of view
+pub const SCROLLBACK_LINES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Cursor {
+    pub row: usize,
+    pub col: usize,
+    /// Set after printing in the last column; the next character wraps first
+    pending_wrap: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+enum Charset {
+    #[default]
+    Ascii,
+    /// DEC special graphics, used by TUIs for line drawing
+    DecSpecial,
+}
+
+impl Charset {
+    fn map(self, c: char) -> char {
+        if self == Charset::Ascii {
+            return c;
+        }
+        match c {
+            '`' => '◆',
+            'a' => '▒',
+            'b' => '␉',
+            'c' => '␌',
+            'd' => '␍',
+            'e' => '␊',
+            'f' => '°',
+            'g' => '±',
+            'h' => '␤',
+            'i' => '␋',
+            'j' => '┘',
+            'k' => '┐',
+            'l' => '┌',
+            'm' => '└',
+            'n' => '┼',
+            'o' => '⎺',
+            'p' => '⎻',
+            'q' => '─',
+            'r' => '⎼',
+            's' => '⎽',
+            't' => '├',
+            'u' => '┤',
+            'v' => '┴',
+            'w' => '┬',
+            'x' => '│',
+            'y' => '≤',
+            'z' => '≥',
+            '{' => 'π',
+            '|' => '≠',
+            '}' => '£',
+            '~' => '·',
+            _ => c,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+struct SavedCursor {
+    cursor: Cursor,
+    pen: Style,
+    origin_mode: bool,
+    charsets: [Charset; 2],
+    active_charset: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Modes {
+    origin: bool,
+    autowrap: bool,
+    insert: bool,
+    linefeed_newline: bool,
+    application_cursor: bool,
+    bracketed_paste: bool,
+    cursor_visible: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Self {
+            origin: false,
+            autowrap: true,
+            insert: false,
+            linefeed_newline: false,
+            application_cursor: false,
+            bracketed_paste: false,
+            cursor_visible: true,
+        }
+    }
+}
+
+/// An xterm-compatible terminal emulator: feed it PTY output, read back the
+/// screen.
+pub struct Terminal {
+    parser: Parser,
+    primary: Grid,
+    alternate: Grid,
+    alternate_active: bool,
+    // Oldest first, bounded by `SCROLLBACK_LINES`
+    scrollback: VecDeque<Row>,
+    cursor: Cursor,
+    saved_primary: SavedCursor,
+    saved_alternate: SavedCursor,
+    pen: Style,
+    scroll_top: usize,
+    scroll_bottom: usize,
+    modes: Modes,
+    tabs: Vec<bool>,
+    charsets: [Charset; 2],
+    active_charset: usize,
+    last_printed: Option<char>,
+    title: String,
+}
+
+impl Terminal {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        let cols = (cols as usize).max(1);
+        let rows = (rows as usize).max(1);
+        Self {
+            parser: Parser::new(),
+            primary: Grid::new(cols, rows),
+            alternate: Grid::new(cols, rows),
+            alternate_active: false,
+            scrollback: VecDeque::new(),
+            cursor: Cursor::default(),
+            saved_primary: SavedCursor::default(),
+            saved_alternate: SavedCursor::default(),
+            pen: Style::default(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            modes: Modes::default(),
+            tabs: default_tabs(cols),
+            charsets: [Charset::Ascii; 2],
+            active_charset: 0,
+            last_printed: None,
+            title: String::new(),
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.screen().cols()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.screen().rows()
+    }
+
+    /// The grid currently being displayed (primary or alternate).
+    pub fn screen(&self) -> &Grid {
+        if self.alternate_active {
+            &self.alternate
+        } else {
+            &self.primary
+        }
+    }
+
+    /// Lines that scrolled off the top of the primary screen, oldest first.
+    pub fn scrollback(&self) -> &VecDeque<Row> {
+        &self.scrollback
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.modes.cursor_visible
+    }
+
+    /// Whether arrow keys should be sent in application mode (`ESC O A`).
+    pub fn application_cursor(&self) -> bool {
+        self.modes.application_cursor
+    }
+
+    /// Whether the program asked for pastes to be wrapped in `ESC [ 200 ~`.
+    pub fn bracketed_paste(&self) -> bool {
+        self.modes.bracketed_paste
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn feed(&mut self, data: &str) {
+        for action in self.parser.feed(data) {
+            match action {
+                Action::Print(c) => self.print(c),
+                Action::Execute(c) => self.execute(c),
+                Action::Csi(csi) => self.csi(&csi),
+                Action::Esc {
+                    intermediates,
+                    final_char,
+                } => self.esc(&intermediates, final_char),
+                Action::Osc(params) => self.osc(&params),
+            }
+        }
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        let cols = (cols as usize).max(1);
+        let rows = (rows as usize).max(1);
+        if cols == self.cols() && rows == self.rows() {
+            return;
+        }
+
+        let keep = self.cursor.row;
+        let (active, inactive) = if self.alternate_active {
+            (&mut self.alternate, &mut self.primary)
+        } else {
+            (&mut self.primary, &mut self.alternate)
+        };
+        let removed = active.resize(cols, rows, keep);
+        inactive.resize(cols, rows, inactive.rows().saturating_sub(1));
+        let removed_count = removed.len();
+        if !self.alternate_active {
+            self.push_scrollback(removed);
+        }
+
+        self.cursor.row = self.cursor.row.saturating_sub(removed_count).min(rows - 1);
+        self.cursor.col = self.cursor.col.min(cols - 1);
+        self.cursor.pending_wrap = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.tabs = default_tabs(cols);
+    }
+
+    fn screen_mut(&mut self) -> &mut Grid {
+        if self.alternate_active {
+            &mut self.alternate
+        } else {
+            &mut self.primary
+        }
+    }
+
+    fn print(&mut self, c: char) {
+        let c = self.charsets[self.active_charset].map(c);
+        let width = match c.width() {
+            Some(w) if w > 0 => w.min(2),
+            // Combining marks and other zero-width characters are not rendered
+            _ => return,
+        };
+        let cols = self.cols();
+
+        if self.cursor.pending_wrap && self.modes.autowrap {
+            self.cursor.col = 0;
+            self.linefeed();
+        }
+        self.cursor.pending_wrap = false;
+
+        if width == 2 && self.cursor.col + 1 >= cols {
+            if cols < 2 {
+                return;
+            }
+            if self.modes.autowrap {
+                // A wide character never straddles the edge, it moves to the next line
+                let (row, col, pen) = (self.cursor.row, self.cursor.col, self.pen);
+                self.screen_mut().erase(row, col, cols, pen);
+                self.cursor.col = 0;
+                self.linefeed();
+            } else {
+                self.cursor.col = cols - 2;
+            }
+        }
+
+        let (row, col, pen) = (self.cursor.row, self.cursor.col, self.pen);
+        if self.modes.insert {
+            self.screen_mut().insert_blanks(row, col, width, pen);
+        }
+        self.screen_mut().set(
+            row,
+            col,
+            Cell {
+                ch: c,
+                width: width as u8,
+                style: pen,
+            },
+        );
+        self.last_printed = Some(c);
+
+        if col + width >= cols {
+            self.cursor.col = cols - 1;
+            self.cursor.pending_wrap = true;
+        } else {
+            self.cursor.col = col + width;
+        }
+    }
+
+    fn execute(&mut self, c: char) {
+        match c {
+            '\x08' => {
+                self.cursor.col = self.cursor.col.saturating_sub(1);
+                self.cursor.pending_wrap = false;
+            }
+            '\t' => self.tab_forward(1),
+            '\n' | '\x0b' | '\x0c' => {
+                if self.modes.linefeed_newline {
+                    self.cursor.col = 0;
+                }
+                self.linefeed();
+            }
+            '\r' => {
+                self.cursor.col = 0;
+                self.cursor.pending_wrap = false;
+            }
+            '\x0e' => self.active_charset = 1,
+            '\x0f' => self.active_charset = 0,
+            _ => {}
+        }
+    }
+
+    fn esc(&mut self, intermediates: &[char], final_char: char) {
+        match (intermediates, final_char) {
+            ([], '7') => self.save_cursor(),
+            ([], '8') => self.restore_cursor(),
+            ([], 'D') => self.linefeed(),
+            ([], 'E') => {
+                self.cursor.col = 0;
+                self.linefeed();
+            }
+            ([], 'M') => self.reverse_index(),
+            ([], 'H') => {
+                let col = self.cursor.col;
+                if let Some(stop) = self.tabs.get_mut(col) {
+                    *stop = true;
+                }
+            }
+            ([], 'c') => self.reset(),
+            (['('], designator) | ([')'], designator) => {
+                let slot = if intermediates[0] == '(' { 0 } else { 1 };
+                self.charsets[slot] = if designator == '0' {
+                    Charset::DecSpecial
+                } else {
+                    Charset::Ascii
+                };
+            }
+            (['#'], '8') => {
+                // DECALN, fill the screen with 'E'
+                let (cols, rows) = (self.cols(), self.rows());
+                for row in 0..rows {
+                    for col in 0..cols {
+                        self.screen_mut().set(
+                            row,
+                            col,
+                            Cell {
+                                ch: 'E',
+                                ..Cell::default()
+                            },
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn osc(&mut self, params: &[String]) {
+        if let [command, title, ..] = params {
+            if command == "0" || command == "2" {
+                self.title = title.clone();
+            }
+        }
+    }
+
+    fn csi(&mut self, csi: &CsiSequence) {
+        if !csi.intermediates.is_empty() {
+            return;
+        }
+        let (cols, rows) = (self.cols(), self.rows());
+        let n = csi.param(0, 1) as usize;
+
+        match (csi.private, csi.final_char) {
+            (None, 'A') => {
+                let top = if self.cursor.row >= self.scroll_top {
+                    self.scroll_top
+                } else {
+                    0
+                };
+                self.move_to(self.cursor.row.saturating_sub(n).max(top), self.cursor.col);
+            }
+            (None, 'B') | (None, 'e') => {
+                let bottom = if self.cursor.row <= self.scroll_bottom {
+                    self.scroll_bottom
+                } else {
+                    rows - 1
+                };
+                self.move_to((self.cursor.row + n).min(bottom), self.cursor.col);
+            }
+            (None, 'C') | (None, 'a') => self.move_to(self.cursor.row, self.cursor.col + n),
+            (None, 'D') => self.move_to(self.cursor.row, self.cursor.col.saturating_sub(n)),
+            (None, 'E') => self.move_to(self.cursor.row + n, 0),
+            (None, 'F') => self.move_to(self.cursor.row.saturating_sub(n), 0),
+            (None, 'G') | (None, '`') => self.move_to(self.cursor.row, n - 1),
+            (None, 'H') | (None, 'f') => {
+                let row = csi.param(0, 1) as usize - 1;
+                let col = csi.param(1, 1) as usize - 1;
+                self.move_to_origin_relative(row, col);
+            }
+            (None, 'd') => {
+                let col = self.cursor.col;
+                self.move_to_origin_relative(n - 1, col);
+            }
+            (None, 'I') => self.tab_forward(n),
+            (None, 'Z') => self.tab_backward(n),
+            (None, 'J') | (Some('?'), 'J') => self.erase_display(csi.raw_param(0).unwrap_or(0)),
+            (None, 'K') | (Some('?'), 'K') => self.erase_line(csi.raw_param(0).unwrap_or(0)),
+            (None, 'L') if self.cursor_in_scroll_region() => {
+                let (row, bottom, pen) = (self.cursor.row, self.scroll_bottom, self.pen);
+                self.screen_mut().scroll_down(row, bottom, n, pen);
+                self.cursor.col = 0;
+            }
+            (None, 'M') if self.cursor_in_scroll_region() => {
+                let (row, bottom, pen) = (self.cursor.row, self.scroll_bottom, self.pen);
+                self.screen_mut().scroll_up(row, bottom, n, pen);
+                self.cursor.col = 0;
+            }
+            (None, '@') => {
+                let (row, col, pen) = (self.cursor.row, self.cursor.col, self.pen);
+                self.screen_mut().insert_blanks(row, col, n, pen);
+            }
+            (None, 'P') => {
+                let (row, col, pen) = (self.cursor.row, self.cursor.col, self.pen);
+                self.screen_mut().delete_chars(row, col, n, pen);
+            }
+            (None, 'X') => {
+                let (row, col, pen) = (self.cursor.row, self.cursor.col, self.pen);
+                self.screen_mut().erase(row, col, col + n, pen);
+            }
+            (None, 'S') => self.scroll_up(n),
+            (None, 'T') => {
+                let (top, bottom, pen) = (self.scroll_top, self.scroll_bottom, self.pen);
+                self.screen_mut().scroll_down(top, bottom, n, pen);
+            }
+            (None, 'b') => {
+                if let Some(c) = self.last_printed {
+                    for _ in 0..n.min(cols * rows) {
+                        self.print(c);
+                    }
+                }
+            }
+            (None, 'g') => match csi.raw_param(0).unwrap_or(0) {
+                0 => {
+                    let col = self.cursor.col;
+                    self.tabs[col] = false;
+                }
+                3 => self.tabs.iter_mut().for_each(|stop| *stop = false),
+                _ => {}
+            },
+            (None, 'm') => self.sgr(csi),
+            (None, 'r') => {
+                let top = csi.param(0, 1) as usize - 1;
+                let bottom = (csi.param(1, rows as u16) as usize).min(rows) - 1;
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_to_origin_relative(0, 0);
+                }
+            }
+            (None, 's') => self.save_cursor(),
+            (None, 'u') => self.restore_cursor(),
+            (None, 'h') | (None, 'l') => {
+                let enable = csi.final_char == 'h';
+                for param in &csi.params {
+                    match param.first() {
+                        Some(4) => self.modes.insert = enable,
+                        Some(20) => self.modes.linefeed_newline = enable,
+                        _ => {}
+                    }
+                }
+            }
+            (Some('?'), 'h') | (Some('?'), 'l') => {
+                let enable = csi.final_char == 'h';
+                for param in &csi.params {
+                    if let Some(&mode) = param.first() {
+                        self.set_private_mode(mode, enable);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enable: bool) {
+        match mode {
+            1 => self.modes.application_cursor = enable,
+            6 => {
+                self.modes.origin = enable;
+                self.move_to_origin_relative(0, 0);
+            }
+            7 => self.modes.autowrap = enable,
+            25 => self.modes.cursor_visible = enable,
+            47 | 1047 => self.set_alternate_screen(enable),
+            1049 => {
+                if enable {
+                    self.save_cursor();
+                    self.set_alternate_screen(true);
+                    let pen = self.pen;
+                    self.alternate.erase_all(pen);
+                } else {
+                    self.set_alternate_screen(false);
+                    self.restore_cursor();
+                }
+            }
+            2004 => self.modes.bracketed_paste = enable,
+            _ => {}
+        }
+    }
+
+    fn set_alternate_screen(&mut self, enable: bool) {
+        if self.alternate_active == enable {
+            return;
+        }
+        self.alternate_active = enable;
+        self.cursor.pending_wrap = false;
+    }
+
+    fn sgr(&mut self, csi: &CsiSequence) {
+        if csi.params.is_empty() {
+            self.pen = Style::default();
+            return;
+        }
+
+        let mut params = csi.params.iter();
+        while let Some(param) = params.next() {
+            let code = param.first().copied().unwrap_or(0);
+            match code {
+                0 => self.pen = Style::default(),
+                1 => self.pen.attrs.insert(Attrs::BOLD),
+                2 => self.pen.attrs.insert(Attrs::DIM),
+                3 => self.pen.attrs.insert(Attrs::ITALIC),
+                4 => {
+                    // 4:0 turns underline off, other sub-styles are drawn as a plain underline
+                    if param.get(1) == Some(&0) {
+                        self.pen.attrs.remove(Attrs::UNDERLINE);
+                    } else {
+                        self.pen.attrs.insert(Attrs::UNDERLINE);
+                    }
+                }
+                5 | 6 => self.pen.attrs.insert(Attrs::BLINK),
+                7 => self.pen.attrs.insert(Attrs::INVERSE),
+                8 => self.pen.attrs.insert(Attrs::HIDDEN),
+                9 => self.pen.attrs.insert(Attrs::STRIKETHROUGH),
+                21 => self.pen.attrs.insert(Attrs::UNDERLINE),
+                22 => {
+                    self.pen.attrs.remove(Attrs::BOLD);
+                    self.pen.attrs.remove(Attrs::DIM);
+                }
+                23 => self.pen.attrs.remove(Attrs::ITALIC),
+                24 => self.pen.attrs.remove(Attrs::UNDERLINE),
+                25 => self.pen.attrs.remove(Attrs::BLINK),
+                27 => self.pen.attrs.remove(Attrs::INVERSE),
+                28 => self.pen.attrs.remove(Attrs::HIDDEN),
+                29 => self.pen.attrs.remove(Attrs::STRIKETHROUGH),
+                30..=37 => self.pen.fg = Color::Indexed((code - 30) as u8),
+                38 => {
+                    if let Some(color) = extended_color(param, &mut params) {
+                        self.pen.fg = color;
+                    }
+                }
+                39 => self.pen.fg = Color::Default,
+                40..=47 => self.pen.bg = Color::Indexed((code - 40) as u8),
+                48 => {
+                    if let Some(color) = extended_color(param, &mut params) {
+                        self.pen.bg = color;
+                    }
+                }
+                49 => self.pen.bg = Color::Default,
+                90..=97 => self.pen.fg = Color::Indexed((code - 90 + 8) as u8),
+                100..=107 => self.pen.bg = Color::Indexed((code - 100 + 8) as u8),
+                _ => {}
+            }
+        }
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        let (row, col, pen) = (self.cursor.row, self.cursor.col, self.pen);
+        let (cols, rows) = (self.cols(), self.rows());
+        let screen = self.screen_mut();
+        match mode {
+            0 => {
+                screen.erase(row, col, cols, pen);
+                for r in row + 1..rows {
+                    screen.erase(r, 0, cols, pen);
+                }
+            }
+            1 => {
+                for r in 0..row {
+                    screen.erase(r, 0, cols, pen);
+                }
+                screen.erase(row, 0, col + 1, pen);
+            }
+            2 => screen.erase_all(pen),
+            // xterm's "erase saved lines"
+            3 => self.scrollback.clear(),
+            _ => {}
+        }
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        let (row, col, pen) = (self.cursor.row, self.cursor.col, self.pen);
+        let cols = self.cols();
+        let screen = self.screen_mut();
+        match mode {
+            0 => screen.erase(row, col, cols, pen),
+            1 => screen.erase(row, 0, col + 1, pen),
+            2 => screen.erase(row, 0, cols, pen),
+            _ => {}
+        }
+    }
+
+    fn linefeed(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows() {
+            self.cursor.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.row == self.scroll_top {
+            let (top, bottom, pen) = (self.scroll_top, self.scroll_bottom, self.pen);
+            self.screen_mut().scroll_down(top, bottom, 1, pen);
+        } else {
+            self.cursor.row = self.cursor.row.saturating_sub(1);
+        }
+    }
+
+    fn cursor_in_scroll_region(&self) -> bool {
+        (self.scroll_top..=self.scroll_bottom).contains(&self.cursor.row)
+    }
+
+    fn scroll_up(&mut self, count: usize) {
+        let (top, bottom, pen) = (self.scroll_top, self.scroll_bottom, self.pen);
+        let removed = self.screen_mut().scroll_up(top, bottom, count, pen);
+        // Only full-width scrolls of the primary screen feed the scrollback, like xterm
+        if top == 0 && !self.alternate_active {
+            self.push_scrollback(removed);
+        }
+    }
+
+    fn push_scrollback(&mut self, rows: Vec<Row>) {
+        for mut row in rows {
+            grid::trim_row(&mut row);
+            self.scrollback.push_back(row);
+        }
+        while self.scrollback.len() > SCROLLBACK_LINES {
+            self.scrollback.pop_front();
+        }
+    }
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        self.cursor.row = row.min(self.rows() - 1);
+        self.cursor.col = col.min(self.cols() - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    /// Moves the cursor, honouring origin mode (rows relative to the scroll region).
+    fn move_to_origin_relative(&mut self, row: usize, col: usize) {
+        if self.modes.origin {
+            let row = (self.scroll_top + row).min(self.scroll_bottom);
+            self.move_to(row, col);
+        } else {
+            self.move_to(row, col);
+        }
+    }
+
+    fn tab_forward(&mut self, count: usize) {
+        let cols = self.cols();
+        for _ in 0..count {
+            let next = (self.cursor.col + 1..cols).find(|&col| self.tabs[col]);
+            self.cursor.col = next.unwrap_or(cols - 1);
+        }
+    }
+
+    fn tab_backward(&mut self, count: usize) {
+        for _ in 0..count {
+            let previous = (0..self.cursor.col).rev().find(|&col| self.tabs[col]);
+            self.cursor.col = previous.unwrap_or(0);
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn save_cursor(&mut self) {
+        let saved = SavedCursor {
+            cursor: self.cursor,
+            pen: self.pen,
+            origin_mode: self.modes.origin,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+        };
+        if self.alternate_active {
+            self.saved_alternate = saved;
+        } else {
+            self.saved_primary = saved;
+        }
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = if self.alternate_active {
+            self.saved_alternate
+        } else {
+            self.saved_primary
+        };
+        self.pen = saved.pen;
+        self.modes.origin = saved.origin_mode;
+        self.charsets = saved.charsets;
+        self.active_charset = saved.active_charset;
+        self.move_to(saved.cursor.row, saved.cursor.col);
+        self.cursor.pending_wrap = saved.cursor.pending_wrap;
+    }
+
+    fn reset(&mut self) {
+        let (cols, rows) = (self.cols() as u16, self.rows() as u16);
+        let scrollback = std::mem::take(&mut self.scrollback);
+        *self = Terminal::new(cols, rows);
+        self.scrollback = scrollback;
+    }
+}
+
+fn default_tabs(cols: usize) -> Vec<bool> {
+    (0..cols)
+        .map(|col| col > 0 && col % TAB_WIDTH == 0)
+        .collect()
+}
+
+/// Parses the color following SGR 38/48, in either the `38;5;n` / `38;2;r;g;b`
+/// form or the colon separated `38:5:n` / `38:2::r:g:b` form.
+fn extended_color<'a>(
+    param: &[u16],
+    rest: &mut impl Iterator<Item = &'a Vec<u16>>,
+) -> Option<Color> {
+    if param.len() > 1 {
+        return match param[1] {
+            5 => param.get(2).map(|&i| Color::Indexed(i.min(255) as u8)),
+            2 => {
+                // An optional color space id may precede the components
+                let components = if param.len() >= 6 {
+                    &param[3..6]
+                } else {
+                    param.get(2..5)?
+                };
+                Some(Color::Rgb(
+                    components[0].min(255) as u8,
+                    components[1].min(255) as u8,
+                    components[2].min(255) as u8,
+                ))
+            }
+            _ => None,
+        };
+    }
+
+    let mut next = || rest.next().and_then(|p| p.first().copied());
+    match next()? {
+        5 => next().map(|i| Color::Indexed(i.min(255) as u8)),
+        2 => {
+            let r = next()?;
+            let g = next()?;
+            let b = next()?;
+            Some(Color::Rgb(
+                r.min(255) as u8,
+                g.min(255) as u8,
+                b.min(255) as u8,
+            ))
+        }
+        _ => None,
+    }
+}
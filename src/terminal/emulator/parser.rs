@@ -0,0 +1,269 @@
+// Upper bounds so a hostile or broken stream can't grow the parser forever
+const MAX_PARAMS: usize = 32;
+const MAX_OSC_LEN: usize = 4096;
+
+/// Something the terminal should do, as decoded by the [`Parser`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// A printable character
+    Print(char),
+    /// A C0 control character (BS, HT, LF, CR, ...)
+    Execute(char),
+    /// A control sequence, `ESC [ ...`
+    Csi(CsiSequence),
+    /// An escape sequence, `ESC <intermediates> <final>`
+    Esc {
+        intermediates: Vec<char>,
+        final_char: char,
+    },
+    /// An operating system command, `ESC ] ... BEL`, split on `;`
+    Osc(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CsiSequence {
+    /// Private marker such as `?` in `ESC [ ? 25 h`
+    pub private: Option<char>,
+    /// Parameters, each with its colon separated sub-parameters
+    pub params: Vec<Vec<u16>>,
+    pub intermediates: Vec<char>,
+    pub final_char: char,
+}
+
+impl CsiSequence {
+    /// Parameter `index`, with 0 or a missing value replaced by `default`.
+    pub fn param(&self, index: usize, default: u16) -> u16 {
+        match self.raw_param(index) {
+            Some(0) | None => default,
+            Some(value) => value,
+        }
+    }
+
+    /// Parameter `index` exactly as it was sent.
+    pub fn raw_param(&self, index: usize) -> Option<u16> {
+        self.params.get(index).and_then(|p| p.first().copied())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    CsiIgnore,
+    OscString,
+    OscEscape,
+    StringIgnore,
+    StringIgnoreEscape,
+}
+
+/// A VT500-style escape sequence parser.
+///
+/// Input may be split anywhere, partial sequences are kept until the next
+/// call to [`Parser::feed`].
+pub struct Parser {
+    state: State,
+    intermediates: Vec<char>,
+    params: Vec<Vec<u16>>,
+    private: Option<char>,
+    osc: String,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            intermediates: Vec::new(),
+            params: Vec::new(),
+            private: None,
+            osc: String::new(),
+        }
+    }
+
+    pub fn feed(&mut self, input: &str) -> Vec<Action> {
+        let mut actions = Vec::new();
+        for c in input.chars() {
+            self.advance(c, &mut actions);
+        }
+        actions
+    }
+
+    fn advance(&mut self, c: char, actions: &mut Vec<Action>) {
+        // CAN and SUB abort any sequence, ESC always starts a new one
+        match c {
+            '\x18' | '\x1a' => {
+                self.state = State::Ground;
+                return;
+            }
+            '\x1b' => {
+                match self.state {
+                    State::OscString => self.state = State::OscEscape,
+                    State::StringIgnore => self.state = State::StringIgnoreEscape,
+                    _ => self.enter_escape(),
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        match self.state {
+            State::Ground => match c {
+                '\x00'..='\x1f' => actions.push(Action::Execute(c)),
+                '\x7f' => {}
+                '\u{9b}' => self.enter_csi(),
+                '\u{9d}' => self.enter_osc(),
+                _ => actions.push(Action::Print(c)),
+            },
+            State::Escape => match c {
+                '\x00'..='\x1f' => actions.push(Action::Execute(c)),
+                '[' => self.enter_csi(),
+                ']' => self.enter_osc(),
+                'P' | 'X' | '^' | '_' => self.state = State::StringIgnore,
+                '\x20'..='\x2f' => {
+                    self.intermediates.push(c);
+                    self.state = State::EscapeIntermediate;
+                }
+                '\x30'..='\x7e' => self.dispatch_esc(c, actions),
+                _ => self.state = State::Ground,
+            },
+            State::EscapeIntermediate => match c {
+                '\x00'..='\x1f' => actions.push(Action::Execute(c)),
+                '\x20'..='\x2f' => self.intermediates.push(c),
+                '\x30'..='\x7e' => self.dispatch_esc(c, actions),
+                _ => self.state = State::Ground,
+            },
+            State::CsiEntry | State::CsiParam => match c {
+                '\x00'..='\x1f' => actions.push(Action::Execute(c)),
+                '0'..='9' => {
+                    self.state = State::CsiParam;
+                    let digit = c as u16 - '0' as u16;
+                    if self.params.is_empty() {
+                        self.params.push(vec![0]);
+                    }
+                    if let Some(value) = self.params.last_mut().and_then(|p| p.last_mut()) {
+                        *value = value.saturating_mul(10).saturating_add(digit);
+                    }
+                }
+                ';' => {
+                    self.state = State::CsiParam;
+                    if self.params.is_empty() {
+                        self.params.push(vec![0]);
+                    }
+                    if self.params.len() >= MAX_PARAMS {
+                        self.state = State::CsiIgnore;
+                    } else {
+                        self.params.push(vec![0]);
+                    }
+                }
+                ':' => {
+                    self.state = State::CsiParam;
+                    if self.params.is_empty() {
+                        self.params.push(vec![0]);
+                    }
+                    if let Some(param) = self.params.last_mut() {
+                        param.push(0);
+                    }
+                }
+                '<'..='?' if self.state == State::CsiEntry => self.private = Some(c),
+                '\x20'..='\x2f' => {
+                    self.intermediates.push(c);
+                    self.state = State::CsiIntermediate;
+                }
+                '\x40'..='\x7e' => self.dispatch_csi(c, actions),
+                _ => self.state = State::CsiIgnore,
+            },
+            State::CsiIntermediate => match c {
+                '\x00'..='\x1f' => actions.push(Action::Execute(c)),
+                '\x20'..='\x2f' => self.intermediates.push(c),
+                '\x40'..='\x7e' => self.dispatch_csi(c, actions),
+                _ => self.state = State::CsiIgnore,
+            },
+            State::CsiIgnore => match c {
+                '\x00'..='\x1f' => actions.push(Action::Execute(c)),
+                '\x40'..='\x7e' => self.state = State::Ground,
+                _ => {}
+            },
+            State::OscString => match c {
+                '\x07' | '\u{9c}' => self.dispatch_osc(actions),
+                '\x00'..='\x1f' => {}
+                _ => {
+                    if self.osc.len() < MAX_OSC_LEN {
+                        self.osc.push(c);
+                    }
+                }
+            },
+            State::OscEscape => {
+                // ESC \ terminates the string, anything else is a new escape sequence
+                self.dispatch_osc(actions);
+                self.enter_escape();
+                if c != '\\' {
+                    self.advance(c, actions);
+                } else {
+                    self.state = State::Ground;
+                }
+            }
+            State::StringIgnore => {
+                if c == '\x07' || c == '\u{9c}' {
+                    self.state = State::Ground;
+                }
+            }
+            State::StringIgnoreEscape => {
+                self.state = if c == '\\' {
+                    State::Ground
+                } else {
+                    State::StringIgnore
+                };
+            }
+        }
+    }
+
+    fn enter_escape(&mut self) {
+        self.state = State::Escape;
+        self.intermediates.clear();
+    }
+
+    fn enter_csi(&mut self) {
+        self.state = State::CsiEntry;
+        self.intermediates.clear();
+        self.params.clear();
+        self.private = None;
+    }
+
+    fn enter_osc(&mut self) {
+        self.state = State::OscString;
+        self.osc.clear();
+    }
+
+    fn dispatch_esc(&mut self, final_char: char, actions: &mut Vec<Action>) {
+        actions.push(Action::Esc {
+            intermediates: std::mem::take(&mut self.intermediates),
+            final_char,
+        });
+        self.state = State::Ground;
+    }
+
+    fn dispatch_csi(&mut self, final_char: char, actions: &mut Vec<Action>) {
+        actions.push(Action::Csi(CsiSequence {
+            private: self.private.take(),
+            params: std::mem::take(&mut self.params),
+            intermediates: std::mem::take(&mut self.intermediates),
+            final_char,
+        }));
+        self.state = State::Ground;
+    }
+
+    fn dispatch_osc(&mut self, actions: &mut Vec<Action>) {
+        let osc = std::mem::take(&mut self.osc);
+        actions.push(Action::Osc(osc.split(';').map(str::to_string).collect()));
+        self.state = State::Ground;
+    }
+}
@@ -0,0 +1,165 @@
+use super::cell::{Attrs, Cell, Color};
+use super::Terminal;
+use wasm_bindgen::JsCast;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+const FONT_FAMILY: &str = "Menlo, Consolas, 'DejaVu Sans Mono', monospace";
+const FONT_SIZE: f64 = 14.0;
+const LINE_HEIGHT: f64 = 1.2;
+const DEFAULT_FG: (u8, u8, u8) = (221, 221, 221);
+const DEFAULT_BG: (u8, u8, u8) = (17, 17, 17);
+
+/// Draws a [`Terminal`] onto a 2D canvas.
+pub struct Renderer {
+    context: CanvasRenderingContext2d,
+    cell_width: f64,
+    cell_height: f64,
+}
+
+impl Renderer {
+    pub fn new(canvas: &HtmlCanvasElement) -> Result<Self, String> {
+        let context = canvas
+            .get_context("2d")
+            .map_err(|e| format!("Failed to get canvas context: {:?}", e))?
+            .ok_or_else(|| "Canvas has no 2d context".to_string())?
+            .dyn_into::<CanvasRenderingContext2d>()
+            .map_err(|_| "Canvas context is not 2d".to_string())?;
+
+        context.set_font(&font(Attrs::default()));
+        let cell_width = context
+            .measure_text("M")
+            .map(|metrics| metrics.width())
+            .unwrap_or(FONT_SIZE * 0.6);
+
+        Ok(Self {
+            context,
+            cell_width,
+            cell_height: (FONT_SIZE * LINE_HEIGHT).ceil(),
+        })
+    }
+
+    /// Width and height of one character cell in CSS pixels.
+    pub fn cell_size(&self) -> (f64, f64) {
+        (self.cell_width, self.cell_height)
+    }
+
+    pub fn render(&self, terminal: &Terminal, canvas: &HtmlCanvasElement) {
+        let (cols, rows) = (terminal.cols(), terminal.rows());
+        let width = cols as f64 * self.cell_width;
+        let height = rows as f64 * self.cell_height;
+        let ratio = web_sys::window()
+            .map(|w| w.device_pixel_ratio())
+            .unwrap_or(1.0);
+
+        // Size the backing store for the screen's pixel ratio so text stays crisp
+        let backing_width = (width * ratio).ceil() as u32;
+        let backing_height = (height * ratio).ceil() as u32;
+        if canvas.width() != backing_width || canvas.height() != backing_height {
+            canvas.set_width(backing_width);
+            canvas.set_height(backing_height);
+            let _ = canvas.set_attribute(
+                "style",
+                &format!("width: {}px; height: {}px;", width, height),
+            );
+        }
+
+        let ctx = &self.context;
+        let _ = ctx.set_transform(ratio, 0.0, 0.0, ratio, 0.0, 0.0);
+        ctx.set_text_baseline("middle");
+        ctx.set_fill_style_str(&css(DEFAULT_BG));
+        ctx.fill_rect(0.0, 0.0, width, height);
+
+        let screen = terminal.screen();
+        for row in 0..rows {
+            let y = row as f64 * self.cell_height;
+            for col in 0..cols {
+                let cell = screen.cell(row, col);
+                if cell.is_spacer() {
+                    continue;
+                }
+                self.draw_cell(cell, col as f64 * self.cell_width, y);
+            }
+        }
+
+        let cursor = terminal.cursor();
+        if terminal.cursor_visible() {
+            let x = cursor.col as f64 * self.cell_width;
+            let y = cursor.row as f64 * self.cell_height;
+            let cell = screen.cell(cursor.row, cursor.col);
+            let width = self.cell_width * cell.width.max(1) as f64;
+
+            ctx.set_fill_style_str(&css(DEFAULT_FG));
+            ctx.fill_rect(x, y, width, self.cell_height);
+            if cell.ch != ' ' {
+                ctx.set_font(&font(cell.style.attrs));
+                ctx.set_fill_style_str(&css(DEFAULT_BG));
+                let _ = ctx.fill_text(&cell.ch.to_string(), x, y + self.cell_height / 2.0);
+            }
+        }
+    }
+
+    fn draw_cell(&self, cell: &Cell, x: f64, y: f64) {
+        let ctx = &self.context;
+        let attrs = cell.style.attrs;
+        let (mut fg, mut bg) = resolve_colors(cell);
+        if attrs.contains(Attrs::INVERSE) {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+        let width = self.cell_width * cell.width as f64;
+
+        if bg != DEFAULT_BG {
+            ctx.set_fill_style_str(&css(bg));
+            ctx.fill_rect(x, y, width, self.cell_height);
+        }
+        if attrs.contains(Attrs::HIDDEN) {
+            return;
+        }
+
+        ctx.set_fill_style_str(&css(fg));
+        if attrs.contains(Attrs::DIM) {
+            ctx.set_global_alpha(0.6);
+        }
+        if cell.ch != ' ' {
+            ctx.set_font(&font(attrs));
+            let _ = ctx.fill_text(&cell.ch.to_string(), x, y + self.cell_height / 2.0);
+        }
+        if attrs.contains(Attrs::UNDERLINE) {
+            ctx.fill_rect(x, y + self.cell_height - 2.0, width, 1.0);
+        }
+        if attrs.contains(Attrs::STRIKETHROUGH) {
+            ctx.fill_rect(x, y + self.cell_height / 2.0, width, 1.0);
+        }
+        ctx.set_global_alpha(1.0);
+    }
+}
+
+fn resolve_colors(cell: &Cell) -> ((u8, u8, u8), (u8, u8, u8)) {
+    let mut fg_color = cell.style.fg;
+    // Bold text in one of the 8 base colors is shown in its bright variant
+    if let Color::Indexed(index @ 0..=7) = fg_color {
+        if cell.style.attrs.contains(Attrs::BOLD) {
+            fg_color = Color::Indexed(index + 8);
+        }
+    }
+    let fg = fg_color.to_rgb().unwrap_or(DEFAULT_FG);
+    let bg = cell.style.bg.to_rgb().unwrap_or(DEFAULT_BG);
+    (fg, bg)
+}
+
+fn font(attrs: Attrs) -> String {
+    let style = if attrs.contains(Attrs::ITALIC) {
+        "italic "
+    } else {
+        ""
+    };
+    let weight = if attrs.contains(Attrs::BOLD) {
+        "bold "
+    } else {
+        ""
+    };
+    format!("{}{}{}px {}", style, weight, FONT_SIZE, FONT_FAMILY)
+}
+
+fn css((r, g, b): (u8, u8, u8)) -> String {
+    format!("rgb({}, {}, {})", r, g, b)
+}
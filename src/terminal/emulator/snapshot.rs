@@ -0,0 +1,199 @@
+//! Serializable copy of a [`Terminal`]'s state, used to bring a viewer that
+//! joins mid-session up to date before live output starts.
+
+use super::cell::{Cell, Style};
+use super::grid::{Grid, Row};
+use super::parser::Parser;
+use super::{Charset, Cursor, Modes, SavedCursor, Terminal, SCROLLBACK_LINES};
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthChar;
+
+/// Bumped whenever the snapshot layout changes incompatibly
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// A run of cells sharing one style. Wide characters take a single `char` in
+/// `text`; their spacer cell is implied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "is_default_style")]
+    pub style: Style,
+}
+
+/// One row with trailing blank cells left out.
+pub type Line = Vec<Span>;
+
+/// Everything a [`Terminal`] needs to carry on exactly where another one left
+/// off, except a half-parsed escape sequence.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub version: u32,
+    cols: usize,
+    rows: usize,
+    title: String,
+    scrollback: Vec<Line>,
+    primary: Vec<Line>,
+    alternate: Vec<Line>,
+    alternate_active: bool,
+    cursor: Cursor,
+    saved_primary: SavedCursor,
+    saved_alternate: SavedCursor,
+    pen: Style,
+    scroll_top: usize,
+    scroll_bottom: usize,
+    modes: Modes,
+    tab_stops: Vec<usize>,
+    charsets: [Charset; 2],
+    active_charset: usize,
+    last_printed: Option<char>,
+}
+
+impl Terminal {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            cols: self.cols(),
+            rows: self.rows(),
+            title: self.title.clone(),
+            scrollback: self.scrollback.iter().map(|row| encode_row(row)).collect(),
+            primary: encode_grid(&self.primary),
+            alternate: encode_grid(&self.alternate),
+            alternate_active: self.alternate_active,
+            cursor: self.cursor,
+            saved_primary: self.saved_primary,
+            saved_alternate: self.saved_alternate,
+            pen: self.pen,
+            scroll_top: self.scroll_top,
+            scroll_bottom: self.scroll_bottom,
+            modes: self.modes,
+            tab_stops: (0..self.tabs.len()).filter(|&col| self.tabs[col]).collect(),
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+            last_printed: self.last_printed,
+        }
+    }
+
+    /// Rebuilds a terminal from a snapshot. Snapshots come from the network,
+    /// so anything out of range is clamped rather than trusted.
+    pub fn restore(snapshot: Snapshot) -> Result<Self, String> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(format!(
+                "Unsupported terminal snapshot version {} (expected {})",
+                snapshot.version, SNAPSHOT_VERSION
+            ));
+        }
+        let cols = snapshot.cols.clamp(1, u16::MAX as usize);
+        let rows = snapshot.rows.clamp(1, u16::MAX as usize);
+
+        let (scroll_top, scroll_bottom) =
+            if snapshot.scroll_top < snapshot.scroll_bottom && snapshot.scroll_bottom < rows {
+                (snapshot.scroll_top, snapshot.scroll_bottom)
+            } else {
+                (0, rows - 1)
+            };
+        let mut tabs = vec![false; cols];
+        for col in snapshot.tab_stops.into_iter().filter(|&col| col < cols) {
+            tabs[col] = true;
+        }
+        let skip = snapshot.scrollback.len().saturating_sub(SCROLLBACK_LINES);
+
+        Ok(Self {
+            parser: Parser::new(),
+            primary: decode_grid(&snapshot.primary, cols, rows),
+            alternate: decode_grid(&snapshot.alternate, cols, rows),
+            alternate_active: snapshot.alternate_active,
+            scrollback: snapshot.scrollback[skip..]
+                .iter()
+                .map(|line| decode_row(line, usize::MAX))
+                .collect(),
+            cursor: clamp_cursor(snapshot.cursor, cols, rows),
+            saved_primary: clamp_saved(snapshot.saved_primary, cols, rows),
+            saved_alternate: clamp_saved(snapshot.saved_alternate, cols, rows),
+            pen: snapshot.pen,
+            scroll_top,
+            scroll_bottom,
+            modes: snapshot.modes,
+            tabs,
+            charsets: snapshot.charsets,
+            active_charset: snapshot.active_charset.min(1),
+            last_printed: snapshot.last_printed,
+            title: snapshot.title,
+        })
+    }
+}
+
+fn is_default_style(style: &Style) -> bool {
+    *style == Style::default()
+}
+
+fn encode_grid(grid: &Grid) -> Vec<Line> {
+    (0..grid.rows())
+        .map(|row| encode_row(grid.row(row)))
+        .collect()
+}
+
+fn encode_row(row: &[Cell]) -> Line {
+    let len = row
+        .iter()
+        .rposition(|cell| *cell != Cell::default())
+        .map_or(0, |last| last + 1);
+
+    let mut line: Line = Vec::new();
+    for cell in row[..len].iter().filter(|cell| !cell.is_spacer()) {
+        match line.last_mut() {
+            Some(span) if span.style == cell.style => span.text.push(cell.ch),
+            _ => line.push(Span {
+                text: cell.ch.to_string(),
+                style: cell.style,
+            }),
+        }
+    }
+    line
+}
+
+fn decode_grid(lines: &[Line], cols: usize, rows: usize) -> Grid {
+    let rows_data = lines
+        .iter()
+        .take(rows)
+        .map(|line| decode_row(line, cols))
+        .collect();
+    Grid::from_lines(cols, rows, rows_data)
+}
+
+/// Expands spans back into cells, stopping at `cols`.
+fn decode_row(line: &Line, cols: usize) -> Row {
+    let mut row = Row::new();
+    for span in line {
+        for ch in span.text.chars() {
+            let width = ch.width().unwrap_or(1).clamp(1, 2);
+            if row.len() + width > cols {
+                return row;
+            }
+            row.push(Cell {
+                ch,
+                width: width as u8,
+                style: span.style,
+            });
+            if width == 2 {
+                row.push(Cell::spacer(span.style));
+            }
+        }
+    }
+    row
+}
+
+fn clamp_cursor(cursor: Cursor, cols: usize, rows: usize) -> Cursor {
+    Cursor {
+        row: cursor.row.min(rows - 1),
+        col: cursor.col.min(cols - 1),
+        pending_wrap: cursor.pending_wrap,
+    }
+}
+
+fn clamp_saved(saved: SavedCursor, cols: usize, rows: usize) -> SavedCursor {
+    SavedCursor {
+        cursor: clamp_cursor(saved.cursor, cols, rows),
+        active_charset: saved.active_charset.min(1),
+        ..saved
+    }
+}
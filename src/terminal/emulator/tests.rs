@@ -0,0 +1,652 @@
+use super::cell::{Attrs, Color};
+use super::parser::{Action, Parser};
+use super::snapshot::{Snapshot, SNAPSHOT_VERSION};
+use super::{Terminal, SCROLLBACK_LINES};
+use crate::terminal::input::{key_to_input, paste_to_input};
+
+fn term(input: &str) -> Terminal {
+    let mut term = Terminal::new(10, 4);
+    term.feed(input);
+    term
+}
+
+fn lines(term: &Terminal) -> Vec<String> {
+    (0..term.rows())
+        .map(|row| term.screen().line_text(row))
+        .collect()
+}
+
+fn cursor(term: &Terminal) -> (usize, usize) {
+    (term.cursor().row, term.cursor().col)
+}
+
+// (description, input, expected screen lines, expected cursor)
+type ScreenCase = (
+    &'static str,
+    &'static str,
+    [&'static str; 4],
+    (usize, usize),
+);
+
+const SCREEN_CORPUS: &[ScreenCase] = &[
+    ("plain text", "hello", ["hello", "", "", ""], (0, 5)),
+    ("crlf", "ab\r\ncd", ["ab", "cd", "", ""], (1, 2)),
+    (
+        "bare lf keeps column",
+        "ab\ncd",
+        ["ab", "  cd", "", ""],
+        (1, 4),
+    ),
+    ("backspace", "abc\x08\x08X", ["aXc", "", "", ""], (0, 2)),
+    (
+        "autowrap",
+        "0123456789ab",
+        ["0123456789", "ab", "", ""],
+        (1, 2),
+    ),
+    (
+        "pending wrap then cr",
+        "0123456789\rX",
+        ["X123456789", "", "", ""],
+        (0, 1),
+    ),
+    (
+        "scroll at bottom",
+        "1\r\n2\r\n3\r\n4\r\n5",
+        ["2", "3", "4", "5"],
+        (3, 1),
+    ),
+    ("cup", "\x1b[3;4HX", ["", "", "   X", ""], (2, 4)),
+    ("cup defaults", "abc\x1b[HX", ["Xbc", "", "", ""], (0, 1)),
+    (
+        "cup clamps",
+        "\x1b[99;99HX",
+        ["", "", "", "         X"],
+        (3, 9),
+    ),
+    (
+        "relative moves",
+        "\x1b[2B\x1b[3CX\x1b[AY\x1b[2DZ",
+        ["", "   ZY", "   X", ""],
+        (1, 4),
+    ),
+    (
+        "cha and vpa",
+        "\x1b[5G\x1b[2dX",
+        ["", "    X", "", ""],
+        (1, 5),
+    ),
+    (
+        "erase to end of line",
+        "abcdef\x1b[1;3H\x1b[K",
+        ["ab", "", "", ""],
+        (0, 2),
+    ),
+    (
+        "erase to start of line",
+        "abcdef\x1b[1;3H\x1b[1K",
+        ["   def", "", "", ""],
+        (0, 2),
+    ),
+    (
+        "erase below",
+        "aaa\r\nbbb\r\nccc\x1b[2;2H\x1b[J",
+        ["aaa", "b", "", ""],
+        (1, 1),
+    ),
+    (
+        "erase above",
+        "aaa\r\nbbb\r\nccc\x1b[2;2H\x1b[1J",
+        ["", "  b", "ccc", ""],
+        (1, 1),
+    ),
+    ("erase all", "aaa\r\nbbb\x1b[2J", ["", "", "", ""], (1, 3)),
+    (
+        "erase chars",
+        "abcdef\x1b[1;2H\x1b[2X",
+        ["a  def", "", "", ""],
+        (0, 1),
+    ),
+    (
+        "insert chars",
+        "abcdef\x1b[1;2H\x1b[2@",
+        ["a  bcdef", "", "", ""],
+        (0, 1),
+    ),
+    (
+        "delete chars",
+        "abcdef\x1b[1;2H\x1b[2P",
+        ["adef", "", "", ""],
+        (0, 1),
+    ),
+    (
+        "insert mode",
+        "abc\x1b[1;1H\x1b[4hXY\x1b[4l",
+        ["XYabc", "", "", ""],
+        (0, 2),
+    ),
+    (
+        "insert lines",
+        "1\r\n2\r\n3\x1b[2;1H\x1b[L",
+        ["1", "", "2", "3"],
+        (1, 0),
+    ),
+    (
+        "delete lines",
+        "1\r\n2\r\n3\x1b[1;1H\x1b[M",
+        ["2", "3", "", ""],
+        (0, 0),
+    ),
+    ("scroll up", "1\r\n2\r\n3\x1b[S", ["2", "3", "", ""], (2, 1)),
+    (
+        "scroll down",
+        "1\r\n2\r\n3\x1b[T",
+        ["", "1", "2", "3"],
+        (2, 1),
+    ),
+    (
+        "scroll region",
+        "1\r\n2\r\n3\r\n4\x1b[2;3r\x1b[3;1H\nX",
+        ["1", "3", "X", "4"],
+        (2, 1),
+    ),
+    (
+        "reverse index at top",
+        "1\r\n2\x1b[H\x1bM",
+        ["", "1", "2", ""],
+        (0, 0),
+    ),
+    (
+        "index and next line",
+        "a\x1bDb\x1bEc",
+        ["a", " b", "c", ""],
+        (2, 1),
+    ),
+    ("tabs", "a\tb\x1b[Ic", ["a       bc", "", "", ""], (0, 9)),
+    (
+        "back tab",
+        "\x1b[10G\x1b[ZX",
+        ["        X", "", "", ""],
+        (0, 9),
+    ),
+    (
+        "clear tab stops",
+        "\x1b[3g\tX",
+        ["         X", "", "", ""],
+        (0, 9),
+    ),
+    (
+        "set tab stop",
+        "\x1b[3g\x1b[4G\x1bH\r\tX",
+        ["   X", "", "", ""],
+        (0, 4),
+    ),
+    (
+        "save and restore",
+        "ab\x1b7\x1b[3;3HX\x1b8Y",
+        ["abY", "", "  X", ""],
+        (0, 3),
+    ),
+    (
+        "csi save and restore",
+        "ab\x1b[s\r\n\x1b[uY",
+        ["abY", "", "", ""],
+        (0, 3),
+    ),
+    ("repeat", "x\x1b[3b", ["xxxx", "", "", ""], (0, 4)),
+    (
+        "line drawing",
+        "\x1b(0lqk\x1b(Bq",
+        ["┌─┐q", "", "", ""],
+        (0, 4),
+    ),
+    (
+        "shift out",
+        "\x1b)0a\x0eq\x0fq",
+        ["a─q", "", "", ""],
+        (0, 3),
+    ),
+    (
+        "no autowrap",
+        "\x1b[?7l0123456789abc",
+        ["012345678c", "", "", ""],
+        (0, 9),
+    ),
+    (
+        "decaln",
+        "\x1b#8",
+        ["EEEEEEEEEE", "EEEEEEEEEE", "EEEEEEEEEE", "EEEEEEEEEE"],
+        (0, 0),
+    ),
+    ("full reset", "abc\x1b[1mx\x1bcZ", ["Z", "", "", ""], (0, 1)),
+    ("wide chars", "日本", ["日本", "", "", ""], (0, 4)),
+    (
+        "wide char wraps whole",
+        "012345678日",
+        ["012345678", "日", "", ""],
+        (1, 2),
+    ),
+    (
+        "overwrite wide char half",
+        "日本\x1b[1;2HX",
+        [" X本", "", "", ""],
+        (0, 2),
+    ),
+    (
+        "combining marks dropped",
+        "e\u{301}x",
+        ["ex", "", "", ""],
+        (0, 2),
+    ),
+    (
+        "cancel aborts sequence",
+        "a\x1b[3\x18b",
+        ["ab", "", "", ""],
+        (0, 2),
+    ),
+    (
+        "unknown sequences ignored",
+        "a\x1b[>0c\x1bP1$r\x1b\\b",
+        ["ab", "", "", ""],
+        (0, 2),
+    ),
+];
+
+#[test]
+fn screen_corpus() {
+    for (description, input, expected_lines, expected_cursor) in SCREEN_CORPUS {
+        let term = term(input);
+        assert_eq!(lines(&term), expected_lines.to_vec(), "{}", description);
+        assert_eq!(cursor(&term), *expected_cursor, "{} (cursor)", description);
+    }
+}
+
+#[test]
+fn input_split_at_every_position() {
+    let input = "\x1b[1;31mred\x1b[0m \x1b]0;title\x07日本\x1b[2;3Hx";
+    let expected = term(input);
+    let chars: Vec<char> = input.chars().collect();
+    for split in 0..chars.len() {
+        let (head, tail): (String, String) = (
+            chars[..split].iter().collect(),
+            chars[split..].iter().collect(),
+        );
+        let mut split_term = Terminal::new(10, 4);
+        split_term.feed(&head);
+        split_term.feed(&tail);
+        assert_eq!(split_term.screen(), expected.screen(), "split at {}", split);
+        assert_eq!(split_term.cursor(), expected.cursor(), "split at {}", split);
+    }
+}
+
+#[test]
+fn sgr_attributes() {
+    let term = term("\x1b[1;3;4;7mA\x1b[22;23mB\x1b[0mC\x1b[2;9mD");
+    let screen = term.screen();
+    let a = screen.cell(0, 0).style.attrs;
+    assert!(a.contains(Attrs::BOLD) && a.contains(Attrs::ITALIC));
+    assert!(a.contains(Attrs::UNDERLINE) && a.contains(Attrs::INVERSE));
+    let b = screen.cell(0, 1).style.attrs;
+    assert!(!b.contains(Attrs::BOLD) && !b.contains(Attrs::ITALIC));
+    assert!(b.contains(Attrs::UNDERLINE));
+    assert_eq!(screen.cell(0, 2).style.attrs, Attrs::default());
+    let d = screen.cell(0, 3).style.attrs;
+    assert!(d.contains(Attrs::DIM) && d.contains(Attrs::STRIKETHROUGH));
+}
+
+#[test]
+fn sgr_underline_subparams() {
+    let term = term("\x1b[4:3mA\x1b[4:0mB");
+    assert!(term
+        .screen()
+        .cell(0, 0)
+        .style
+        .attrs
+        .contains(Attrs::UNDERLINE));
+    assert!(!term
+        .screen()
+        .cell(0, 1)
+        .style
+        .attrs
+        .contains(Attrs::UNDERLINE));
+}
+
+#[test]
+fn sgr_colors() {
+    let cases: &[(&str, Color, Color)] = &[
+        ("\x1b[31;42m", Color::Indexed(1), Color::Indexed(2)),
+        ("\x1b[91;102m", Color::Indexed(9), Color::Indexed(10)),
+        (
+            "\x1b[38;5;208;48;5;17m",
+            Color::Indexed(208),
+            Color::Indexed(17),
+        ),
+        ("\x1b[38:5:208m", Color::Indexed(208), Color::Default),
+        (
+            "\x1b[38;2;255;128;0m",
+            Color::Rgb(255, 128, 0),
+            Color::Default,
+        ),
+        ("\x1b[48;2;1;2;3m", Color::Default, Color::Rgb(1, 2, 3)),
+        (
+            "\x1b[38:2::10:20:30m",
+            Color::Rgb(10, 20, 30),
+            Color::Default,
+        ),
+        (
+            "\x1b[38:2:10:20:30m",
+            Color::Rgb(10, 20, 30),
+            Color::Default,
+        ),
+        ("\x1b[31;42m\x1b[39;49m", Color::Default, Color::Default),
+        (
+            "\x1b[38;2;255;0;0;1m",
+            Color::Rgb(255, 0, 0),
+            Color::Default,
+        ),
+    ];
+    for (input, fg, bg) in cases {
+        let term = term(&format!("{}X", input));
+        let style = term.screen().cell(0, 0).style;
+        assert_eq!((style.fg, style.bg), (*fg, *bg), "{:?}", input);
+    }
+
+    // Parameters following a truecolor color are still applied
+    let term = term("\x1b[38;2;255;0;0;1mX");
+    assert!(term.screen().cell(0, 0).style.attrs.contains(Attrs::BOLD));
+}
+
+#[test]
+fn erase_keeps_background_color() {
+    let term = term("\x1b[44m\x1b[2J");
+    assert_eq!(term.screen().cell(2, 5).style.bg, Color::Indexed(4));
+}
+
+#[test]
+fn wide_char_cells() {
+    let term = term("a日");
+    let screen = term.screen();
+    assert_eq!(screen.cell(0, 1).ch, '日');
+    assert_eq!(screen.cell(0, 1).width, 2);
+    assert!(screen.cell(0, 2).is_spacer());
+    assert_eq!(cursor(&term), (0, 3));
+}
+
+#[test]
+fn alternate_screen_1049() {
+    let mut term = term("shell$ ");
+    term.feed("\x1b[?1049h");
+    assert!(term.alternate_active);
+    assert_eq!(lines(&term), vec!["", "", "", ""]);
+    term.feed("\x1b[Hvim");
+    assert_eq!(lines(&term)[0], "vim");
+
+    term.feed("\x1b[?1049l");
+    assert!(!term.alternate_active);
+    assert_eq!(lines(&term)[0], "shell$");
+    assert_eq!(cursor(&term), (0, 7));
+}
+
+#[test]
+fn alternate_screen_is_cleared_on_reentry() {
+    let mut term = term("\x1b[?1049hjunk\x1b[?1049l\x1b[?1049h");
+    assert_eq!(lines(&term)[0], "");
+    term.feed("\x1b[?1049l");
+    assert_eq!(lines(&term)[0], "");
+}
+
+#[test]
+fn private_modes() {
+    let mut term = term("");
+    assert!(!term.bracketed_paste());
+    assert!(!term.application_cursor());
+    assert!(term.cursor_visible());
+
+    term.feed("\x1b[?2004h\x1b[?1h\x1b[?25l");
+    assert!(term.bracketed_paste());
+    assert!(term.application_cursor());
+    assert!(!term.cursor_visible());
+
+    term.feed("\x1b[?2004;1;25l\x1b[?25h");
+    assert!(!term.bracketed_paste());
+    assert!(!term.application_cursor());
+    assert!(term.cursor_visible());
+}
+
+#[test]
+fn origin_mode() {
+    let term = term("\x1b[2;3r\x1b[?6h\x1b[1;1HX\x1b[9;1HY");
+    assert_eq!(lines(&term), vec!["", "X", "Y", ""]);
+}
+
+#[test]
+fn osc_title() {
+    assert_eq!(term("\x1b]0;first\x07").title(), "first");
+    assert_eq!(term("\x1b]2;second\x1b\\").title(), "second");
+    assert_eq!(term("\x1b]1;icon only\x07").title(), "");
+    // A title interrupted by a new escape sequence still applies, and the sequence runs
+    let term = term("\x1b]2;cut\x1b[2;1HX");
+    assert_eq!(term.title(), "cut");
+    assert_eq!(lines(&term)[1], "X");
+}
+
+#[test]
+fn resize_keeps_cursor_line_visible() {
+    let mut term = term("1\r\n2\r\n3\r\n4");
+    term.resize(5, 2);
+    assert_eq!(lines(&term), vec!["3", "4"]);
+    assert_eq!(cursor(&term), (1, 1));
+
+    term.resize(8, 3);
+    assert_eq!(lines(&term), vec!["3", "4", ""]);
+    term.feed("\r\n\r\nabcdefghij");
+    assert_eq!(lines(&term), vec!["", "abcdefgh", "ij"]);
+}
+
+fn scrollback_text(term: &Terminal) -> Vec<String> {
+    term.scrollback()
+        .iter()
+        .map(super::grid::row_text)
+        .collect()
+}
+
+#[test]
+fn scrollback_collects_lines_scrolled_off_the_top() {
+    let mut term = term("1\r\n2\r\n3\r\n4\r\n5\r\n6");
+    assert_eq!(scrollback_text(&term), vec!["1", "2"]);
+
+    // Scrolling inside a region or on the alternate screen doesn't feed it
+    term.feed("\x1b[2;3r\x1b[3;1H\n\n\x1b[r");
+    term.feed("\x1b[?1049h\r\n\r\n\r\n\r\n\r\n\x1b[?1049l");
+    assert_eq!(scrollback_text(&term), vec!["1", "2"]);
+
+    // Lines pushed out by shrinking the screen do
+    assert_eq!(lines(&term), vec!["3", "", "", "6"]);
+    term.feed("\x1b[4;1H");
+    term.resize(10, 2);
+    assert_eq!(scrollback_text(&term), vec!["1", "2", "3", ""]);
+
+    term.feed("\x1b[3J");
+    assert!(term.scrollback().is_empty());
+}
+
+#[test]
+fn scrollback_is_bounded() {
+    let mut term = Terminal::new(10, 4);
+    for i in 0..SCROLLBACK_LINES + 50 {
+        term.feed(&format!("{}\r\n", i));
+    }
+    assert_eq!(term.scrollback().len(), SCROLLBACK_LINES);
+    // 4 screen rows hold the last three numbers and an empty line
+    assert_eq!(scrollback_text(&term)[0], "47");
+}
+
+/// Terminals exercising most of the state a snapshot has to carry
+fn snapshot_fixtures() -> Vec<(&'static str, Terminal)> {
+    let mut fixtures = vec![
+        ("empty", Terminal::new(10, 4)),
+        ("plain", term("hello\r\nworld")),
+        (
+            "styles",
+            term("\x1b[1;31mred\x1b[0m \x1b[38;2;1;2;3;48;5;200mrgb\x1b[7m inv\x1b[44m\x1b[K"),
+        ),
+        ("wide chars", term("日本語\r\nx日\x1b[1;4H")),
+        ("pending wrap", term("0123456789")),
+        (
+            "scrollback",
+            term("1\r\n\x1b[32m2\r\n3\r\n4\r\n5\r\n6 日本"),
+        ),
+        (
+            "alternate screen",
+            term("shell$ \x1b[?1049h\x1b[?1h\x1b[?2004h\x1b[2;3r\x1b[2;2Hvim"),
+        ),
+        (
+            "modes and charsets",
+            term("\x1b[?25l\x1b[4h\x1b[20h\x1b[?7l\x1b(0\x0eqq\x1b]0;title\x07\x1b[3g\x1bH\x1b7"),
+        ),
+    ];
+    let mut resized = term("1\r\n2\r\n3\r\n4");
+    resized.resize(6, 2);
+    fixtures.push(("resized", resized));
+    fixtures
+}
+
+#[test]
+fn snapshot_round_trips() {
+    for (description, original) in snapshot_fixtures() {
+        let snapshot = original.snapshot();
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let decoded: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, snapshot, "{} (json)", description);
+
+        let restored = Terminal::restore(decoded).unwrap();
+        assert_eq!(restored.snapshot(), snapshot, "{}", description);
+        assert_eq!(
+            restored.screen(),
+            original.screen(),
+            "{} (screen)",
+            description
+        );
+        assert_eq!(
+            restored.scrollback(),
+            original.scrollback(),
+            "{}",
+            description
+        );
+        assert_eq!(
+            restored.cursor(),
+            original.cursor(),
+            "{} (cursor)",
+            description
+        );
+        assert_eq!(
+            restored.title(),
+            original.title(),
+            "{} (title)",
+            description
+        );
+    }
+}
+
+#[test]
+fn restored_terminal_continues_like_the_original() {
+    let more = "ab\x1b[Hc\tx\x1b8y\x1b[?1049l\r\nq日本\x1b[1mz\x1b[2Sw";
+    for (description, mut original) in snapshot_fixtures() {
+        let mut restored = Terminal::restore(original.snapshot()).unwrap();
+        original.feed(more);
+        restored.feed(more);
+        assert_eq!(restored.snapshot(), original.snapshot(), "{}", description);
+    }
+}
+
+#[test]
+fn snapshot_rejects_other_versions() {
+    let mut snapshot = term("x").snapshot();
+    snapshot.version = SNAPSHOT_VERSION + 1;
+    assert!(Terminal::restore(snapshot).is_err());
+}
+
+#[test]
+fn snapshot_clamps_untrusted_input() {
+    let mut value = serde_json::to_value(term("abc").snapshot()).unwrap();
+    value["cursor"]["row"] = 99.into();
+    value["scroll_bottom"] = 50.into();
+    value["primary"][0] = serde_json::json!([{ "text": "0123456789日本" }]);
+    let restored = Terminal::restore(serde_json::from_value(value).unwrap()).unwrap();
+    assert_eq!(cursor(&restored), (3, 3));
+    assert_eq!(lines(&restored)[0], "0123456789");
+}
+
+#[test]
+fn parser_actions() {
+    let mut parser = Parser::new();
+    let actions = parser.feed("a\r\x1b[?25;1h\x1b(0\x1b]0;t;x\x07");
+    assert_eq!(actions[0], Action::Print('a'));
+    assert_eq!(actions[1], Action::Execute('\r'));
+    match &actions[2] {
+        Action::Csi(csi) => {
+            assert_eq!(csi.private, Some('?'));
+            assert_eq!(csi.params, vec![vec![25], vec![1]]);
+            assert_eq!(csi.final_char, 'h');
+        }
+        other => panic!("expected csi, got {:?}", other),
+    }
+    assert_eq!(
+        actions[3],
+        Action::Esc {
+            intermediates: vec!['('],
+            final_char: '0'
+        }
+    );
+    assert_eq!(
+        actions[4],
+        Action::Osc(vec!["0".to_string(), "t".to_string(), "x".to_string()])
+    );
+}
+
+#[test]
+fn parser_limits_params() {
+    let mut parser = Parser::new();
+    let long = format!("\x1b[{}m", "1;".repeat(100));
+    assert!(parser.feed(&long).is_empty());
+    assert_eq!(parser.feed("x"), vec![Action::Print('x')]);
+}
+
+#[test]
+fn keys() {
+    assert_eq!(key_to_input("a", false, false, false).as_deref(), Some("a"));
+    assert_eq!(
+        key_to_input("c", true, false, false).as_deref(),
+        Some("\x03")
+    );
+    assert_eq!(
+        key_to_input("x", false, true, false).as_deref(),
+        Some("\x1bx")
+    );
+    assert_eq!(
+        key_to_input("Enter", false, false, false).as_deref(),
+        Some("\r")
+    );
+    assert_eq!(
+        key_to_input("ArrowUp", false, false, false).as_deref(),
+        Some("\x1b[A")
+    );
+    assert_eq!(
+        key_to_input("ArrowUp", false, false, true).as_deref(),
+        Some("\x1bOA")
+    );
+    assert_eq!(key_to_input("Shift", false, false, false), None);
+    assert_eq!(key_to_input("F13", false, false, false), None);
+}
+
+#[test]
+fn bracketed_paste() {
+    assert_eq!(paste_to_input("a\nb", false), "a\rb");
+    assert_eq!(paste_to_input("a\r\nb", true), "\x1b[200~a\rb\x1b[201~");
+    assert_eq!(
+        paste_to_input("x\x1b[201~rm -rf ~", true),
+        "\x1b[200~xrm -rf ~\x1b[201~"
+    );
+}
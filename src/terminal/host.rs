@@ -0,0 +1,517 @@
+use super::channel::{on_frame, send_frame};
+use super::control::Control;
+use super::emulator::Terminal;
+use super::protocol::{ChannelId, TerminalFrame};
+use crate::services::api::{
+    kill_terminal, resize_terminal, start_terminal, write_terminal, TerminalExit, TerminalOutput,
+};
+use crate::services::events::{listen, EventListener};
+use crate::utils::share_access::ShareAccess;
+use gloo::timers::callback::Timeout;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{RtcDataChannel, RtcDataChannelState};
+
+// Output chunks kept for terminals the host doesn't know the id of yet, and for how long
+const MAX_EARLY_OUTPUT: usize = 64;
+const EARLY_OUTPUT_TTL_MS: f64 = 10_000.0;
+
+/// One PTY shared by the host
+struct HostChannel {
+    channel: ChannelId,
+    terminal_id: String,
+    // Mirror of the PTY's screen and bounded scrollback, snapshotted for viewers that connect late
+    terminal: Terminal,
+}
+
+/// A viewer the shells are shared with
+struct HostViewer {
+    user_id: String,
+    data_channel: RtcDataChannel,
+    // What the viewer was approved for
+    granted: ShareAccess,
+    // The size the viewer last reported
+    size: Option<(u16, u16)>,
+}
+
+struct HostState {
+    // Replaced when a viewer comes back on a new peer connection
+    viewers: RefCell<Vec<HostViewer>>,
+    // Who drives the shells, and which viewer it was handed to if not the host
+    control: Cell<Control>,
+    controller: RefCell<Option<String>>,
+    control_timeout: RefCell<Option<Timeout>>,
+    channels: RefCell<Vec<HostChannel>>,
+    next_channel: Cell<ChannelId>,
+    // Size every PTY runs at
+    size: Cell<(u16, u16)>,
+    // Output of shells not known yet, with when it came in
+    early_output: RefCell<Vec<(f64, TerminalOutput)>>,
+    on_change: Box<dyn Fn()>,
+}
+
+impl HostState {
+    /// What `user_id` may do right now: whoever is in control can type.
+    fn access(&self, user_id: &str) -> ShareAccess {
+        if self.holds_control(user_id) {
+            return ShareAccess::Interactive;
+        }
+        self.viewers
+            .borrow()
+            .iter()
+            .find(|viewer| viewer.user_id == user_id)
+            .map(|viewer| viewer.granted)
+            .unwrap_or_default()
+    }
+
+    fn holds_control(&self, user_id: &str) -> bool {
+        self.control.get().is_viewer() && self.controller.borrow().as_deref() == Some(user_id)
+    }
+
+    /// Hands control to `controller` or takes it back, and tells the viewers.
+    fn set_control(self: &Rc<Self>, control: Control, controller: Option<String>) {
+        self.control.set(control);
+        *self.controller.borrow_mut() = controller;
+        // A time-limited grant hands control back on its own
+        let timeout = control.seconds_left().map(|seconds| {
+            let state = Rc::downgrade(self);
+            Timeout::new(seconds.saturating_mul(1000), move || {
+                if let Some(state) = state.upgrade() {
+                    // This timeout is running, so it must not be dropped from inside itself
+                    if let Some(timeout) = state.control_timeout.borrow_mut().take() {
+                        timeout.forget();
+                    }
+                    state.set_control(Control::Host, None);
+                }
+            })
+        });
+        *self.control_timeout.borrow_mut() = timeout;
+
+        for viewer in self.viewers.borrow().iter() {
+            self.send_control(viewer);
+        }
+        self.apply_viewer_sizes();
+        (self.on_change)();
+    }
+
+    /// Tells `viewer` what it may do and who is in control.
+    fn send_control(&self, viewer: &HostViewer) {
+        let state = self
+            .control
+            .get()
+            .to_state(self.holds_control(&viewer.user_id));
+        let access = self.access(&viewer.user_id);
+        send_to(viewer, &TerminalFrame::Access { access });
+        send_to(viewer, &TerminalFrame::Control { state });
+    }
+
+    /// Sends a frame to every connected viewer. Anything sent before a viewer
+    /// connects is covered by the snapshots it gets once its channel opens.
+    fn send(&self, frame: TerminalFrame) {
+        for viewer in self.viewers.borrow().iter() {
+            send_to(viewer, &frame);
+        }
+    }
+
+    fn output(&self, output: TerminalOutput) {
+        let mut channels = self.channels.borrow_mut();
+        let Some(host_channel) = channels.iter_mut().find(|c| c.terminal_id == output.id) else {
+            // The shell can print before start_terminal has returned its id
+            let now = js_sys::Date::now();
+            let mut early = self.early_output.borrow_mut();
+            early.retain(|(at, _)| now - at < EARLY_OUTPUT_TTL_MS);
+            if early.len() < MAX_EARLY_OUTPUT {
+                early.push((now, output));
+            }
+            return;
+        };
+        host_channel.terminal.feed(&output.data);
+        let channel = host_channel.channel;
+        drop(channels);
+        self.send(TerminalFrame::Output {
+            channel,
+            data: output.data,
+        });
+    }
+
+    /// Brings a freshly connected viewer up to date: access level, control, size, and
+    /// a snapshot of every open PTY.
+    fn send_initial_state(&self, user_id: &str) {
+        let viewers = self.viewers.borrow();
+        let Some(viewer) = viewers.iter().find(|viewer| viewer.user_id == user_id) else {
+            return;
+        };
+        let (cols, rows) = self.size.get();
+        self.send_control(viewer);
+        send_to(viewer, &TerminalFrame::Size { cols, rows });
+        for host_channel in self.channels.borrow().iter() {
+            let channel = host_channel.channel;
+            send_to(viewer, &TerminalFrame::Open { channel });
+            send_to(
+                viewer,
+                &TerminalFrame::Snapshot {
+                    channel,
+                    snapshot: Box::new(host_channel.terminal.snapshot()),
+                },
+            );
+        }
+    }
+
+    fn terminal_id(&self, channel: ChannelId) -> Option<String> {
+        self.channels
+            .borrow()
+            .iter()
+            .find(|c| c.channel == channel)
+            .map(|c| c.terminal_id.clone())
+    }
+
+    fn channel_for(&self, terminal_id: &str) -> Option<ChannelId> {
+        self.channels
+            .borrow()
+            .iter()
+            .find(|c| c.terminal_id == terminal_id)
+            .map(|c| c.channel)
+    }
+
+    fn remove(&self, channel: ChannelId) -> Option<String> {
+        let mut channels = self.channels.borrow_mut();
+        let index = channels.iter().position(|c| c.channel == channel)?;
+        Some(channels.remove(index).terminal_id)
+    }
+
+    /// Resizes every PTY to what the viewers can show and tells them the result.
+    fn apply_viewer_sizes(&self) {
+        let viewers: Vec<_> = self
+            .viewers
+            .borrow()
+            .iter()
+            .filter_map(|viewer| {
+                let (cols, rows) = viewer.size?;
+                Some((self.access(&viewer.user_id), cols, rows))
+            })
+            .collect();
+        let Some(size) = negotiate_size(viewers) else {
+            return;
+        };
+        if size == self.size.get() {
+            return;
+        }
+        self.size.set(size);
+
+        let (cols, rows) = size;
+        for host_channel in self.channels.borrow_mut().iter_mut() {
+            host_channel.terminal.resize(cols, rows);
+            let terminal_id = host_channel.terminal_id.clone();
+            spawn_local(async move {
+                if let Err(e) = resize_terminal(terminal_id, cols, rows).await {
+                    log::error!("Failed to resize terminal: {}", e);
+                }
+            });
+        }
+        self.send(TerminalFrame::Size { cols, rows });
+    }
+
+    async fn open_channel(&self) -> Result<ChannelId, String> {
+        let (cols, rows) = self.size.get();
+        let terminal_id = start_terminal(cols, rows, None).await?;
+        let channel = self.next_channel.get();
+        self.next_channel.set(channel + 1);
+
+        self.channels.borrow_mut().push(HostChannel {
+            channel,
+            terminal_id: terminal_id.clone(),
+            terminal: Terminal::new(cols, rows),
+        });
+        self.send(TerminalFrame::Open { channel });
+
+        // Replay what the new shell printed early; output of shells that never
+        // showed up is dropped once stale rather than replayed forever
+        let now = js_sys::Date::now();
+        let (early, others): (Vec<_>, Vec<_>) = self
+            .early_output
+            .take()
+            .into_iter()
+            .partition(|(_, output)| output.id == terminal_id);
+        *self.early_output.borrow_mut() = others
+            .into_iter()
+            .filter(|(at, _)| now - at < EARLY_OUTPUT_TTL_MS)
+            .collect();
+        for (_, output) in early {
+            self.output(output);
+        }
+        (self.on_change)();
+        Ok(channel)
+    }
+}
+
+/// Host side of a terminal share: local PTYs multiplexed onto a data channel
+/// per viewer, each tagged with its own [`ChannelId`]. Dropping the host
+/// kills every shell it started.
+pub struct TerminalHost {
+    state: Rc<HostState>,
+    _output: EventListener,
+    _exit: EventListener,
+}
+
+impl TerminalHost {
+    /// Starts a first shell, shared with the viewers added later on.
+    /// `on_change` is called whenever a shell is opened or closed, or a
+    /// viewer is added or removed.
+    pub async fn start(
+        cols: u16,
+        rows: u16,
+        on_change: impl Fn() + 'static,
+    ) -> Result<Self, String> {
+        let state = Rc::new(HostState {
+            viewers: RefCell::new(Vec::new()),
+            control: Cell::new(Control::Host),
+            controller: RefCell::new(None),
+            control_timeout: RefCell::new(None),
+            channels: RefCell::new(Vec::new()),
+            next_channel: Cell::new(1),
+            size: Cell::new((cols, rows)),
+            early_output: RefCell::new(Vec::new()),
+            on_change: Box::new(on_change),
+        });
+
+        let output = listen("terminal-output", {
+            let state = state.clone();
+            move |output: TerminalOutput| state.output(output)
+        })
+        .await;
+
+        // A shell that exits on its own closes its tab on the viewers
+        let exit = listen("terminal-exit", {
+            let state = state.clone();
+            move |exit: TerminalExit| {
+                if let Some(channel) = state.channel_for(&exit.id) {
+                    state.remove(channel);
+                    state.send(TerminalFrame::Close { channel });
+                    (state.on_change)();
+                } else {
+                    state
+                        .early_output
+                        .borrow_mut()
+                        .retain(|(_, output)| output.id != exit.id);
+                }
+            }
+        })
+        .await;
+
+        let host = Self {
+            state,
+            _output: output,
+            _exit: exit,
+        };
+        host.state.open_channel().await?;
+        Ok(host)
+    }
+
+    /// Shares the shells with `user_id` through `data_channel`, or carries on
+    /// over it once the viewer is back on a new peer connection. Once the
+    /// channel opens the viewer gets a snapshot of every shell, then live output.
+    pub fn add_viewer(&self, user_id: &str, data_channel: RtcDataChannel, access: ShareAccess) {
+        let viewer = HostViewer {
+            user_id: user_id.to_string(),
+            data_channel: data_channel.clone(),
+            granted: access,
+            size: None,
+        };
+        {
+            let mut viewers = self.state.viewers.borrow_mut();
+            viewers.retain(|viewer| viewer.user_id != user_id);
+            viewers.push(viewer);
+        }
+        attach(&self.state, user_id, &data_channel);
+        (self.state.on_change)();
+    }
+
+    /// Stops sharing with `user_id`, taking back control it may have had.
+    pub fn remove_viewer(&self, user_id: &str) {
+        self.state
+            .viewers
+            .borrow_mut()
+            .retain(|viewer| viewer.user_id != user_id);
+        if self.state.holds_control(user_id) {
+            self.state.set_control(Control::Host, None);
+        } else {
+            // It no longer has a say in the size
+            self.state.apply_viewer_sizes();
+            (self.state.on_change)();
+        }
+    }
+
+    /// Starts another shell on the same data channels, at the size currently
+    /// negotiated with the viewers. The returned future doesn't borrow the
+    /// host, so it can be awaited outside a `RefCell`.
+    pub fn open_channel(&self) -> impl Future<Output = Result<ChannelId, String>> {
+        let state = self.state.clone();
+        async move { state.open_channel().await }
+    }
+
+    /// Kills the shell behind `channel` and closes its tab on the viewers.
+    pub fn close_channel(&self, channel: ChannelId) {
+        let Some(terminal_id) = self.state.remove(channel) else {
+            return;
+        };
+        self.state.send(TerminalFrame::Close { channel });
+        (self.state.on_change)();
+        spawn_local(async move {
+            if let Err(e) = kill_terminal(terminal_id).await {
+                log::error!("Failed to kill terminal: {}", e);
+            }
+        });
+    }
+
+    /// Channels currently open, oldest first.
+    pub fn channels(&self) -> Vec<ChannelId> {
+        self.state
+            .channels
+            .borrow()
+            .iter()
+            .map(|c| c.channel)
+            .collect()
+    }
+
+    /// Backend terminal id of the PTY behind `channel`.
+    pub fn terminal_id(&self, channel: ChannelId) -> Option<String> {
+        self.state.terminal_id(channel)
+    }
+
+    pub fn control(&self) -> Control {
+        self.state.control.get()
+    }
+
+    /// Lets `user_id` type, for `seconds` or until taken back. Whoever had
+    /// control before is left with what it was approved for.
+    pub fn grant_control(&self, user_id: &str, seconds: Option<u32>) {
+        self.state
+            .set_control(Control::viewer(seconds), Some(user_id.to_string()));
+    }
+
+    /// Takes control back from the viewer, leaving it with what it was approved for.
+    pub fn take_control_back(&self) {
+        self.state.set_control(Control::Host, None);
+    }
+}
+
+impl Drop for TerminalHost {
+    fn drop(&mut self) {
+        for host_channel in self.state.channels.borrow_mut().drain(..) {
+            spawn_local(async move {
+                if let Err(e) = kill_terminal(host_channel.terminal_id).await {
+                    log::error!("Failed to kill terminal: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// Sends `user_id` the current state once `data_channel` opens, then takes
+/// its keystrokes and size reports.
+fn attach(state: &Rc<HostState>, user_id: &str, data_channel: &RtcDataChannel) {
+    // The viewer may connect long after the shells started printing
+    {
+        let state = state.clone();
+        let user_id = user_id.to_string();
+        let on_open = Closure::wrap(Box::new(move || {
+            state.send_initial_state(&user_id);
+        }) as Box<dyn FnMut()>);
+
+        data_channel.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        on_open.forget();
+    }
+
+    // Keystrokes and size reports coming back from the viewer
+    {
+        let state = state.clone();
+        let user_id = user_id.to_string();
+        on_frame(data_channel, move |frame| match frame {
+            TerminalFrame::Input { .. } if !state.access(&user_id).can_type() => {
+                log::warn!("Dropping input from read-only viewer");
+            }
+            TerminalFrame::Input { channel, data } => {
+                let Some(terminal_id) = state.terminal_id(channel) else {
+                    log::warn!("Dropping input for unknown terminal channel {}", channel);
+                    return;
+                };
+                spawn_local(async move {
+                    if let Err(e) = write_terminal(terminal_id, data).await {
+                        log::error!("Failed to write to terminal: {}", e);
+                    }
+                });
+            }
+            TerminalFrame::Resize { cols, rows } => {
+                let size = Some((cols.max(1), rows.max(1)));
+                // A removed viewer's channel is closing, its reports don't count
+                if let Some(viewer) = state
+                    .viewers
+                    .borrow_mut()
+                    .iter_mut()
+                    .find(|viewer| viewer.user_id == user_id)
+                {
+                    viewer.size = size;
+                }
+                state.apply_viewer_sizes();
+            }
+            other => log::warn!("Unexpected terminal frame from viewer: {:?}", other),
+        });
+    }
+}
+
+/// Picks the PTY size for a set of `(access, cols, rows)` viewer reports: the
+/// smallest size every interactive viewer can show. Read-only viewers don't
+/// get a say, they letterbox whatever size is picked. Returns `None` when no
+/// interactive viewer has reported yet.
+fn negotiate_size(
+    viewers: impl IntoIterator<Item = (ShareAccess, u16, u16)>,
+) -> Option<(u16, u16)> {
+    viewers
+        .into_iter()
+        .filter(|(access, _, _)| access.can_type())
+        .map(|(_, cols, rows)| (cols, rows))
+        .reduce(|(cols, rows), (c, r)| (cols.min(c), rows.min(r)))
+}
+
+/// Sends a frame if `viewer` is connected.
+fn send_to(viewer: &HostViewer, frame: &TerminalFrame) {
+    if viewer.data_channel.ready_state() != RtcDataChannelState::Open {
+        return;
+    }
+    if let Err(e) = send_frame(&viewer.data_channel, frame) {
+        log::error!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_the_smallest_interactive_size() {
+        let viewers = [
+            (ShareAccess::Interactive, 120, 30),
+            (ShareAccess::Interactive, 100, 40),
+            (ShareAccess::Interactive, 140, 50),
+        ];
+        assert_eq!(negotiate_size(viewers), Some((100, 30)));
+    }
+
+    #[test]
+    fn read_only_viewers_dont_shrink_the_size() {
+        let viewers = [
+            (ShareAccess::ReadOnly, 40, 10),
+            (ShareAccess::Interactive, 120, 30),
+        ];
+        assert_eq!(negotiate_size(viewers), Some((120, 30)));
+    }
+
+    #[test]
+    fn keeps_the_size_until_an_interactive_viewer_reports() {
+        assert_eq!(negotiate_size([]), None);
+        assert_eq!(negotiate_size([(ShareAccess::ReadOnly, 80, 24)]), None);
+    }
+}
@@ -0,0 +1,69 @@
+/// Translates a DOM `KeyboardEvent.key` into the bytes a terminal expects.
+///
+/// Returns `None` for keys that produce no input (modifiers, function keys
+/// we don't map, ...). `application_cursor` selects the `ESC O` form of the
+/// cursor keys, as requested by full-screen programs through DECCKM.
+pub fn key_to_input(key: &str, ctrl: bool, alt: bool, application_cursor: bool) -> Option<String> {
+    let cursor_prefix = if application_cursor { "\x1bO" } else { "\x1b[" };
+    let sequence = match key {
+        "Enter" => "\r".to_string(),
+        "Backspace" => "\x7f".to_string(),
+        "Tab" => "\t".to_string(),
+        "Escape" => "\x1b".to_string(),
+        "ArrowUp" => format!("{}A", cursor_prefix),
+        "ArrowDown" => format!("{}B", cursor_prefix),
+        "ArrowRight" => format!("{}C", cursor_prefix),
+        "ArrowLeft" => format!("{}D", cursor_prefix),
+        "Home" => format!("{}H", cursor_prefix),
+        "End" => format!("{}F", cursor_prefix),
+        "Insert" => "\x1b[2~".to_string(),
+        "Delete" => "\x1b[3~".to_string(),
+        "PageUp" => "\x1b[5~".to_string(),
+        "PageDown" => "\x1b[6~".to_string(),
+        _ => {
+            let mut chars = key.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            if ctrl {
+                control_char(c)?.to_string()
+            } else {
+                c.to_string()
+            }
+        }
+    };
+
+    if alt {
+        Some(format!("\x1b{}", sequence))
+    } else {
+        Some(sequence)
+    }
+}
+
+fn control_char(c: char) -> Option<char> {
+    match c {
+        'a'..='z' => Some((c as u8 - b'a' + 1) as char),
+        'A'..='Z' => Some((c as u8 - b'A' + 1) as char),
+        '@' | ' ' => Some('\0'),
+        '[' => Some('\x1b'),
+        '\\' => Some('\x1c'),
+        ']' => Some('\x1d'),
+        '^' => Some('\x1e'),
+        '_' => Some('\x1f'),
+        _ => None,
+    }
+}
+
+/// Prepares pasted text for the terminal, wrapping it in bracketed paste
+/// markers when the running program asked for them.
+pub fn paste_to_input(text: &str, bracketed: bool) -> String {
+    let text = text.replace("\r\n", "\r").replace('\n', "\r");
+    if bracketed {
+        // Pasted text must not be able to end the bracket early
+        let text = text.replace("\x1b[201~", "");
+        format!("\x1b[200~{}\x1b[201~", text)
+    } else {
+        text
+    }
+}
@@ -0,0 +1,8 @@
+pub mod channel;
+pub mod control;
+pub mod emulator;
+pub mod host;
+pub mod input;
+pub mod protocol;
+pub mod tabs;
+pub mod viewer;
@@ -0,0 +1,7 @@
+use super::emulator::snapshot::Snapshot;
+
+pub use share_protocol::{ChannelId, ControlState, TERMINAL_CHANNEL_LABEL};
+
+/// A message exchanged over the terminal data channel, with snapshots of our
+/// terminal emulator.
+pub type TerminalFrame = share_protocol::TerminalFrame<Box<Snapshot>>;
@@ -0,0 +1,133 @@
+use super::emulator::renderer::Renderer;
+use super::emulator::snapshot::Snapshot;
+use super::protocol::ChannelId;
+use super::viewer::TerminalView;
+use std::collections::BTreeMap;
+use web_sys::HtmlCanvasElement;
+use yew::NodeRef;
+
+/// Viewer side of a multiplexed share: one [`TerminalView`] per host PTY,
+/// all drawn onto the same canvas. Only the active tab is rendered.
+pub struct TerminalTabs {
+    canvas: NodeRef,
+    views: BTreeMap<ChannelId, TerminalView>,
+    active: Option<ChannelId>,
+    // Every host PTY runs at the same size
+    size: (u16, u16),
+}
+
+impl TerminalTabs {
+    pub fn new(canvas: NodeRef) -> Self {
+        Self {
+            canvas,
+            views: BTreeMap::new(),
+            active: None,
+            size: (80, 24),
+        }
+    }
+
+    /// Adds a tab for `channel`, selecting it if it's the first one.
+    pub fn open(&mut self, channel: ChannelId) {
+        self.views
+            .entry(channel)
+            .or_insert_with(|| TerminalView::new(self.canvas.clone(), self.size.0, self.size.1));
+        if self.active.is_none() {
+            self.select(channel);
+        }
+    }
+
+    /// Removes the tab for `channel`, falling back to the first remaining tab.
+    pub fn close(&mut self, channel: ChannelId) {
+        self.views.remove(&channel);
+        if self.active == Some(channel) {
+            self.active = None;
+            if let Some(&first) = self.views.keys().next() {
+                self.select(first);
+            }
+        }
+    }
+
+    pub fn select(&mut self, channel: ChannelId) {
+        if let Some(view) = self.views.get_mut(&channel) {
+            self.active = Some(channel);
+            view.render();
+        }
+    }
+
+    /// Feeds output to the tab for `channel` and redraws it if it's active.
+    /// Returns true when the tab's title changed.
+    pub fn feed(&mut self, channel: ChannelId, data: &str) -> bool {
+        if !self.views.contains_key(&channel) {
+            self.open(channel);
+        }
+        let Some(view) = self.views.get_mut(&channel) else {
+            return false;
+        };
+        let title = view.title().to_string();
+        view.feed(data);
+        if self.active == Some(channel) {
+            view.render();
+        }
+        view.title() != title
+    }
+
+    /// Catches the tab for `channel` up with the host's current screen.
+    pub fn restore(&mut self, channel: ChannelId, snapshot: Snapshot) -> Result<(), String> {
+        self.open(channel);
+        let Some(view) = self.views.get_mut(&channel) else {
+            return Ok(());
+        };
+        view.restore(snapshot)?;
+        if self.active == Some(channel) {
+            view.render();
+        }
+        Ok(())
+    }
+
+    /// Applies the size the host settled on to every tab.
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.size = (cols, rows);
+        for view in self.views.values_mut() {
+            view.resize(cols, rows);
+        }
+        if let Some(view) = self.active.and_then(|channel| self.views.get_mut(&channel)) {
+            view.render();
+        }
+    }
+
+    /// How many cols/rows fit into `width` x `height` CSS pixels.
+    pub fn fit(&self, width: f64, height: f64) -> Option<(u16, u16)> {
+        let canvas = self.canvas.cast::<HtmlCanvasElement>()?;
+        let (cell_width, cell_height) = Renderer::new(&canvas).ok()?.cell_size();
+        let cols = (width / cell_width).floor().clamp(1.0, u16::MAX as f64) as u16;
+        let rows = (height / cell_height).floor().clamp(1.0, u16::MAX as f64) as u16;
+        Some((cols, rows))
+    }
+
+    pub fn active(&self) -> Option<ChannelId> {
+        self.active
+    }
+
+    pub fn active_view(&self) -> Option<&TerminalView> {
+        self.active.and_then(|channel| self.views.get(&channel))
+    }
+
+    /// Channel and label of every tab, in the order they were opened.
+    pub fn labels(&self) -> Vec<(ChannelId, String)> {
+        self.views
+            .iter()
+            .map(|(&channel, view)| {
+                let label = if view.title().is_empty() {
+                    format!("Shell {}", channel)
+                } else {
+                    view.title().to_string()
+                };
+                (channel, label)
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+}
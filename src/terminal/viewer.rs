@@ -0,0 +1,78 @@
+use super::emulator::renderer::Renderer;
+use super::emulator::snapshot::Snapshot;
+use super::emulator::Terminal;
+use super::input::{key_to_input, paste_to_input};
+use web_sys::{HtmlCanvasElement, KeyboardEvent};
+use yew::NodeRef;
+
+/// Viewer side of a terminal share: emulates the host's terminal and draws
+/// it onto a canvas.
+pub struct TerminalView {
+    terminal: Terminal,
+    renderer: Option<Renderer>,
+    canvas: NodeRef,
+}
+
+impl TerminalView {
+    pub fn new(canvas: NodeRef, cols: u16, rows: u16) -> Self {
+        Self {
+            terminal: Terminal::new(cols, rows),
+            renderer: None,
+            canvas,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        self.terminal.title()
+    }
+
+    /// Feeds host output to the emulator. The canvas is only redrawn by
+    /// [`TerminalView::render`].
+    pub fn feed(&mut self, data: &str) {
+        self.terminal.feed(data);
+    }
+
+    /// Replaces the emulated state with a snapshot taken by the host.
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), String> {
+        self.terminal = Terminal::restore(snapshot)?;
+        Ok(())
+    }
+
+    /// Resizes the emulated screen to the size the host's PTY runs at.
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.terminal.resize(cols, rows);
+    }
+
+    pub fn render(&mut self) {
+        let Some(canvas) = self.canvas.cast::<HtmlCanvasElement>() else {
+            return;
+        };
+        if self.renderer.is_none() {
+            match Renderer::new(&canvas) {
+                Ok(renderer) => self.renderer = Some(renderer),
+                Err(e) => {
+                    log::error!("Failed to create terminal renderer: {}", e);
+                    return;
+                }
+            }
+        }
+        if let Some(renderer) = &self.renderer {
+            renderer.render(&self.terminal, &canvas);
+        }
+    }
+
+    /// Bytes to send to the host for a key press, if any.
+    pub fn key_input(&self, event: &KeyboardEvent) -> Option<String> {
+        key_to_input(
+            &event.key(),
+            event.ctrl_key(),
+            event.alt_key(),
+            self.terminal.application_cursor(),
+        )
+    }
+
+    /// Bytes to send to the host for pasted text.
+    pub fn paste_input(&self, text: &str) -> String {
+        paste_to_input(text, self.terminal.bracketed_paste())
+    }
+}
@@ -1,5 +1,5 @@
 use web_sys::window;
-use crate::constants::STORAGE_KEY_AUTH_TOKEN;
+use crate::constants::{STORAGE_KEY_AUTH_TOKEN, STORAGE_KEY_USER_EMAIL};
 
 /// Get the authentication token from localStorage
 pub fn get_auth_token() -> Option<String> {
@@ -22,11 +22,28 @@ pub fn store_auth_token(token: &str) {
     }
 }
 
-/// Remove the authentication token from localStorage
+/// Remove the authentication token, and who it was issued to, from localStorage
 pub fn clear_auth_token() {
     if let Some(window) = window() {
         if let Ok(Some(storage)) = window.local_storage() {
             let _ = storage.remove_item(STORAGE_KEY_AUTH_TOKEN);
+            let _ = storage.remove_item(STORAGE_KEY_USER_EMAIL);
+        }
+    }
+}
+
+/// Get the email of the logged in user from localStorage
+pub fn get_user_email() -> Option<String> {
+    window()
+        .and_then(|w| w.local_storage().ok().flatten())
+        .and_then(|storage| storage.get_item(STORAGE_KEY_USER_EMAIL).ok().flatten())
+}
+
+/// Store the email the user logged in with in localStorage
+pub fn store_user_email(email: &str) {
+    if let Some(window) = window() {
+        if let Ok(Some(storage)) = window.local_storage() {
+            let _ = storage.set_item(STORAGE_KEY_USER_EMAIL, email);
         }
     }
 }
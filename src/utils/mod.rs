@@ -1 +1,3 @@
 pub mod auth;
+pub mod share_access;
+pub mod share_session;
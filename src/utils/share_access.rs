@@ -0,0 +1,30 @@
+use crate::constants::STORAGE_KEY_SHARE_ACCESS;
+pub use share_protocol::ShareAccess;
+use std::collections::HashMap;
+use web_sys::window;
+
+fn load_all() -> HashMap<String, ShareAccess> {
+    window()
+        .and_then(|w| w.local_storage().ok().flatten())
+        .and_then(|storage| storage.get_item(STORAGE_KEY_SHARE_ACCESS).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Get the access level granted to a viewer, read-only unless set otherwise
+pub fn get_share_access(viewer_id: &str) -> ShareAccess {
+    load_all().get(viewer_id).copied().unwrap_or_default()
+}
+
+/// Store the access level granted to a viewer in localStorage
+pub fn set_share_access(viewer_id: &str, access: ShareAccess) {
+    let mut all = load_all();
+    all.insert(viewer_id.to_string(), access);
+    if let Some(window) = window() {
+        if let Ok(Some(storage)) = window.local_storage() {
+            if let Ok(json) = serde_json::to_string(&all) {
+                let _ = storage.set_item(STORAGE_KEY_SHARE_ACCESS, &json);
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::constants::STORAGE_KEY_SHARE_SESSIONS;
+use std::collections::HashMap;
+use web_sys::window;
+
+/// A fresh id for a screen share session, unique enough to tell one page
+/// mount from the next.
+pub fn new_session_id() -> String {
+    format!(
+        "{:x}-{:08x}",
+        js_sys::Date::now() as u64,
+        (js_sys::Math::random() * u32::MAX as f64) as u32
+    )
+}
+
+fn load_all() -> HashMap<String, String> {
+    window()
+        .and_then(|w| w.local_storage().ok().flatten())
+        .and_then(|storage| storage.get_item(STORAGE_KEY_SHARE_SESSIONS).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_all(all: &HashMap<String, String>) {
+    if let Some(window) = window() {
+        if let Ok(Some(storage)) = window.local_storage() {
+            if let Ok(json) = serde_json::to_string(all) {
+                let _ = storage.set_item(STORAGE_KEY_SHARE_SESSIONS, &json);
+            }
+        }
+    }
+}
+
+/// Get the session of the viewer's request we last approved, if any
+pub fn get_approved_session(viewer_id: &str) -> Option<String> {
+    load_all().get(viewer_id).cloned()
+}
+
+/// Store the session of an approved request, replacing the viewer's previous one
+pub fn set_approved_session(viewer_id: &str, session_id: &str) {
+    let mut all = load_all();
+    all.insert(viewer_id.to_string(), session_id.to_string());
+    save_all(&all);
+}
+
+/// Forget the approved session once either side hung up
+pub fn clear_approved_session(viewer_id: &str, session_id: &str) {
+    let mut all = load_all();
+    if all.get(viewer_id).map(String::as_str) == Some(session_id) {
+        all.remove(viewer_id);
+        save_all(&all);
+    }
+}